@@ -0,0 +1,140 @@
+use {
+    anchor_lang::prelude::*,
+    anchor_spl::{
+        associated_token::AssociatedToken,
+        token::Token,
+    },
+    nft_marketplace::{
+        cpi::accounts::BuyNft,
+        program::NftMarketplace,
+    },
+};
+
+use crate::state::*;
+
+// Buys a listed NFT into the treasury. The approved price is passed on as the
+// marketplace's `max_price`, so the DAO never pays more than it approved, even
+// if the NFT got relisted or a USD listing got more expensive.
+pub fn buy_for_treasury(
+    ctx: Context<BuyForTreasury>,
+    max_price: u64,
+) -> Result<()> {
+    msg!("Buying NFT for the DAO treasury...");
+    let dao_key = ctx.accounts.dao.key();
+    let treasury_seeds : &[&[&[u8]]] = &[&[
+        DAO_TREASURY_SEED.as_bytes(),
+        dao_key.as_ref(),
+        &[ctx.accounts.dao.treasury_bump],
+    ]];
+
+    nft_marketplace::cpi::buy_nft(
+        CpiContext::new_with_signer(
+            ctx.accounts.marketplace_program.to_account_info(),
+            BuyNft {
+                buyer: ctx.accounts.dao_treasury.to_account_info(),
+                seller: ctx.accounts.seller.to_account_info(),
+                mint: ctx.accounts.mint.to_account_info(),
+                marketplace: ctx.accounts.marketplace.to_account_info(),
+                treasury: ctx.accounts.marketplace_treasury.to_account_info(),
+                referrer: None,
+                nft_listing_account: ctx.accounts.nft_listing_account.to_account_info(),
+                marketplace_stats: ctx.accounts.marketplace_stats.to_account_info(),
+                collection_stats: ctx.accounts.collection_stats.as_ref().map(|stats| stats.to_account_info()),
+                metadata: ctx.accounts.metadata.to_account_info(),
+                buyer_attestation: None,
+                price_feed: None,
+                pda_token_account: ctx.accounts.pda_token_account.to_account_info(),
+                buyer_token_account: ctx.accounts.treasury_token_account.to_account_info(),
+                sale_receipt: ctx.accounts.sale_receipt.to_account_info(),
+                program_pda: ctx.accounts.program_pda.to_account_info(),
+                token_program: ctx.accounts.token_program.to_account_info(),
+                associated_token_program: ctx.accounts.associated_token_program.to_account_info(),
+                system_program: ctx.accounts.system_program.to_account_info(),
+                rent: ctx.accounts.rent.to_account_info(),
+            },
+            treasury_seeds,
+        ),
+        Some(max_price),
+    )?;
+
+    msg!("NFT bought for at most {} lamports", max_price);
+
+    Ok(())
+}
+
+// The marketplace accounts are only forwarded, the marketplace validates them
+#[derive(Accounts)]
+pub struct BuyForTreasury<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        seeds = [
+            DAO_SEED.as_bytes(),
+            dao.authority.as_ref(),
+        ],
+        bump = dao.bump,
+        has_one = authority,
+    )]
+    pub dao: Account<'info, Dao>,
+
+    #[account(
+        mut,
+        seeds = [
+            DAO_TREASURY_SEED.as_bytes(),
+            dao.key().as_ref(),
+        ],
+        bump = dao.treasury_bump,
+    )]
+    pub dao_treasury: SystemAccount<'info>,
+
+    /// CHECK: Must be the owner of the listing, checked by the marketplace
+    #[account(mut)]
+    pub seller: UncheckedAccount<'info>,
+
+    /// CHECK: Checked by the marketplace
+    #[account(mut)]
+    pub mint: UncheckedAccount<'info>,
+
+    /// CHECK: Checked by the marketplace
+    pub marketplace: UncheckedAccount<'info>,
+
+    /// CHECK: Receives the platform fee, checked by the marketplace
+    #[account(mut)]
+    pub marketplace_treasury: UncheckedAccount<'info>,
+
+    /// CHECK: Checked and closed by the marketplace
+    #[account(mut)]
+    pub nft_listing_account: UncheckedAccount<'info>,
+
+    /// CHECK: Checked by the marketplace
+    #[account(mut)]
+    pub marketplace_stats: UncheckedAccount<'info>,
+
+    /// CHECK: Required for NFTs of a tracked collection, checked by the marketplace
+    #[account(mut)]
+    pub collection_stats: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Checked by the marketplace
+    pub metadata: UncheckedAccount<'info>,
+
+    /// CHECK: Checked by the marketplace
+    #[account(mut)]
+    pub pda_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: Treasury's associated token account, created by the marketplace if needed
+    #[account(mut)]
+    pub treasury_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: Checked by the marketplace
+    #[account(mut)]
+    pub sale_receipt: UncheckedAccount<'info>,
+
+    /// CHECK: Checked by the marketplace
+    pub program_pda: UncheckedAccount<'info>,
+
+    pub marketplace_program: Program<'info, NftMarketplace>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
@@ -0,0 +1,47 @@
+use anchor_lang::prelude::*;
+
+use crate::state::*;
+
+pub fn create_dao(
+    ctx: Context<CreateDao>,
+) -> Result<()> {
+    msg!("Creating DAO...");
+    let dao = &mut ctx.accounts.dao;
+    dao.authority = ctx.accounts.authority.key();
+    dao.treasury_bump = ctx.bumps.dao_treasury;
+    dao.bump = ctx.bumps.dao;
+
+    msg!("DAO treasury at {}", ctx.accounts.dao_treasury.key());
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct CreateDao<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + Dao::MAX_SIZE,
+        seeds = [
+            DAO_SEED.as_bytes(),
+            authority.key().as_ref(),
+        ],
+        bump,
+    )]
+    pub dao: Account<'info, Dao>,
+
+    // Plain system account so it can pay through the system program, funded by transfers
+    #[account(
+        seeds = [
+            DAO_TREASURY_SEED.as_bytes(),
+            dao.key().as_ref(),
+        ],
+        bump,
+    )]
+    pub dao_treasury: SystemAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
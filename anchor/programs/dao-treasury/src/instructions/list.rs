@@ -0,0 +1,111 @@
+use {
+    anchor_lang::prelude::*,
+    anchor_spl::{
+        associated_token::AssociatedToken,
+        token::Token,
+    },
+    nft_marketplace::{
+        cpi::accounts::ListNft,
+        program::NftMarketplace,
+    },
+};
+
+use crate::state::*;
+
+// Lists an NFT held by the treasury, sale proceeds go back to the treasury
+pub fn list_from_treasury(
+    ctx: Context<ListFromTreasury>,
+    price: u64,
+    requires_attestation: bool,
+) -> Result<()> {
+    msg!("Listing NFT from the DAO treasury...");
+    let dao_key = ctx.accounts.dao.key();
+    let treasury_seeds : &[&[&[u8]]] = &[&[
+        DAO_TREASURY_SEED.as_bytes(),
+        dao_key.as_ref(),
+        &[ctx.accounts.dao.treasury_bump],
+    ]];
+
+    nft_marketplace::cpi::list_nft(
+        CpiContext::new_with_signer(
+            ctx.accounts.marketplace_program.to_account_info(),
+            ListNft {
+                owner: ctx.accounts.dao_treasury.to_account_info(),
+                mint: ctx.accounts.mint.to_account_info(),
+                owner_token_account: ctx.accounts.treasury_token_account.to_account_info(),
+                nft_listing_account: ctx.accounts.nft_listing_account.to_account_info(),
+                marketplace_stats: ctx.accounts.marketplace_stats.to_account_info(),
+                marketplace: None,
+                price_feed: None,
+                pda_token_account: ctx.accounts.pda_token_account.to_account_info(),
+                program_pda: ctx.accounts.program_pda.to_account_info(),
+                token_program: ctx.accounts.token_program.to_account_info(),
+                associated_token_program: ctx.accounts.associated_token_program.to_account_info(),
+                rent: ctx.accounts.rent.to_account_info(),
+                system_program: ctx.accounts.system_program.to_account_info(),
+            },
+            treasury_seeds,
+        ),
+        price,
+        requires_attestation,
+    )?;
+
+    msg!("Treasury NFT listed for {} lamports", price);
+
+    Ok(())
+}
+
+// The marketplace accounts are only forwarded, the marketplace validates them
+#[derive(Accounts)]
+pub struct ListFromTreasury<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        seeds = [
+            DAO_SEED.as_bytes(),
+            dao.authority.as_ref(),
+        ],
+        bump = dao.bump,
+        has_one = authority,
+    )]
+    pub dao: Account<'info, Dao>,
+
+    #[account(
+        mut,
+        seeds = [
+            DAO_TREASURY_SEED.as_bytes(),
+            dao.key().as_ref(),
+        ],
+        bump = dao.treasury_bump,
+    )]
+    pub dao_treasury: SystemAccount<'info>,
+
+    /// CHECK: Checked by the marketplace
+    #[account(mut)]
+    pub mint: UncheckedAccount<'info>,
+
+    /// CHECK: Treasury's token account holding the NFT, checked by the marketplace
+    #[account(mut)]
+    pub treasury_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: Created by the marketplace
+    #[account(mut)]
+    pub nft_listing_account: UncheckedAccount<'info>,
+
+    /// CHECK: Checked by the marketplace
+    #[account(mut)]
+    pub marketplace_stats: UncheckedAccount<'info>,
+
+    /// CHECK: Checked by the marketplace
+    #[account(mut)]
+    pub pda_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: Checked by the marketplace
+    pub program_pda: UncheckedAccount<'info>,
+
+    pub marketplace_program: Program<'info, NftMarketplace>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub rent: Sysvar<'info, Rent>,
+    pub system_program: Program<'info, System>,
+}
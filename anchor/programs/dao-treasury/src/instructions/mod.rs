@@ -0,0 +1,8 @@
+pub use dao::*;
+pub mod dao;
+
+pub use buy::*;
+pub mod buy;
+
+pub use list::*;
+pub mod list;
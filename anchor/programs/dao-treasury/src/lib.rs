@@ -0,0 +1,52 @@
+// Sample program composing with the marketplace: a DAO keeps SOL and NFTs
+// in a treasury PDA and lists or buys through CPI, signing as that PDA.
+//
+// Invariants a CPI caller has to uphold, see also the README:
+// - The PDA acting as owner or buyer must be a plain system account without
+//   data. The marketplace pays rent and the price from it through the system
+//   program, and it has to stay above the rent-exempt minimum afterwards.
+// - Signer privileges are forwarded with `invoke_signed`, the marketplace
+//   never signs for the caller and never calls back into it.
+// - The marketplace charges whatever the listing asks unless `max_price` is
+//   set, a caller that needs a price limit passes it as `max_price`.
+
+// Anchor 0.30 macros expand to cfgs this crate does not declare as features
+#![allow(unexpected_cfgs)]
+
+use anchor_lang::prelude::*;
+
+use crate::instructions::*;
+
+pub mod instructions;
+pub mod state;
+
+declare_id!("oVb4LMEG9HZzRCpeLaymB4aoQv1uMNaryG4U7mbvMke");
+
+#[program]
+pub mod dao_treasury {
+    use super::*;
+
+    // DAO with its treasury PDA, the authority executes approved proposals
+    pub fn create_dao(
+        ctx: Context<CreateDao>,
+    ) -> Result<()> {
+        dao::create_dao(ctx)
+    }
+
+    // Buy a listed NFT with treasury SOL, up to the approved price
+    pub fn buy_for_treasury(
+        ctx: Context<BuyForTreasury>,
+        max_price: u64,
+    ) -> Result<()> {
+        buy::buy_for_treasury(ctx, max_price)
+    }
+
+    // List an NFT owned by the treasury
+    pub fn list_from_treasury(
+        ctx: Context<ListFromTreasury>,
+        price: u64,
+        requires_attestation: bool,
+    ) -> Result<()> {
+        list::list_from_treasury(ctx, price, requires_attestation)
+    }
+}
@@ -0,0 +1,16 @@
+use anchor_lang::prelude::*;
+
+pub const DAO_SEED: &str = "DAO_EMSKIQ_SEED";
+pub const DAO_TREASURY_SEED: &str = "DAO_TREASURY_EMSKIQ_SEED";
+
+#[account]
+pub struct Dao {
+    // Executes what the DAO voted for, e.g. a multisig or governance PDA
+    pub authority: Pubkey,
+    pub treasury_bump: u8,
+    pub bump: u8,
+}
+
+impl Dao {
+    pub const MAX_SIZE: usize = 32 + 1 + 1;
+}
@@ -4,4 +4,184 @@ use anchor_lang::prelude::*;
 pub enum NftMarketplaceError {
     #[msg("Insufficient funds to purchase the NFT.")]
     InsufficientFunds,
+    #[msg("Signer is not the update authority of this NFT's metadata.")]
+    InvalidUpdateAuthority,
+    #[msg("Basis points must not exceed 10000.")]
+    InvalidBasisPoints,
+    #[msg("The NFT is currently listed on the marketplace.")]
+    NftCurrentlyListed,
+    #[msg("Drop supply cap must be greater than zero.")]
+    InvalidDropSupply,
+    #[msg("Drop name is too long for the numbered NFT names.")]
+    DropNameTooLong,
+    #[msg("Drop URI is too long.")]
+    DropUriTooLong,
+    #[msg("The drop has not started yet.")]
+    DropNotStarted,
+    #[msg("The drop is sold out.")]
+    DropSoldOut,
+    #[msg("Wallet is not on the drop allowlist.")]
+    NotOnAllowlist,
+    #[msg("Wallet has reached the mint limit for this drop.")]
+    WalletMintLimitReached,
+    #[msg("Signer is not allowed to perform this action.")]
+    Unauthorized,
+    #[msg("Buyer cannot refer their own purchase.")]
+    SelfReferral,
+    #[msg("NFT is not a verified member of the required collection.")]
+    NotInCollection,
+    #[msg("Arithmetic overflow.")]
+    ArithmeticOverflow,
+    #[msg("Rental duration must be between one day and the maximum set by the owner.")]
+    InvalidRentalDuration,
+    #[msg("The NFT is already rented out.")]
+    AlreadyRented,
+    #[msg("The rental period has not expired yet.")]
+    RentalNotExpired,
+    #[msg("The renter's token account is required to end an active rental.")]
+    MissingRenterTokenAccount,
+    #[msg("Fraction supply must be greater than zero.")]
+    InvalidFractionSupply,
+    #[msg("Fraction amount must be greater than zero.")]
+    InvalidFractionAmount,
+    #[msg("The fraction vault is no longer active.")]
+    VaultNotActive,
+    #[msg("The fraction vault has not been bought out yet.")]
+    VaultNotBoughtOut,
+    #[msg("Loan principal and duration must be greater than zero.")]
+    InvalidLoanTerms,
+    #[msg("The loan is not in the right state for this action.")]
+    InvalidLoanState,
+    #[msg("The loan deadline has passed.")]
+    LoanExpired,
+    #[msg("The loan deadline has not passed yet.")]
+    LoanNotExpired,
+    #[msg("Installment deposit is below the minimum or not below the price.")]
+    InvalidInstallmentDeposit,
+    #[msg("Installment duration is out of the allowed range.")]
+    InvalidInstallmentDuration,
+    #[msg("The installment deadline has passed.")]
+    InstallmentDeadlinePassed,
+    #[msg("The installment deadline has not passed yet.")]
+    InstallmentDeadlineNotPassed,
+    #[msg("Dispute window must not be negative.")]
+    InvalidDisputeWindow,
+    #[msg("The dispute window is over.")]
+    DisputeWindowClosed,
+    #[msg("The settlement is disputed and must be resolved by the arbiter.")]
+    SettlementDisputed,
+    #[msg("The settlement is not disputed.")]
+    SettlementNotDisputed,
+    #[msg("The seller's token account is required to refund the buyer.")]
+    MissingSellerTokenAccount,
+    #[msg("A bundle must contain between 2 and 10 NFTs.")]
+    InvalidBundleSize,
+    #[msg("Bundle accounts must be passed as (mint, source, destination) triples.")]
+    InvalidBundleAccounts,
+    #[msg("Bundle contains the same NFT more than once.")]
+    DuplicateBundleMint,
+    #[msg("Bid price must be greater than zero.")]
+    InvalidBidPrice,
+    #[msg("The NFT does not have the traits required by the bid.")]
+    TraitMismatch,
+    #[msg("Rating must be between 1 and 5.")]
+    InvalidRating,
+    #[msg("This listing can only be bought with a valid buyer attestation.")]
+    AttestationRequired,
+    #[msg("The buyer attestation has expired.")]
+    AttestationExpired,
+    #[msg("Attestation expiry must be in the future.")]
+    InvalidAttestationExpiry,
+    #[msg("The NFT is non-transferable (frozen) and cannot be listed.")]
+    NftNonTransferable,
+    #[msg("At most 4 additional creators are supported.")]
+    TooManyCreators,
+    #[msg("Creator shares must not add up to more than 100.")]
+    InvalidCreatorShares,
+    #[msg("A creator is listed more than once.")]
+    DuplicateCreator,
+    #[msg("Raffle needs a ticket price, at least one ticket and an end time in the future.")]
+    InvalidRaffleParams,
+    #[msg("The raffle has ended.")]
+    RaffleEnded,
+    #[msg("The raffle has not ended yet.")]
+    RaffleNotEnded,
+    #[msg("Not enough raffle tickets left.")]
+    RaffleSoldOut,
+    #[msg("The raffle winner has already been drawn.")]
+    RaffleAlreadyDrawn,
+    #[msg("The raffle winner has not been drawn yet.")]
+    RaffleNotDrawn,
+    #[msg("No raffle tickets were sold.")]
+    NoTicketsSold,
+    #[msg("Raffle tickets were already sold.")]
+    RaffleHasTickets,
+    #[msg("These tickets do not contain the winning ticket.")]
+    NotWinningTicket,
+    #[msg("These tickets contain the winning ticket, which has not been claimed.")]
+    PrizeNotClaimed,
+    #[msg("Pool deadline must be in the future.")]
+    InvalidPoolDeadline,
+    #[msg("The pool no longer accepts contributions.")]
+    PoolClosed,
+    #[msg("Contribution exceeds the amount still needed by the pool.")]
+    PoolTargetExceeded,
+    #[msg("The pool has not raised the full price yet.")]
+    PoolNotFunded,
+    #[msg("The pool purchase has already been executed.")]
+    PoolAlreadyExecuted,
+    #[msg("The pool purchase has not been executed.")]
+    PoolNotExecuted,
+    #[msg("Contributions can only be withdrawn after the pool deadline.")]
+    PoolDeadlineNotPassed,
+    #[msg("The listing price differs from the pool target.")]
+    ListingPriceChanged,
+    #[msg("The account is not a listing of this marketplace.")]
+    InvalidListingAccount,
+    #[msg("The listing already uses the current layout.")]
+    ListingAlreadyMigrated,
+    #[msg("The price feed is not a trading SOL/USD price account.")]
+    InvalidPriceFeed,
+    #[msg("The price feed has not been updated recently enough.")]
+    StalePrice,
+    #[msg("Max price age must be positive.")]
+    InvalidMaxPriceAge,
+    #[msg("The confidence interval of the price feed is too wide.")]
+    PriceConfidenceTooWide,
+    #[msg("The USD price cannot be converted to lamports.")]
+    InvalidUsdPrice,
+    #[msg("The price exceeds the buyer's maximum.")]
+    PriceAboveMax,
+    #[msg("Listings pegged to USD can only be bought directly.")]
+    UsdListingNotSupported,
+    #[msg("The raffle draw is already committed to a slot that can still be drawn.")]
+    RaffleDrawCommitted,
+    #[msg("The raffle draw is not committed or its slot has not been produced yet.")]
+    RaffleDrawNotReady,
+    #[msg("The draw slot has left SlotHashes, commit the draw again.")]
+    RaffleDrawExpired,
+    #[msg("The pool reserve price must cover the pool target.")]
+    PoolReserveBelowTarget,
+    #[msg("The pool still holds contributions.")]
+    PoolHasContributions,
+    #[msg("Buying a USD listing requires a max price.")]
+    MaxPriceRequired,
+    #[msg("The mint is not an NFT.")]
+    NotAnNft,
+    #[msg("A loan offer must be restricted to a collection or a specific NFT.")]
+    LoanCollateralUnrestricted,
+    #[msg("The loan offer is for another NFT.")]
+    WrongLoanCollateral,
+    #[msg("Reserve price must be greater than zero.")]
+    InvalidReservePrice,
+    #[msg("The attestation was issued by a previous compliance authority.")]
+    AttestationIssuerRevoked,
+    #[msg("The escrow token account is required to withdraw an NFT that is not rented out.")]
+    MissingEscrowAccount,
+    #[msg("The collection stats account of the NFT's verified collection is required.")]
+    CollectionStatsRequired,
+    #[msg("A fully paid installment plan cannot be forfeited.")]
+    InstallmentFullyPaid,
+    #[msg("Sellers cannot buy or review their own listing.")]
+    SelfPurchase,
 }
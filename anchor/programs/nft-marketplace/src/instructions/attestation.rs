@@ -0,0 +1,114 @@
+use anchor_lang::prelude::*;
+
+use crate::state::*;
+use crate::errors::*;
+
+pub fn issue_attestation(
+    ctx: Context<IssueAttestation>,
+    expires_at: i64,
+) -> Result<()> {
+    msg!("Issuing buyer attestation...");
+    require!(
+        expires_at > Clock::get()?.unix_timestamp,
+        NftMarketplaceError::InvalidAttestationExpiry
+    );
+
+    // Issuing again for the same buyer just renews the expiry
+    let attestation = &mut ctx.accounts.buyer_attestation;
+    attestation.buyer = ctx.accounts.buyer.key();
+    attestation.issuer = ctx.accounts.compliance_authority.key();
+    attestation.expires_at = expires_at;
+    attestation.bump = ctx.bumps.buyer_attestation;
+
+    msg!("Buyer {} attested until {}", attestation.buyer, expires_at);
+
+    emit_attestation_event(attestation, false)
+}
+
+pub fn revoke_attestation(
+    ctx: Context<RevokeAttestation>,
+) -> Result<()> {
+    msg!("Revoking buyer attestation...");
+
+    emit_attestation_event(&ctx.accounts.buyer_attestation, true)
+}
+
+fn emit_attestation_event(attestation: &Account<BuyerAttestation>, revoked: bool) -> Result<()> {
+    let clock = Clock::get()?;
+    emit!(BuyerAttestationEvent {
+        version: EVENT_VERSION,
+        buyer: attestation.buyer,
+        issuer: attestation.issuer,
+        expires_at: attestation.expires_at,
+        revoked,
+        slot: clock.slot,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct IssueAttestation<'info> {
+    #[account(mut)]
+    pub compliance_authority: Signer<'info>,
+
+    /// CHECK: The attested buyer, only used as a seed
+    pub buyer: UncheckedAccount<'info>,
+
+    #[account(
+        seeds = [MARKETPLACE_CONFIG_SEED.as_bytes()],
+        bump = marketplace.bump,
+        has_one = compliance_authority @ NftMarketplaceError::Unauthorized,
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+
+    #[account(
+        init_if_needed,
+        payer = compliance_authority,
+        space = 8 + BuyerAttestation::MAX_SIZE,
+        seeds = [
+            BUYER_ATTESTATION_SEED.as_bytes(),
+            buyer.key().as_ref(),
+        ],
+        bump,
+    )]
+    pub buyer_attestation: Account<'info, BuyerAttestation>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RevokeAttestation<'info> {
+    #[account(mut)]
+    pub compliance_authority: Signer<'info>,
+
+    #[account(
+        seeds = [MARKETPLACE_CONFIG_SEED.as_bytes()],
+        bump = marketplace.bump,
+        has_one = compliance_authority @ NftMarketplaceError::Unauthorized,
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+
+    #[account(
+        mut,
+        seeds = [
+            BUYER_ATTESTATION_SEED.as_bytes(),
+            buyer_attestation.buyer.as_ref(),
+        ],
+        bump = buyer_attestation.bump,
+        close = compliance_authority,
+    )]
+    pub buyer_attestation: Account<'info, BuyerAttestation>,
+}
+
+#[event]
+pub struct BuyerAttestationEvent {
+    pub version: u8,
+    pub buyer: Pubkey,
+    pub issuer: Pubkey,
+    pub expires_at: i64,
+    pub revoked: bool,
+    pub slot: u64,
+    pub timestamp: i64,
+}
@@ -0,0 +1,474 @@
+use {
+    anchor_lang::{
+        prelude::*, system_program,
+    },
+    anchor_spl::{
+        associated_token::{self, get_associated_token_address, AssociatedToken},
+        token::{self, Token, TokenAccount, Mint},
+        metadata::MetadataAccount,
+    },
+};
+
+use crate::state::*;
+use crate::errors::*;
+use crate::utils::*;
+
+// Each NFT in a bundle is passed through `remaining_accounts` as a triple,
+// a purchase also passes the accounts that record the sale in the collection stats
+const ACCOUNTS_PER_NFT: usize = 3;
+const ACCOUNTS_PER_BOUGHT_NFT: usize = 5;
+
+pub fn list_bundle<'info>(
+    ctx: Context<'_, '_, 'info, 'info, ListBundle<'info>>,
+    bundle_id: u64,
+    price: u64,
+) -> Result<()> {
+    msg!("Listing bundle...");
+    let nfts = bundle_nfts(ctx.remaining_accounts, ACCOUNTS_PER_NFT)?;
+
+    let mut mints = Vec::with_capacity(nfts.len());
+    // (mint, owner token account, program PDA token account)
+    for nft in nfts {
+        let mint = Account::<Mint>::try_from(&nft[0])?;
+        require!(is_nft(&mint), NftMarketplaceError::NotAnNft);
+        require!(!mints.contains(&mint.key()), NftMarketplaceError::DuplicateBundleMint);
+
+        let owner_token_account = Account::<TokenAccount>::try_from(&nft[1])?;
+        require_keys_eq!(owner_token_account.mint, mint.key(), NftMarketplaceError::InvalidBundleAccounts);
+        require_keys_eq!(owner_token_account.owner, ctx.accounts.owner.key(), NftMarketplaceError::InvalidBundleAccounts);
+        require!(!owner_token_account.is_frozen(), NftMarketplaceError::NftNonTransferable);
+        require_keys_eq!(
+            nft[2].key(),
+            get_associated_token_address(&ctx.accounts.program_pda.key(), &mint.key()),
+            NftMarketplaceError::InvalidBundleAccounts
+        );
+
+        associated_token::create_idempotent(
+            CpiContext::new(
+                ctx.accounts.associated_token_program.to_account_info(),
+                associated_token::Create {
+                    payer: ctx.accounts.owner.to_account_info(),
+                    associated_token: nft[2].clone(),
+                    authority: ctx.accounts.program_pda.to_account_info(),
+                    mint: nft[0].clone(),
+                    system_program: ctx.accounts.system_program.to_account_info(),
+                    token_program: ctx.accounts.token_program.to_account_info(),
+                }
+            ),
+        )?;
+
+        token::transfer(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: nft[1].clone(),
+                    to: nft[2].clone(),
+                    authority: ctx.accounts.owner.to_account_info(),
+                }),
+            1
+        )?;
+
+        mints.push(mint.key());
+    }
+
+    let bundle = &mut ctx.accounts.bundle;
+    bundle.owner = ctx.accounts.owner.key();
+    bundle.bundle_id = bundle_id;
+    bundle.price = price;
+    bundle.mints = mints;
+    bundle.bump = ctx.bumps.bundle;
+
+    msg!("Bundle of {} NFTs listed successfully!", bundle.mints.len());
+
+    let clock = Clock::get()?;
+    emit!(BundleListedEvent {
+        version: EVENT_VERSION,
+        bundle: bundle.key(),
+        owner: bundle.owner,
+        mints: bundle.mints.clone(),
+        price,
+        slot: clock.slot,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+pub fn buy_bundle<'info>(
+    ctx: Context<'_, '_, 'info, 'info, BuyBundle<'info>>,
+) -> Result<()> {
+    msg!("Buying bundle...");
+    let bundle = &ctx.accounts.bundle;
+    let nfts = bundle_nfts(ctx.remaining_accounts, ACCOUNTS_PER_BOUGHT_NFT)?;
+    require!(nfts.len() == bundle.mints.len(), NftMarketplaceError::InvalidBundleAccounts);
+
+    require!(
+        ctx.accounts.buyer.lamports() >= bundle.price,
+        NftMarketplaceError::InsufficientFunds
+    );
+
+    let (seller_proceeds, marketplace_fee, _) = ctx.accounts.marketplace.split_price(bundle.price, false);
+
+    system_program::transfer(
+        CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            system_program::Transfer {
+                from: ctx.accounts.buyer.to_account_info(),
+                to: ctx.accounts.seller.to_account_info(),
+            }
+        ),
+        seller_proceeds,
+    )?;
+
+    if marketplace_fee > 0 {
+        system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.buyer.to_account_info(),
+                    to: ctx.accounts.treasury.to_account_info(),
+                }
+            ),
+            marketplace_fee,
+        )?;
+    }
+
+    let bump_seed = ctx.bumps.program_pda;
+    let program_pda_seeds : &[&[&[u8]]] = &[&[NFT_MARKET_PLACE_SEED.as_bytes(), &[bump_seed]]];
+
+    // A bundle has no price per NFT, so every NFT counts in its collection
+    // stats with an even share of the price and the first one takes the remainder
+    let clock = Clock::get()?;
+    let share = bundle.price / nfts.len() as u64;
+    let remainder = bundle.price % nfts.len() as u64;
+
+    // (mint, program PDA token account, buyer token account, metadata,
+    // collection stats or the program id when there are none), in listing order
+    for (i, (nft, mint)) in nfts.iter().zip(bundle.mints.iter()).enumerate() {
+        require_keys_eq!(nft[0].key(), *mint, NftMarketplaceError::InvalidBundleAccounts);
+        require_keys_eq!(
+            nft[1].key(),
+            get_associated_token_address(&ctx.accounts.program_pda.key(), mint),
+            NftMarketplaceError::InvalidBundleAccounts
+        );
+        require_keys_eq!(
+            nft[2].key(),
+            get_associated_token_address(&ctx.accounts.buyer.key(), mint),
+            NftMarketplaceError::InvalidBundleAccounts
+        );
+
+        associated_token::create_idempotent(
+            CpiContext::new(
+                ctx.accounts.associated_token_program.to_account_info(),
+                associated_token::Create {
+                    payer: ctx.accounts.buyer.to_account_info(),
+                    associated_token: nft[2].clone(),
+                    authority: ctx.accounts.buyer.to_account_info(),
+                    mint: nft[0].clone(),
+                    system_program: ctx.accounts.system_program.to_account_info(),
+                    token_program: ctx.accounts.token_program.to_account_info(),
+                }
+            ),
+        )?;
+
+        token::transfer(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: nft[1].clone(),
+                    to: nft[2].clone(),
+                    authority: ctx.accounts.program_pda.to_account_info(),
+                })
+            .with_signer(program_pda_seeds),
+            1,
+        )?;
+
+        token::close_account(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token::CloseAccount {
+                    account: nft[1].clone(),
+                    destination: ctx.accounts.seller.to_account_info(),
+                    authority: ctx.accounts.program_pda.to_account_info(),
+                })
+            .with_signer(program_pda_seeds),
+        )?;
+
+        let metadata = Account::<MetadataAccount>::try_from(&nft[3])?;
+        require_keys_eq!(metadata.mint, *mint, NftMarketplaceError::InvalidBundleAccounts);
+        let collection_stats = (nft[4].key() != *ctx.program_id).then_some(&nft[4]);
+        let price = if i == 0 { share + remainder } else { share };
+        record_collection_sale(ctx.program_id, &metadata, collection_stats, price, clock.unix_timestamp)?;
+    }
+
+    // The marketplace totals count the bundle as a single sale
+    ctx.accounts.marketplace_stats.bump = ctx.bumps.marketplace_stats;
+    ctx.accounts.marketplace_stats.record_sale(bundle.price);
+
+    msg!("Bundle purchased successfully!");
+
+    emit!(BundleSoldEvent {
+        version: EVENT_VERSION,
+        bundle: bundle.key(),
+        seller: ctx.accounts.seller.key(),
+        buyer: ctx.accounts.buyer.key(),
+        mints: bundle.mints.clone(),
+        price: bundle.price,
+        marketplace_fee,
+        seller_proceeds,
+        slot: clock.slot,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+// Delists the bundle, the seller gets every NFT back along with the rent
+// of the bundle and escrow accounts
+pub fn cancel_bundle<'info>(
+    ctx: Context<'_, '_, 'info, 'info, CancelBundle<'info>>,
+) -> Result<()> {
+    msg!("Cancelling bundle...");
+    let bundle = &ctx.accounts.bundle;
+    let nfts = bundle_nfts(ctx.remaining_accounts, ACCOUNTS_PER_NFT)?;
+    require!(nfts.len() == bundle.mints.len(), NftMarketplaceError::InvalidBundleAccounts);
+
+    let bump_seed = ctx.bumps.program_pda;
+    let program_pda_seeds : &[&[&[u8]]] = &[&[NFT_MARKET_PLACE_SEED.as_bytes(), &[bump_seed]]];
+
+    // (mint, program PDA token account, owner token account), in listing order
+    for (nft, mint) in nfts.iter().zip(bundle.mints.iter()) {
+        require_keys_eq!(nft[0].key(), *mint, NftMarketplaceError::InvalidBundleAccounts);
+        require_keys_eq!(
+            nft[1].key(),
+            get_associated_token_address(&ctx.accounts.program_pda.key(), mint),
+            NftMarketplaceError::InvalidBundleAccounts
+        );
+        require_keys_eq!(
+            nft[2].key(),
+            get_associated_token_address(&ctx.accounts.owner.key(), mint),
+            NftMarketplaceError::InvalidBundleAccounts
+        );
+
+        associated_token::create_idempotent(
+            CpiContext::new(
+                ctx.accounts.associated_token_program.to_account_info(),
+                associated_token::Create {
+                    payer: ctx.accounts.owner.to_account_info(),
+                    associated_token: nft[2].clone(),
+                    authority: ctx.accounts.owner.to_account_info(),
+                    mint: nft[0].clone(),
+                    system_program: ctx.accounts.system_program.to_account_info(),
+                    token_program: ctx.accounts.token_program.to_account_info(),
+                }
+            ),
+        )?;
+
+        token::transfer(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: nft[1].clone(),
+                    to: nft[2].clone(),
+                    authority: ctx.accounts.program_pda.to_account_info(),
+                })
+            .with_signer(program_pda_seeds),
+            1,
+        )?;
+
+        token::close_account(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token::CloseAccount {
+                    account: nft[1].clone(),
+                    destination: ctx.accounts.owner.to_account_info(),
+                    authority: ctx.accounts.program_pda.to_account_info(),
+                })
+            .with_signer(program_pda_seeds),
+        )?;
+    }
+
+    msg!("Bundle cancelled, NFTs returned to the owner.");
+
+    let clock = Clock::get()?;
+    emit!(BundleCancelledEvent {
+        version: EVENT_VERSION,
+        bundle: bundle.key(),
+        owner: bundle.owner,
+        mints: bundle.mints.clone(),
+        slot: clock.slot,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+fn bundle_nfts<'a, 'info>(
+    accounts: &'a [AccountInfo<'info>],
+    accounts_per_nft: usize,
+) -> Result<Vec<&'a [AccountInfo<'info>]>> {
+    let chunks = accounts.chunks_exact(accounts_per_nft);
+    require!(chunks.remainder().is_empty(), NftMarketplaceError::InvalidBundleAccounts);
+
+    let nfts: Vec<_> = chunks.collect();
+    require!(
+        (2..=Bundle::MAX_MINTS).contains(&nfts.len()),
+        NftMarketplaceError::InvalidBundleSize
+    );
+
+    Ok(nfts)
+}
+
+#[derive(Accounts)]
+#[instruction(bundle_id: u64)]
+pub struct ListBundle<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + Bundle::MAX_SIZE,
+        seeds = [
+            BUNDLE_SEED.as_bytes(),
+            owner.key().as_ref(),
+            &bundle_id.to_le_bytes(),
+        ],
+        bump,
+    )]
+    pub bundle: Account<'info, Bundle>,
+
+    /// CHECK: Program Derived Address
+    #[account(
+        seeds = [NFT_MARKET_PLACE_SEED.as_bytes()],
+        bump,
+    )]
+    pub program_pda: UncheckedAccount<'info>,
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct BuyBundle<'info> {
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    /// CHECK: The seller's account, gets the bundle and escrow rent back
+    #[account(
+        mut,
+        address = bundle.owner,
+    )]
+    pub seller: UncheckedAccount<'info>,
+
+    #[account(
+        seeds = [MARKETPLACE_CONFIG_SEED.as_bytes()],
+        bump = marketplace.bump,
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+
+    /// CHECK: Only receives the platform fee, must match the marketplace treasury
+    #[account(
+        mut,
+        address = marketplace.treasury,
+    )]
+    pub treasury: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            BUNDLE_SEED.as_bytes(),
+            bundle.owner.as_ref(),
+            &bundle.bundle_id.to_le_bytes(),
+        ],
+        bump = bundle.bump,
+        close = seller,
+    )]
+    pub bundle: Account<'info, Bundle>,
+
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        space = 8 + MarketplaceStats::MAX_SIZE,
+        seeds = [MARKETPLACE_STATS_SEED.as_bytes()],
+        bump,
+    )]
+    pub marketplace_stats: Account<'info, MarketplaceStats>,
+
+    /// CHECK: Program Derived Address
+    #[account(
+        seeds = [NFT_MARKET_PLACE_SEED.as_bytes()],
+        bump,
+    )]
+    pub program_pda: UncheckedAccount<'info>,
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelBundle<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            BUNDLE_SEED.as_bytes(),
+            owner.key().as_ref(),
+            &bundle.bundle_id.to_le_bytes(),
+        ],
+        bump = bundle.bump,
+        has_one = owner @ NftMarketplaceError::Unauthorized,
+        close = owner,
+    )]
+    pub bundle: Account<'info, Bundle>,
+
+    /// CHECK: Program Derived Address
+    #[account(
+        seeds = [NFT_MARKET_PLACE_SEED.as_bytes()],
+        bump,
+    )]
+    pub program_pda: UncheckedAccount<'info>,
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event]
+pub struct BundleListedEvent {
+    pub version: u8,
+    pub bundle: Pubkey,
+    pub owner: Pubkey,
+    pub mints: Vec<Pubkey>,
+    pub price: u64,
+    pub slot: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct BundleSoldEvent {
+    pub version: u8,
+    pub bundle: Pubkey,
+    pub seller: Pubkey,
+    pub buyer: Pubkey,
+    pub mints: Vec<Pubkey>,
+    pub price: u64,
+    pub marketplace_fee: u64,
+    pub seller_proceeds: u64,
+    pub slot: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct BundleCancelledEvent {
+    pub version: u8,
+    pub bundle: Pubkey,
+    pub owner: Pubkey,
+    pub mints: Vec<Pubkey>,
+    pub slot: u64,
+    pub timestamp: i64,
+}
@@ -0,0 +1,127 @@
+use {
+    anchor_lang::prelude::*,
+    anchor_spl::{
+        token::{Token, TokenAccount, Mint},
+        metadata::{
+            burn_nft,
+            BurnNft as MetaplexBurnNft,
+            Metadata,
+        },
+    }
+};
+
+use crate::state::*;
+use crate::errors::*;
+
+pub fn burn(
+    ctx: Context<BurnNft>,
+) -> Result<()> {
+    msg!("Burning NFT...");
+    msg!("Mint: {}", &ctx.accounts.mint.key());
+
+    // A listed NFT sits in the program escrow, refuse explicitly
+    // instead of letting the token program fail on an empty account
+    require!(
+        ctx.accounts.nft_listing_account.data_is_empty(),
+        NftMarketplaceError::NftCurrentlyListed
+    );
+
+    let lamports_before = ctx.accounts.owner.lamports();
+
+    let collection_metadata = ctx.accounts.collection_metadata.as_ref();
+    let mut cpi_ctx = CpiContext::new(
+        ctx.accounts.token_metadata_program.to_account_info(),
+        MetaplexBurnNft {
+            metadata: ctx.accounts.metadata.to_account_info(),
+            owner: ctx.accounts.owner.to_account_info(),
+            mint: ctx.accounts.mint.to_account_info(),
+            token: ctx.accounts.owner_token_account.to_account_info(),
+            edition: ctx.accounts.master_edition.to_account_info(),
+            spl_token: ctx.accounts.token_program.to_account_info(),
+        }
+    );
+    if let Some(collection_metadata) = collection_metadata {
+        cpi_ctx = cpi_ctx.with_remaining_accounts(vec![collection_metadata.to_account_info()]);
+    }
+    burn_nft(cpi_ctx, collection_metadata.map(|account| account.key()))?;
+
+    // Metaplex closes the token, metadata and edition accounts into the owner
+    let rent_reclaimed = ctx.accounts.owner.lamports().saturating_sub(lamports_before);
+
+    msg!("NFT burned, reclaimed {} lamports", rent_reclaimed);
+
+    let clock = Clock::get()?;
+    emit!(NftBurnedEvent {
+        version: EVENT_VERSION,
+        mint: ctx.accounts.mint.key(),
+        owner: ctx.accounts.owner.key(),
+        marketplace: *ctx.program_id,
+        rent_reclaimed,
+        slot: clock.slot,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct BurnNft<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(mut)]
+    pub mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        constraint = owner_token_account.mint == mint.key(),
+        constraint = owner_token_account.owner == owner.key(),
+    )]
+    pub owner_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"metadata".as_ref(), token_metadata_program.key().as_ref(), mint.key().as_ref()],
+        bump,
+        seeds::program = token_metadata_program.key()
+    )]
+    /// CHECK: Metaplex will do the check
+    pub metadata: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"metadata".as_ref(), token_metadata_program.key().as_ref(), mint.key().as_ref(), b"edition".as_ref()],
+        bump,
+        seeds::program = token_metadata_program.key()
+    )]
+    /// CHECK: Metaplex will do the check
+    pub master_edition: UncheckedAccount<'info>,
+
+    /// CHECK: Only required when the NFT is a verified member of a sized collection
+    #[account(mut)]
+    pub collection_metadata: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Listing PDA of the mint, must not exist for the burn to go through
+    #[account(
+        seeds = [
+            LISTED_NFT_SEED.as_bytes(),
+            mint.key().as_ref()
+        ],
+        bump,
+    )]
+    pub nft_listing_account: UncheckedAccount<'info>,
+
+    pub token_metadata_program: Program<'info, Metadata>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[event]
+pub struct NftBurnedEvent {
+    pub version: u8,
+    pub mint: Pubkey,
+    pub owner: Pubkey,
+    pub marketplace: Pubkey,
+    pub rent_reclaimed: u64,
+    pub slot: u64,
+    pub timestamp: i64,
+}
@@ -5,28 +5,72 @@ use {
     anchor_spl::{
         associated_token::AssociatedToken,
         token::{self, Token, TokenAccount, Mint},
+        metadata::{Metadata, MetadataAccount},
     },
 };
 
 use crate::state::*;
 use crate::errors::*;
+use crate::utils::*;
 
 
 pub fn buy(
     ctx: Context<BuyNft>,
+    max_price: Option<u64>,
 ) -> Result<()> {
     let nft_listing_account = &mut ctx.accounts.nft_listing_account;
 
     msg!("Buying NFT");
+    // A self-purchase only costs the fee and would mint a receipt for a fake review
+    require_keys_neq!(
+        ctx.accounts.buyer.key(),
+        ctx.accounts.seller.key(),
+        NftMarketplaceError::SelfPurchase
+    );
+    check_buyer_attestation(
+        &ctx.accounts.marketplace,
+        nft_listing_account,
+        ctx.accounts.buyer_attestation.as_ref(),
+        &ctx.accounts.buyer.key(),
+    )?;
+
+    let clock = Clock::get()?;
+    let price = if nft_listing_account.usd_price > 0 {
+        let price_feed = ctx.accounts.price_feed
+            .as_ref()
+            .ok_or(NftMarketplaceError::InvalidPriceFeed)?;
+        usd_to_lamports(
+            &ctx.accounts.marketplace,
+            price_feed,
+            nft_listing_account.usd_price,
+            clock.unix_timestamp,
+        )?
+    } else {
+        nft_listing_account.price
+    };
+    // Guards the buyer against SOL moving, or a relisting at another price.
+    // The lamport price of a USD listing is unknown upfront, so it needs one.
+    require!(
+        max_price.is_some() || nft_listing_account.usd_price == 0,
+        NftMarketplaceError::MaxPriceRequired
+    );
+    if let Some(max_price) = max_price {
+        require!(price <= max_price, NftMarketplaceError::PriceAboveMax);
+    }
+
     // Ensure the buyer has sent enough lamports
     require!(
-        ctx.accounts.buyer.lamports() >= nft_listing_account.price,
+        ctx.accounts.buyer.lamports() >= price,
         NftMarketplaceError::InsufficientFunds
     );
 
     let bump_seed = ctx.bumps.program_pda;
     let program_pda_seeds : &[&[&[u8]]] = &[&[NFT_MARKET_PLACE_SEED.as_bytes(), &[bump_seed]]];
 
+    let with_referrer = ctx.accounts.referrer.is_some();
+    let (seller_proceeds, marketplace_fee, referral_fee) =
+        ctx.accounts.marketplace.split_price(price, with_referrer);
+
     system_program::transfer(
         CpiContext::new(
             ctx.accounts.system_program.to_account_info(),
@@ -35,10 +79,41 @@ pub fn buy(
                 to: ctx.accounts.seller.to_account_info(),
             }
         ),
-        nft_listing_account.price,
+        seller_proceeds,
     )?;
     msg!("Lamports transferred successfully.");
 
+    if marketplace_fee > 0 {
+        system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.buyer.to_account_info(),
+                    to: ctx.accounts.treasury.to_account_info(),
+                }
+            ),
+            marketplace_fee,
+        )?;
+        msg!("Marketplace fee transferred.");
+    }
+
+    if let Some(referrer) = &ctx.accounts.referrer {
+        require_keys_neq!(referrer.key(), ctx.accounts.buyer.key(), NftMarketplaceError::SelfReferral);
+        if referral_fee > 0 {
+            system_program::transfer(
+                CpiContext::new(
+                    ctx.accounts.system_program.to_account_info(),
+                    system_program::Transfer {
+                        from: ctx.accounts.buyer.to_account_info(),
+                        to: referrer.to_account_info(),
+                    }
+                ),
+                referral_fee,
+            )?;
+            msg!("Referral fee transferred.");
+        }
+    }
+
     // // Transfer payment from buyer to seller - I personally don't like this way
     // **ctx.accounts.buyer.lamports.borrow_mut() -= listing_account.price;
     // **ctx.accounts.seller.lamports.borrow_mut() += listing_account.price;
@@ -56,13 +131,51 @@ pub fn buy(
         1,
     )?;
 
+    // The escrow is empty now, hand its rent back to the lister
+    close_escrow_account(
+        &ctx.accounts.pda_token_account,
+        &ctx.accounts.seller.to_account_info(),
+        &ctx.accounts.program_pda,
+        bump_seed,
+        &ctx.accounts.token_program,
+    )?;
+
+    let sale_receipt = &mut ctx.accounts.sale_receipt;
+    sale_receipt.mint = ctx.accounts.mint.key();
+    sale_receipt.seller = ctx.accounts.seller.key();
+    sale_receipt.buyer = ctx.accounts.buyer.key();
+    sale_receipt.price = price;
+    sale_receipt.bump = ctx.bumps.sale_receipt;
+
+    let marketplace_stats = &mut ctx.accounts.marketplace_stats;
+    marketplace_stats.bump = ctx.bumps.marketplace_stats;
+    marketplace_stats.record_delisting();
+    marketplace_stats.record_sale(price);
+
+    record_collection_sale(
+        ctx.program_id,
+        &ctx.accounts.metadata,
+        ctx.accounts.collection_stats.as_deref(),
+        price,
+        clock.unix_timestamp,
+    )?;
+
     msg!("NFT purchased successfully!");
 
     emit!(NFTSoldEvent {
+        version: EVENT_VERSION,
         mint: ctx.accounts.mint.key(),
         seller: ctx.accounts.seller.key(),
         buyer: ctx.accounts.buyer.key(),
         marketplace: *ctx.program_id,
+        price,
+        marketplace_fee,
+        seller_proceeds,
+        referrer: ctx.accounts.referrer.as_ref().map(|referrer| referrer.key()),
+        referral_fee,
+        payment_mint: None,
+        slot: clock.slot,
+        timestamp: clock.unix_timestamp,
     });
 
     Ok(())
@@ -73,24 +186,78 @@ pub struct BuyNft<'info> {
     #[account(mut)]
     pub buyer: Signer<'info>,
 
-    /// CHECK: The seller's account
-    #[account(mut)]
+    /// CHECK: The seller's account, must be the owner of the listing
+    #[account(
+        mut,
+        address = nft_listing_account.owner,
+    )]
     pub seller: UncheckedAccount<'info>,
 
     #[account(mut)]
     pub mint: Account<'info, Mint>,
 
+    #[account(
+        seeds = [MARKETPLACE_CONFIG_SEED.as_bytes()],
+        bump = marketplace.bump,
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+
+    /// CHECK: Only receives the platform fee, must match the marketplace treasury
+    #[account(
+        mut,
+        address = marketplace.treasury,
+    )]
+    pub treasury: UncheckedAccount<'info>,
+
+    /// CHECK: Optional referrer, only receives its share of the platform fee
+    #[account(mut)]
+    pub referrer: Option<UncheckedAccount<'info>>,
+
     #[account(
         mut,
         seeds = [
             LISTED_NFT_SEED.as_bytes(),
             mint.key().as_ref()
         ],
-        bump,
+        bump = nft_listing_account.bump,
         close = seller,
     )]
     pub nft_listing_account: Account<'info, ListedNft>,
 
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        space = 8 + MarketplaceStats::MAX_SIZE,
+        seeds = [MARKETPLACE_STATS_SEED.as_bytes()],
+        bump,
+    )]
+    pub marketplace_stats: Account<'info, MarketplaceStats>,
+
+    /// CHECK: Stats PDA of the NFT's verified collection, checked and written by the handler
+    #[account(mut)]
+    pub collection_stats: Option<UncheckedAccount<'info>>,
+
+    // Proves the collection membership, so the sale lands in its stats
+    #[account(
+        seeds = [b"metadata".as_ref(), Metadata::id().as_ref(), mint.key().as_ref()],
+        bump,
+        seeds::program = Metadata::id(),
+    )]
+    pub metadata: Account<'info, MetadataAccount>,
+
+    #[account(
+        seeds = [
+            BUYER_ATTESTATION_SEED.as_bytes(),
+            buyer.key().as_ref(),
+        ],
+        bump = buyer_attestation.bump,
+    )]
+    pub buyer_attestation: Option<Account<'info, BuyerAttestation>>,
+
+    /// CHECK: SOL/USD price account pinned in the config, parsed by the handler for USD listings
+    #[account(address = marketplace.price_feed @ NftMarketplaceError::InvalidPriceFeed)]
+    pub price_feed: Option<UncheckedAccount<'info>>,
+
     #[account(
         mut,
         associated_token::mint = mint,
@@ -106,6 +273,20 @@ pub struct BuyNft<'info> {
     )]
     pub buyer_token_account: Account<'info, TokenAccount>,
 
+    // Lets the buyer review the seller once
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        space = 8 + SaleReceipt::MAX_SIZE,
+        seeds = [
+            SALE_RECEIPT_SEED.as_bytes(),
+            mint.key().as_ref(),
+            buyer.key().as_ref(),
+        ],
+        bump,
+    )]
+    pub sale_receipt: Account<'info, SaleReceipt>,
+
     /// CHECK: Program Derived Address
     #[account(
         seeds = [NFT_MARKET_PLACE_SEED.as_bytes()],
@@ -122,8 +303,21 @@ pub struct BuyNft<'info> {
 
 #[event]
 pub struct NFTSoldEvent {
+    pub version: u8,
     pub mint: Pubkey,
     pub seller: Pubkey,
     pub buyer: Pubkey,
     pub marketplace: Pubkey,
+    // Full amount paid by the buyer, in lamports
+    pub price: u64,
+    // Platform cut paid to the treasury, excluding the referral fee
+    pub marketplace_fee: u64,
+    // What actually ended up in the seller's account
+    pub seller_proceeds: u64,
+    pub referrer: Option<Pubkey>,
+    pub referral_fee: u64,
+    // `None` means the sale was settled in native SOL
+    pub payment_mint: Option<Pubkey>,
+    pub slot: u64,
+    pub timestamp: i64,
 }
@@ -0,0 +1,282 @@
+use {
+    anchor_lang::{
+        prelude::*, system_program,
+    },
+    anchor_spl::{
+        associated_token::AssociatedToken,
+        token::{self, Token, TokenAccount, Mint},
+        metadata::{Metadata, MetadataAccount},
+    },
+};
+
+use crate::state::*;
+use crate::errors::*;
+use crate::utils::*;
+
+pub fn place_collection_bid(
+    ctx: Context<PlaceCollectionBid>,
+    collection: Pubkey,
+    trait_root: [u8; 32],
+    price: u64,
+) -> Result<()> {
+    msg!("Placing collection bid...");
+    require!(price > 0, NftMarketplaceError::InvalidBidPrice);
+
+    // The bid PDA escrows the offered lamports until it is accepted or cancelled
+    system_program::transfer(
+        CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            system_program::Transfer {
+                from: ctx.accounts.bidder.to_account_info(),
+                to: ctx.accounts.bid.to_account_info(),
+            }
+        ),
+        price,
+    )?;
+
+    let bid = &mut ctx.accounts.bid;
+    bid.bidder = ctx.accounts.bidder.key();
+    bid.collection = collection;
+    bid.price = price;
+    bid.trait_root = trait_root;
+    bid.bump = ctx.bumps.bid;
+
+    msg!("Bid of {} lamports placed on collection {}", price, collection);
+
+    emit_collection_bid_event(bid, None, CollectionBidStatus::Placed, 0)
+}
+
+pub fn cancel_collection_bid(
+    ctx: Context<CancelCollectionBid>,
+) -> Result<()> {
+    msg!("Cancelling collection bid...");
+
+    // Closing the bid returns the escrowed lamports along with the rent
+    emit_collection_bid_event(&ctx.accounts.bid, None, CollectionBidStatus::Cancelled, 0)
+}
+
+pub fn accept_collection_bid(
+    ctx: Context<AcceptCollectionBid>,
+    proof: Vec<[u8; 32]>,
+) -> Result<()> {
+    msg!("Accepting collection bid...");
+    let bid = &ctx.accounts.bid;
+
+    require!(
+        is_verified_collection_member(&ctx.accounts.metadata, &bid.collection),
+        NftMarketplaceError::NotInCollection
+    );
+    if bid.trait_root != [0u8; 32] {
+        require!(
+            verify_merkle_proof(&proof, &bid.trait_root, merkle_leaf(&ctx.accounts.mint.key())),
+            NftMarketplaceError::TraitMismatch
+        );
+    }
+
+    token::transfer(
+        CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            token::Transfer {
+                from: ctx.accounts.seller_token_account.to_account_info(),
+                to: ctx.accounts.bidder_token_account.to_account_info(),
+                authority: ctx.accounts.seller.to_account_info(),
+            }),
+        1
+    )?;
+
+    let price = bid.price;
+    let (seller_proceeds, marketplace_fee, _) = ctx.accounts.marketplace.split_price(price, false);
+
+    ctx.accounts.bid.sub_lamports(price)?;
+    ctx.accounts.seller.add_lamports(seller_proceeds)?;
+    ctx.accounts.treasury.add_lamports(marketplace_fee)?;
+
+    ctx.accounts.marketplace_stats.bump = ctx.bumps.marketplace_stats;
+    ctx.accounts.marketplace_stats.record_sale(price);
+    record_collection_sale(
+        ctx.program_id,
+        &ctx.accounts.metadata,
+        ctx.accounts.collection_stats.as_deref(),
+        price,
+        Clock::get()?.unix_timestamp,
+    )?;
+
+    msg!("NFT sold to collection bidder!");
+
+    emit_collection_bid_event(
+        &ctx.accounts.bid,
+        Some(ctx.accounts.mint.key()),
+        CollectionBidStatus::Accepted,
+        marketplace_fee,
+    )
+}
+
+fn emit_collection_bid_event(
+    bid: &Account<CollectionBid>,
+    mint: Option<Pubkey>,
+    status: CollectionBidStatus,
+    marketplace_fee: u64,
+) -> Result<()> {
+    let clock = Clock::get()?;
+    emit!(CollectionBidEvent {
+        version: EVENT_VERSION,
+        bid: bid.key(),
+        bidder: bid.bidder,
+        collection: bid.collection,
+        trait_root: bid.trait_root,
+        price: bid.price,
+        mint,
+        marketplace_fee,
+        status,
+        slot: clock.slot,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(collection: Pubkey, trait_root: [u8; 32])]
+pub struct PlaceCollectionBid<'info> {
+    #[account(mut)]
+    pub bidder: Signer<'info>,
+
+    #[account(
+        init,
+        payer = bidder,
+        space = 8 + CollectionBid::MAX_SIZE,
+        seeds = [
+            COLLECTION_BID_SEED.as_bytes(),
+            bidder.key().as_ref(),
+            collection.as_ref(),
+            trait_root.as_ref(),
+        ],
+        bump,
+    )]
+    pub bid: Account<'info, CollectionBid>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelCollectionBid<'info> {
+    #[account(mut)]
+    pub bidder: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            COLLECTION_BID_SEED.as_bytes(),
+            bidder.key().as_ref(),
+            bid.collection.as_ref(),
+            bid.trait_root.as_ref(),
+        ],
+        bump = bid.bump,
+        has_one = bidder,
+        close = bidder,
+    )]
+    pub bid: Account<'info, CollectionBid>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptCollectionBid<'info> {
+    #[account(mut)]
+    pub seller: Signer<'info>,
+
+    /// CHECK: The bidder's account, gets the bid rent back
+    #[account(
+        mut,
+        address = bid.bidder,
+    )]
+    pub bidder: UncheckedAccount<'info>,
+
+    pub mint: Account<'info, Mint>,
+
+    #[account(
+        seeds = [b"metadata".as_ref(), token_metadata_program.key().as_ref(), mint.key().as_ref()],
+        bump,
+        seeds::program = token_metadata_program.key(),
+    )]
+    pub metadata: Account<'info, MetadataAccount>,
+
+    #[account(
+        mut,
+        constraint = seller_token_account.mint == mint.key(),
+        constraint = seller_token_account.owner == seller.key(),
+    )]
+    pub seller_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        init_if_needed,
+        payer = seller,
+        associated_token::mint = mint,
+        associated_token::authority = bidder,
+    )]
+    pub bidder_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [
+            COLLECTION_BID_SEED.as_bytes(),
+            bid.bidder.as_ref(),
+            bid.collection.as_ref(),
+            bid.trait_root.as_ref(),
+        ],
+        bump = bid.bump,
+        close = bidder,
+    )]
+    pub bid: Account<'info, CollectionBid>,
+
+    #[account(
+        seeds = [MARKETPLACE_CONFIG_SEED.as_bytes()],
+        bump = marketplace.bump,
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+
+    /// CHECK: Only receives the platform fee, must match the marketplace treasury
+    #[account(
+        mut,
+        address = marketplace.treasury,
+    )]
+    pub treasury: UncheckedAccount<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = seller,
+        space = 8 + MarketplaceStats::MAX_SIZE,
+        seeds = [MARKETPLACE_STATS_SEED.as_bytes()],
+        bump,
+    )]
+    pub marketplace_stats: Account<'info, MarketplaceStats>,
+
+    /// CHECK: Stats PDA of the bid's collection, checked and written by the handler
+    #[account(mut)]
+    pub collection_stats: Option<UncheckedAccount<'info>>,
+
+    pub token_metadata_program: Program<'info, Metadata>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(AnchorDeserialize, AnchorSerialize, Clone)]
+pub enum CollectionBidStatus {
+    Placed,
+    Cancelled,
+    Accepted,
+}
+
+#[event]
+pub struct CollectionBidEvent {
+    pub version: u8,
+    pub bid: Pubkey,
+    pub bidder: Pubkey,
+    pub collection: Pubkey,
+    pub trait_root: [u8; 32],
+    pub price: u64,
+    pub mint: Option<Pubkey>,
+    pub marketplace_fee: u64,
+    pub status: CollectionBidStatus,
+    pub slot: u64,
+    pub timestamp: i64,
+}
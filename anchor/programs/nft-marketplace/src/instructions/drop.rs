@@ -0,0 +1,433 @@
+use {
+    anchor_lang::{
+        prelude::*, system_program,
+    },
+    anchor_spl::{
+        associated_token::AssociatedToken,
+        token::Token,
+        metadata::{
+            update_metadata_accounts_v2,
+            UpdateMetadataAccountsV2,
+            Metadata,
+            MetadataAccount,
+            mpl_token_metadata::{
+                types::{DataV2, Creator},
+                MAX_NAME_LENGTH, MAX_URI_LENGTH,
+            },
+        },
+    },
+};
+
+use crate::state::*;
+use crate::errors::*;
+use crate::utils::*;
+use crate::instructions::mint::create_mint_and_mint_one;
+use crate::instructions::metadata::{create_metadata_and_master_edition, MetadataCpiAccounts};
+use crate::instructions::update_metadata::MetadataUpdatedEvent;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct DropParams {
+    pub treasury: Pubkey,
+    pub supply_cap: u64,
+    pub price: u64,
+    pub start_time: i64,
+    pub per_wallet_limit: u16,
+    pub merkle_root: [u8; 32],
+    pub name: String,
+    pub base_uri: String,
+}
+
+pub fn initialize_drop(
+    ctx: Context<InitializeDrop>,
+    drop_id: u64,
+    params: DropParams,
+) -> Result<()> {
+    msg!("Initializing drop...");
+    require!(params.supply_cap > 0, NftMarketplaceError::InvalidDropSupply);
+    require!(params.name.len() <= Drop::MAX_NAME_LEN, NftMarketplaceError::DropNameTooLong);
+    require!(params.base_uri.len() <= Drop::MAX_BASE_URI_LEN, NftMarketplaceError::DropUriTooLong);
+    // The last NFT gets the longest number, Metaplex would reject its mint
+    require!(
+        drop_nft_name(&params.name, params.supply_cap).len() <= MAX_NAME_LENGTH,
+        NftMarketplaceError::DropNameTooLong
+    );
+    require!(
+        drop_nft_uri(&params.base_uri, params.supply_cap).len() <= MAX_URI_LENGTH,
+        NftMarketplaceError::DropUriTooLong
+    );
+
+    let drop = &mut ctx.accounts.drop;
+    drop.creator = ctx.accounts.creator.key();
+    drop.treasury = params.treasury;
+    drop.drop_id = drop_id;
+    drop.supply_cap = params.supply_cap;
+    drop.minted = 0;
+    drop.price = params.price;
+    drop.start_time = params.start_time;
+    drop.per_wallet_limit = params.per_wallet_limit;
+    drop.merkle_root = params.merkle_root;
+    drop.name = params.name;
+    drop.base_uri = params.base_uri;
+    drop.bump = ctx.bumps.drop;
+
+    msg!("Drop {} initialized", drop.key());
+
+    let clock = Clock::get()?;
+    emit!(DropCreatedEvent {
+        version: EVENT_VERSION,
+        drop: drop.key(),
+        creator: drop.creator,
+        treasury: drop.treasury,
+        supply_cap: drop.supply_cap,
+        price: drop.price,
+        start_time: drop.start_time,
+        slot: clock.slot,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+pub fn mint_from_drop(
+    ctx: Context<MintFromDrop>,
+    proof: Vec<[u8; 32]>,
+) -> Result<()> {
+    let clock = Clock::get()?;
+    let drop = &ctx.accounts.drop;
+
+    msg!("Minting from drop {}", drop.key());
+    require!(clock.unix_timestamp >= drop.start_time, NftMarketplaceError::DropNotStarted);
+    require!(drop.minted < drop.supply_cap, NftMarketplaceError::DropSoldOut);
+
+    if drop.merkle_root != [0u8; 32] {
+        require!(
+            verify_merkle_proof(&proof, &drop.merkle_root, merkle_leaf(&ctx.accounts.minter.key())),
+            NftMarketplaceError::NotOnAllowlist
+        );
+    }
+
+    let mint_record = &mut ctx.accounts.mint_record;
+    if drop.per_wallet_limit > 0 {
+        require!(
+            mint_record.minted < drop.per_wallet_limit,
+            NftMarketplaceError::WalletMintLimitReached
+        );
+    }
+    mint_record.minted += 1;
+
+    if drop.price > 0 {
+        system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.minter.to_account_info(),
+                    to: ctx.accounts.treasury.to_account_info(),
+                }
+            ),
+            drop.price,
+        )?;
+        msg!("Mint price paid to the treasury.");
+    }
+
+    create_mint_and_mint_one(
+        &ctx.accounts.minter,
+        &ctx.accounts.mint,
+        &ctx.accounts.token_account,
+        &ctx.accounts.rent,
+        &ctx.accounts.system_program,
+        &ctx.accounts.token_program,
+        &ctx.accounts.associated_token_program,
+    )?;
+
+    // The drop PDA stays the update authority, so the creator manages the
+    // metadata of every NFT in the drop through `update_drop_metadata`
+    let number = drop.minted + 1;
+    let name = drop_nft_name(&drop.name, number);
+    let uri = drop_nft_uri(&drop.base_uri, number);
+    let drop_id_bytes = drop.drop_id.to_le_bytes();
+    let drop_seeds: &[&[&[u8]]] = &[&[
+        DROP_SEED.as_bytes(),
+        drop.creator.as_ref(),
+        &drop_id_bytes,
+        &[drop.bump],
+    ]];
+    let drop_info = drop.to_account_info();
+    create_metadata_and_master_edition(
+        MetadataCpiAccounts {
+            metadata: &ctx.accounts.metadata,
+            master_edition: &ctx.accounts.master_edition,
+            mint: &ctx.accounts.mint,
+            mint_authority: &ctx.accounts.minter,
+            update_authority: &drop_info,
+            token_metadata_program: &ctx.accounts.token_metadata_program,
+            system_program: &ctx.accounts.system_program,
+            token_program: &ctx.accounts.token_program,
+            rent: &ctx.accounts.rent,
+        },
+        DataV2 {
+            name: name.clone(),
+            symbol: NFT_SYMBOL.to_string(),
+            uri: uri.clone(),
+            seller_fee_basis_points: 0,
+            // The drop PDA signs as a verified creator without a share, which
+            // proves the NFT came from this drop. The creator cannot sign
+            // here and verifies later through `sign_as_creator`.
+            creators: Some(vec![
+                Creator {
+                    address: drop.key(),
+                    verified: true,
+                    share: 0,
+                },
+                Creator {
+                    address: drop.creator,
+                    verified: false,
+                    share: 100,
+                },
+            ]),
+            collection: None,
+            uses: None,
+        },
+        drop_seeds,
+    )?;
+
+    let drop = &mut ctx.accounts.drop;
+    drop.minted = number;
+
+    msg!("Minted {} from drop", name);
+
+    emit!(DropMintEvent {
+        version: EVENT_VERSION,
+        drop: drop.key(),
+        mint: ctx.accounts.mint.key(),
+        minter: ctx.accounts.minter.key(),
+        price: drop.price,
+        number,
+        name,
+        uri,
+        slot: clock.slot,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+// Lets the creator fix the URI of an NFT from the drop, or hand its update
+// authority over, with the drop PDA signing as the update authority
+pub fn update_drop_metadata(
+    ctx: Context<UpdateDropMetadata>,
+    uri: Option<String>,
+    new_update_authority: Option<Pubkey>,
+) -> Result<()> {
+    msg!("Updating metadata of drop NFT {}...", ctx.accounts.mint.key());
+    if let Some(uri) = &uri {
+        require!(uri.len() <= MAX_URI_LENGTH, NftMarketplaceError::DropUriTooLong);
+    }
+
+    // Metaplex pads the stored strings with zeroes, so strip them
+    // before handing the untouched fields back
+    let current = &ctx.accounts.metadata;
+    let data = DataV2 {
+        name: current.name.trim_matches('\0').to_string(),
+        symbol: current.symbol.trim_matches('\0').to_string(),
+        uri: uri.unwrap_or_else(|| current.uri.trim_matches('\0').to_string()),
+        seller_fee_basis_points: current.seller_fee_basis_points,
+        creators: current.creators.clone(),
+        collection: current.collection.clone(),
+        uses: current.uses.clone(),
+    };
+
+    let drop = &ctx.accounts.drop;
+    let drop_id_bytes = drop.drop_id.to_le_bytes();
+    let drop_seeds: &[&[&[u8]]] = &[&[
+        DROP_SEED.as_bytes(),
+        drop.creator.as_ref(),
+        &drop_id_bytes,
+        &[drop.bump],
+    ]];
+    update_metadata_accounts_v2(
+        CpiContext::new_with_signer(
+            ctx.accounts.token_metadata_program.to_account_info(),
+            UpdateMetadataAccountsV2 {
+                metadata: ctx.accounts.metadata.to_account_info(),
+                update_authority: drop.to_account_info(),
+            },
+            drop_seeds,
+        ),
+        new_update_authority,
+        Some(data.clone()),
+        None,
+        None,
+    )?;
+
+    msg!("Drop NFT metadata updated");
+
+    let clock = Clock::get()?;
+    emit!(MetadataUpdatedEvent {
+        version: EVENT_VERSION,
+        mint: ctx.accounts.mint.key(),
+        update_authority: new_update_authority.unwrap_or(drop.key()),
+        name: data.name,
+        uri: data.uri,
+        seller_fee_basis_points: data.seller_fee_basis_points,
+        primary_sale_happened: current.primary_sale_happened,
+        slot: clock.slot,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+fn drop_nft_name(name: &str, number: u64) -> String {
+    format!("{} #{}", name, number)
+}
+
+fn drop_nft_uri(base_uri: &str, number: u64) -> String {
+    format!("{}{}.json", base_uri, number)
+}
+
+#[derive(Accounts)]
+#[instruction(drop_id: u64)]
+pub struct InitializeDrop<'info> {
+    #[account(mut)]
+    pub creator: Signer<'info>,
+
+    #[account(
+        init,
+        payer = creator,
+        space = 8 + Drop::MAX_SIZE,
+        seeds = [
+            DROP_SEED.as_bytes(),
+            creator.key().as_ref(),
+            &drop_id.to_le_bytes(),
+        ],
+        bump,
+    )]
+    pub drop: Account<'info, Drop>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct MintFromDrop<'info> {
+    #[account(mut)]
+    pub minter: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            DROP_SEED.as_bytes(),
+            drop.creator.as_ref(),
+            &drop.drop_id.to_le_bytes(),
+        ],
+        bump = drop.bump,
+    )]
+    pub drop: Account<'info, Drop>,
+
+    #[account(
+        init_if_needed,
+        payer = minter,
+        space = 8 + DropMintRecord::MAX_SIZE,
+        seeds = [
+            DROP_MINT_RECORD_SEED.as_bytes(),
+            drop.key().as_ref(),
+            minter.key().as_ref(),
+        ],
+        bump,
+    )]
+    pub mint_record: Account<'info, DropMintRecord>,
+
+    /// CHECK: Only receives lamports, must match the drop's treasury
+    #[account(
+        mut,
+        address = drop.treasury,
+    )]
+    pub treasury: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub mint: Signer<'info>,
+
+    /// CHECK: We will create it with Anchor
+    #[account(mut)]
+    pub token_account: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"metadata".as_ref(), token_metadata_program.key().as_ref(), mint.key().as_ref()],
+        bump,
+        seeds::program = token_metadata_program.key()
+    )]
+    /// CHECK: Metaplex will do the check
+    pub metadata: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"metadata".as_ref(), token_metadata_program.key().as_ref(), mint.key().as_ref(), b"edition".as_ref()],
+        bump,
+        seeds::program = token_metadata_program.key()
+    )]
+    /// CHECK: Metaplex will do the check
+    pub master_edition: UncheckedAccount<'info>,
+
+    pub token_metadata_program: Program<'info, Metadata>,
+    pub rent: Sysvar<'info, Rent>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateDropMetadata<'info> {
+    pub creator: Signer<'info>,
+
+    #[account(
+        seeds = [
+            DROP_SEED.as_bytes(),
+            creator.key().as_ref(),
+            &drop.drop_id.to_le_bytes(),
+        ],
+        bump = drop.bump,
+        has_one = creator @ NftMarketplaceError::Unauthorized,
+    )]
+    pub drop: Account<'info, Drop>,
+
+    /// CHECK: Only used for the metadata address and the event
+    pub mint: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"metadata".as_ref(), token_metadata_program.key().as_ref(), mint.key().as_ref()],
+        bump,
+        seeds::program = token_metadata_program.key(),
+        constraint = metadata.update_authority == drop.key() @ NftMarketplaceError::InvalidUpdateAuthority,
+    )]
+    pub metadata: Account<'info, MetadataAccount>,
+
+    pub token_metadata_program: Program<'info, Metadata>,
+}
+
+#[event]
+pub struct DropCreatedEvent {
+    pub version: u8,
+    pub drop: Pubkey,
+    pub creator: Pubkey,
+    pub treasury: Pubkey,
+    pub supply_cap: u64,
+    pub price: u64,
+    pub start_time: i64,
+    pub slot: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct DropMintEvent {
+    pub version: u8,
+    pub drop: Pubkey,
+    pub mint: Pubkey,
+    pub minter: Pubkey,
+    pub price: u64,
+    pub number: u64,
+    pub name: String,
+    pub uri: String,
+    pub slot: u64,
+    pub timestamp: i64,
+}
@@ -0,0 +1,401 @@
+use {
+    anchor_lang::{
+        prelude::*, system_program,
+    },
+    anchor_spl::{
+        associated_token::AssociatedToken,
+        token::{self, Token, TokenAccount, Mint},
+    },
+};
+
+use crate::state::*;
+use crate::errors::*;
+use crate::utils::*;
+
+pub fn fractionalize(
+    ctx: Context<Fractionalize>,
+    fraction_supply: u64,
+    reserve_price: u64,
+) -> Result<()> {
+    msg!("Fractionalizing NFT...");
+    require!(fraction_supply > 0, NftMarketplaceError::InvalidFractionSupply);
+    // A zero reserve would let anyone take the NFT for free
+    require!(reserve_price > 0, NftMarketplaceError::InvalidReservePrice);
+
+    // Lock the NFT in the program PDA escrow
+    token::transfer(
+        CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            token::Transfer {
+                from: ctx.accounts.owner_token_account.to_account_info(),
+                to: ctx.accounts.pda_token_account.to_account_info(),
+                authority: ctx.accounts.owner.to_account_info(),
+            }),
+        1
+    )?;
+
+    let vault = &mut ctx.accounts.fraction_vault;
+    vault.curator = ctx.accounts.owner.key();
+    vault.nft_mint = ctx.accounts.mint.key();
+    vault.fraction_mint = ctx.accounts.fraction_mint.key();
+    vault.fraction_supply = fraction_supply;
+    vault.reserve_price = reserve_price;
+    vault.state = FractionVaultState::Active;
+    vault.buyer = None;
+    vault.proceeds = 0;
+    vault.rent_payer = ctx.accounts.owner.key();
+    vault.escrow_payer = ctx.accounts.owner.key();
+    vault.bump = ctx.bumps.fraction_vault;
+
+    mint_fractions(
+        vault,
+        &ctx.accounts.fraction_mint,
+        &ctx.accounts.owner_fraction_account,
+        &ctx.accounts.token_program,
+        fraction_supply,
+    )?;
+
+    msg!("NFT fractionalized into {} fractions", fraction_supply);
+
+    let clock = Clock::get()?;
+    emit!(NftFractionalizedEvent {
+        version: EVENT_VERSION,
+        vault: vault.key(),
+        mint: vault.nft_mint,
+        fraction_mint: vault.fraction_mint,
+        curator: vault.curator,
+        fraction_supply,
+        reserve_price,
+        slot: clock.slot,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+pub fn start_buyout(
+    ctx: Context<StartBuyout>,
+) -> Result<()> {
+    msg!("Buying out fractionalized NFT...");
+    let vault = &ctx.accounts.fraction_vault;
+    require!(vault.state == FractionVaultState::Active, NftMarketplaceError::VaultNotActive);
+
+    // The reserve stays in the vault until fraction holders redeem it
+    system_program::transfer(
+        CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            system_program::Transfer {
+                from: ctx.accounts.buyer.to_account_info(),
+                to: ctx.accounts.fraction_vault.to_account_info(),
+            }
+        ),
+        vault.reserve_price,
+    )?;
+
+    let bump_seed = ctx.bumps.program_pda;
+    let program_pda_seeds : &[&[&[u8]]] = &[&[NFT_MARKET_PLACE_SEED.as_bytes(), &[bump_seed]]];
+
+    token::transfer(
+        CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            token::Transfer {
+                from: ctx.accounts.pda_token_account.to_account_info(),
+                to: ctx.accounts.buyer_token_account.to_account_info(),
+                authority: ctx.accounts.program_pda.to_account_info(),
+            })
+        .with_signer(program_pda_seeds),
+        1,
+    )?;
+
+    close_escrow_account(
+        &ctx.accounts.pda_token_account,
+        &ctx.accounts.escrow_payer.to_account_info(),
+        &ctx.accounts.program_pda,
+        bump_seed,
+        &ctx.accounts.token_program,
+    )?;
+
+    let vault = &mut ctx.accounts.fraction_vault;
+    vault.state = FractionVaultState::BoughtOut;
+    vault.buyer = Some(ctx.accounts.buyer.key());
+    vault.proceeds = vault.reserve_price;
+
+    msg!("NFT bought out, fractions can now be redeemed");
+
+    let clock = Clock::get()?;
+    emit!(BuyoutEvent {
+        version: EVENT_VERSION,
+        vault: vault.key(),
+        mint: vault.nft_mint,
+        buyer: ctx.accounts.buyer.key(),
+        price: vault.reserve_price,
+        slot: clock.slot,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+pub fn redeem(
+    ctx: Context<Redeem>,
+    amount: u64,
+) -> Result<()> {
+    msg!("Redeeming fractions...");
+    let vault = &ctx.accounts.fraction_vault;
+    require!(vault.state == FractionVaultState::BoughtOut, NftMarketplaceError::VaultNotBoughtOut);
+    require!(amount > 0, NftMarketplaceError::InvalidFractionAmount);
+
+    // Pay out of what is left, so rounding never strands lamports
+    // in the vault or lets the last holders come up short
+    let outstanding = ctx.accounts.fraction_mint.supply;
+    let payout = (vault.proceeds as u128 * amount as u128 / outstanding as u128) as u64;
+
+    token::burn(
+        CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            token::Burn {
+                mint: ctx.accounts.fraction_mint.to_account_info(),
+                from: ctx.accounts.holder_fraction_account.to_account_info(),
+                authority: ctx.accounts.holder.to_account_info(),
+            }),
+        amount,
+    )?;
+
+    ctx.accounts.fraction_vault.sub_lamports(payout)?;
+    ctx.accounts.holder.add_lamports(payout)?;
+
+    let vault = &mut ctx.accounts.fraction_vault;
+    vault.proceeds -= payout;
+
+    msg!("Redeemed {} fractions for {} lamports", amount, payout);
+
+    let clock = Clock::get()?;
+    emit!(FractionsRedeemedEvent {
+        version: EVENT_VERSION,
+        vault: vault.key(),
+        holder: ctx.accounts.holder.key(),
+        amount,
+        payout,
+        slot: clock.slot,
+        timestamp: clock.unix_timestamp,
+    });
+
+    // The last redemption frees the vault address, so the NFT can be
+    // fractionalized or pool-bought again
+    ctx.accounts.fraction_mint.reload()?;
+    if ctx.accounts.fraction_mint.supply == 0 {
+        ctx.accounts.fraction_vault.close(ctx.accounts.rent_payer.to_account_info())?;
+    }
+
+    Ok(())
+}
+
+// Mints fraction tokens with the vault PDA as mint authority
+pub(crate) fn mint_fractions<'info>(
+    vault: &Account<'info, FractionVault>,
+    fraction_mint: &Account<'info, Mint>,
+    to: &Account<'info, TokenAccount>,
+    token_program: &Program<'info, Token>,
+    amount: u64,
+) -> Result<()> {
+    let vault_seeds : &[&[&[u8]]] = &[&[
+        FRACTION_VAULT_SEED.as_bytes(),
+        vault.nft_mint.as_ref(),
+        &[vault.bump],
+    ]];
+
+    token::mint_to(
+        CpiContext::new(
+            token_program.to_account_info(),
+            token::MintTo {
+                mint: fraction_mint.to_account_info(),
+                to: to.to_account_info(),
+                authority: vault.to_account_info(),
+            })
+        .with_signer(vault_seeds),
+        amount,
+    )
+}
+
+#[derive(Accounts)]
+pub struct Fractionalize<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        constraint = owner_token_account.mint == mint.key(),
+        constraint = owner_token_account.owner == owner.key(),
+    )]
+    pub owner_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + FractionVault::MAX_SIZE,
+        seeds = [
+            FRACTION_VAULT_SEED.as_bytes(),
+            mint.key().as_ref(),
+        ],
+        bump,
+    )]
+    pub fraction_vault: Account<'info, FractionVault>,
+
+    // Generated by the caller. SPL mints cannot be closed, so a mint PDA of the
+    // NFT would outlive the vault and block fractionalizing the NFT again.
+    #[account(
+        init,
+        payer = owner,
+        mint::decimals = 0,
+        mint::authority = fraction_vault,
+    )]
+    pub fraction_mint: Account<'info, Mint>,
+
+    #[account(
+        init_if_needed,
+        payer = owner,
+        associated_token::mint = fraction_mint,
+        associated_token::authority = owner,
+    )]
+    pub owner_fraction_account: Account<'info, TokenAccount>,
+
+    #[account(
+        init_if_needed,
+        payer = owner,
+        associated_token::mint = mint,
+        associated_token::authority = program_pda,
+    )]
+    pub pda_token_account: Account<'info, TokenAccount>,
+
+    /// CHECK: Program Derived Address
+    #[account(
+        seeds = [NFT_MARKET_PLACE_SEED.as_bytes()],
+        bump,
+    )]
+    pub program_pda: UncheckedAccount<'info>,
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct StartBuyout<'info> {
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    pub mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [
+            FRACTION_VAULT_SEED.as_bytes(),
+            mint.key().as_ref(),
+        ],
+        bump = fraction_vault.bump,
+        has_one = escrow_payer,
+    )]
+    pub fraction_vault: Account<'info, FractionVault>,
+
+    /// CHECK: Receives the rent of the emptied escrow token account
+    #[account(mut)]
+    pub escrow_payer: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = program_pda,
+    )]
+    pub pda_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        associated_token::mint = mint,
+        associated_token::authority = buyer,
+    )]
+    pub buyer_token_account: Account<'info, TokenAccount>,
+
+    /// CHECK: Program Derived Address
+    #[account(
+        seeds = [NFT_MARKET_PLACE_SEED.as_bytes()],
+        bump,
+    )]
+    pub program_pda: UncheckedAccount<'info>,
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Redeem<'info> {
+    #[account(mut)]
+    pub holder: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            FRACTION_VAULT_SEED.as_bytes(),
+            fraction_vault.nft_mint.as_ref(),
+        ],
+        bump = fraction_vault.bump,
+        has_one = fraction_mint,
+        has_one = rent_payer,
+    )]
+    pub fraction_vault: Account<'info, FractionVault>,
+
+    /// CHECK: Receives the vault rent after the last redemption
+    #[account(mut)]
+    pub rent_payer: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub fraction_mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        associated_token::mint = fraction_mint,
+        associated_token::authority = holder,
+    )]
+    pub holder_fraction_account: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[event]
+pub struct NftFractionalizedEvent {
+    pub version: u8,
+    pub vault: Pubkey,
+    pub mint: Pubkey,
+    pub fraction_mint: Pubkey,
+    pub curator: Pubkey,
+    pub fraction_supply: u64,
+    pub reserve_price: u64,
+    pub slot: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct BuyoutEvent {
+    pub version: u8,
+    pub vault: Pubkey,
+    pub mint: Pubkey,
+    pub buyer: Pubkey,
+    pub price: u64,
+    pub slot: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct FractionsRedeemedEvent {
+    pub version: u8,
+    pub vault: Pubkey,
+    pub holder: Pubkey,
+    pub amount: u64,
+    pub payout: u64,
+    pub slot: u64,
+    pub timestamp: i64,
+}
@@ -0,0 +1,473 @@
+use {
+    anchor_lang::{
+        prelude::*, system_program,
+    },
+    anchor_spl::{
+        associated_token::AssociatedToken,
+        token::{self, Token, TokenAccount, Mint},
+        metadata::{Metadata, MetadataAccount},
+    },
+};
+
+use crate::state::*;
+use crate::errors::*;
+use crate::utils::*;
+
+pub fn start_installment_purchase(
+    ctx: Context<StartInstallmentPurchase>,
+    deposit: u64,
+    duration: i64,
+) -> Result<()> {
+    msg!("Starting installment purchase...");
+    check_buyer_attestation(
+        &ctx.accounts.marketplace,
+        &ctx.accounts.nft_listing_account,
+        ctx.accounts.buyer_attestation.as_ref(),
+        &ctx.accounts.buyer.key(),
+    )?;
+    // These flows lock in the lamport price, USD listings only sell through `buy_nft`
+    require!(
+        ctx.accounts.nft_listing_account.usd_price == 0,
+        NftMarketplaceError::UsdListingNotSupported
+    );
+    let price = ctx.accounts.nft_listing_account.price;
+
+    let min_deposit = (price as u128 * INSTALLMENT_MIN_DEPOSIT_BPS as u128 / MAX_BPS as u128) as u64;
+    // Paying it all upfront is a plain `buy_nft`, the plan needs a final installment to release the NFT
+    require!(deposit >= min_deposit && deposit < price, NftMarketplaceError::InvalidInstallmentDeposit);
+    require!(
+        duration > 0 && duration <= INSTALLMENT_MAX_DURATION,
+        NftMarketplaceError::InvalidInstallmentDuration
+    );
+
+    pay_seller(
+        &ctx.accounts.marketplace,
+        &ctx.accounts.buyer,
+        &ctx.accounts.seller,
+        &ctx.accounts.treasury,
+        &ctx.accounts.system_program,
+        deposit,
+    )?;
+
+    ctx.accounts.marketplace_stats.bump = ctx.bumps.marketplace_stats;
+    ctx.accounts.marketplace_stats.record_delisting();
+
+    // The listing account is closed by now, so the NFT can no longer be
+    // bought by anyone else while it sits in escrow for this buyer
+    let clock = Clock::get()?;
+    let plan = &mut ctx.accounts.installment_plan;
+    plan.mint = ctx.accounts.mint.key();
+    plan.seller = ctx.accounts.seller.key();
+    plan.buyer = ctx.accounts.buyer.key();
+    plan.price = price;
+    plan.paid = deposit;
+    plan.deadline = clock.unix_timestamp + duration;
+    plan.bump = ctx.bumps.installment_plan;
+
+    msg!("Listing locked to buyer until {}", plan.deadline);
+
+    emit_installment_event(plan, deposit, InstallmentStatus::Started)
+}
+
+pub fn pay_installment(
+    ctx: Context<PayInstallment>,
+    amount: u64,
+) -> Result<()> {
+    msg!("Paying installment...");
+    let plan = &ctx.accounts.installment_plan;
+    require!(Clock::get()?.unix_timestamp <= plan.deadline, NftMarketplaceError::InstallmentDeadlinePassed);
+
+    let amount = amount.min(plan.price - plan.paid);
+    require!(amount > 0, NftMarketplaceError::InvalidInstallmentDeposit);
+
+    pay_seller(
+        &ctx.accounts.marketplace,
+        &ctx.accounts.buyer,
+        &ctx.accounts.seller,
+        &ctx.accounts.treasury,
+        &ctx.accounts.system_program,
+        amount,
+    )?;
+
+    let plan = &mut ctx.accounts.installment_plan;
+    plan.paid += amount;
+
+    if plan.paid < plan.price {
+        msg!("Paid {} of {} lamports", plan.paid, plan.price);
+        return emit_installment_event(plan, amount, InstallmentStatus::Paid);
+    }
+
+    // Fully paid, release the NFT and close the plan
+    let bump_seed = ctx.bumps.program_pda;
+    let program_pda_seeds : &[&[&[u8]]] = &[&[NFT_MARKET_PLACE_SEED.as_bytes(), &[bump_seed]]];
+
+    token::transfer(
+        CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            token::Transfer {
+                from: ctx.accounts.pda_token_account.to_account_info(),
+                to: ctx.accounts.buyer_token_account.to_account_info(),
+                authority: ctx.accounts.program_pda.to_account_info(),
+            })
+        .with_signer(program_pda_seeds),
+        1,
+    )?;
+
+    close_escrow_account(
+        &ctx.accounts.pda_token_account,
+        &ctx.accounts.seller.to_account_info(),
+        &ctx.accounts.program_pda,
+        bump_seed,
+        &ctx.accounts.token_program,
+    )?;
+
+    // Only a fully paid plan counts as a sale
+    let price = ctx.accounts.installment_plan.price;
+    ctx.accounts.marketplace_stats.record_sale(price);
+    record_collection_sale(
+        ctx.program_id,
+        &ctx.accounts.metadata,
+        ctx.accounts.collection_stats.as_deref(),
+        price,
+        Clock::get()?.unix_timestamp,
+    )?;
+
+    ctx.accounts.installment_plan.close(ctx.accounts.buyer.to_account_info())?;
+
+    msg!("Final installment paid, NFT released to the buyer.");
+
+    emit_installment_event(&ctx.accounts.installment_plan, amount, InstallmentStatus::Completed)
+}
+
+pub fn forfeit_installment(
+    ctx: Context<ForfeitInstallment>,
+) -> Result<()> {
+    msg!("Forfeiting installment purchase...");
+    let plan = &ctx.accounts.installment_plan;
+    require!(Clock::get()?.unix_timestamp > plan.deadline, NftMarketplaceError::InstallmentDeadlineNotPassed);
+    require!(plan.paid < plan.price, NftMarketplaceError::InstallmentFullyPaid);
+
+    // Payments made so far stay with the seller, who gets the NFT back
+    let bump_seed = ctx.bumps.program_pda;
+    let program_pda_seeds : &[&[&[u8]]] = &[&[NFT_MARKET_PLACE_SEED.as_bytes(), &[bump_seed]]];
+
+    token::transfer(
+        CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            token::Transfer {
+                from: ctx.accounts.pda_token_account.to_account_info(),
+                to: ctx.accounts.seller_token_account.to_account_info(),
+                authority: ctx.accounts.program_pda.to_account_info(),
+            })
+        .with_signer(program_pda_seeds),
+        1,
+    )?;
+
+    close_escrow_account(
+        &ctx.accounts.pda_token_account,
+        &ctx.accounts.seller.to_account_info(),
+        &ctx.accounts.program_pda,
+        bump_seed,
+        &ctx.accounts.token_program,
+    )?;
+
+    msg!("NFT returned to the seller.");
+
+    emit_installment_event(plan, 0, InstallmentStatus::Forfeited)
+}
+
+// Forwards a payment to the seller, minus the marketplace fee
+fn pay_seller<'info>(
+    marketplace: &Account<'info, Marketplace>,
+    buyer: &Signer<'info>,
+    seller: &UncheckedAccount<'info>,
+    treasury: &UncheckedAccount<'info>,
+    system_program: &Program<'info, System>,
+    amount: u64,
+) -> Result<()> {
+    let (seller_proceeds, marketplace_fee, _) = marketplace.split_price(amount, false);
+
+    system_program::transfer(
+        CpiContext::new(
+            system_program.to_account_info(),
+            system_program::Transfer {
+                from: buyer.to_account_info(),
+                to: seller.to_account_info(),
+            }
+        ),
+        seller_proceeds,
+    )?;
+
+    if marketplace_fee > 0 {
+        system_program::transfer(
+            CpiContext::new(
+                system_program.to_account_info(),
+                system_program::Transfer {
+                    from: buyer.to_account_info(),
+                    to: treasury.to_account_info(),
+                }
+            ),
+            marketplace_fee,
+        )?;
+    }
+
+    Ok(())
+}
+
+fn emit_installment_event(plan: &Account<InstallmentPlan>, amount: u64, status: InstallmentStatus) -> Result<()> {
+    let clock = Clock::get()?;
+    emit!(InstallmentEvent {
+        version: EVENT_VERSION,
+        mint: plan.mint,
+        seller: plan.seller,
+        buyer: plan.buyer,
+        price: plan.price,
+        amount,
+        paid: plan.paid,
+        deadline: plan.deadline,
+        status,
+        slot: clock.slot,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct StartInstallmentPurchase<'info> {
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    /// CHECK: The seller's account
+    #[account(
+        mut,
+        address = nft_listing_account.owner,
+    )]
+    pub seller: UncheckedAccount<'info>,
+
+    pub mint: Account<'info, Mint>,
+
+    #[account(
+        seeds = [MARKETPLACE_CONFIG_SEED.as_bytes()],
+        bump = marketplace.bump,
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+
+    /// CHECK: Only receives the platform fee, must match the marketplace treasury
+    #[account(
+        mut,
+        address = marketplace.treasury,
+    )]
+    pub treasury: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            LISTED_NFT_SEED.as_bytes(),
+            mint.key().as_ref()
+        ],
+        bump = nft_listing_account.bump,
+        close = seller,
+    )]
+    pub nft_listing_account: Account<'info, ListedNft>,
+
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        space = 8 + MarketplaceStats::MAX_SIZE,
+        seeds = [MARKETPLACE_STATS_SEED.as_bytes()],
+        bump,
+    )]
+    pub marketplace_stats: Account<'info, MarketplaceStats>,
+
+    #[account(
+        seeds = [
+            BUYER_ATTESTATION_SEED.as_bytes(),
+            buyer.key().as_ref(),
+        ],
+        bump = buyer_attestation.bump,
+    )]
+    pub buyer_attestation: Option<Account<'info, BuyerAttestation>>,
+
+    #[account(
+        init,
+        payer = buyer,
+        space = 8 + InstallmentPlan::MAX_SIZE,
+        seeds = [
+            INSTALLMENT_SEED.as_bytes(),
+            mint.key().as_ref()
+        ],
+        bump,
+    )]
+    pub installment_plan: Account<'info, InstallmentPlan>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct PayInstallment<'info> {
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    /// CHECK: The seller's account
+    #[account(
+        mut,
+        address = installment_plan.seller,
+    )]
+    pub seller: UncheckedAccount<'info>,
+
+    pub mint: Account<'info, Mint>,
+
+    #[account(
+        seeds = [MARKETPLACE_CONFIG_SEED.as_bytes()],
+        bump = marketplace.bump,
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+
+    /// CHECK: Only receives the platform fee, must match the marketplace treasury
+    #[account(
+        mut,
+        address = marketplace.treasury,
+    )]
+    pub treasury: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            INSTALLMENT_SEED.as_bytes(),
+            mint.key().as_ref()
+        ],
+        bump = installment_plan.bump,
+        has_one = buyer @ NftMarketplaceError::Unauthorized,
+    )]
+    pub installment_plan: Account<'info, InstallmentPlan>,
+
+    #[account(
+        mut,
+        seeds = [MARKETPLACE_STATS_SEED.as_bytes()],
+        bump = marketplace_stats.bump,
+    )]
+    pub marketplace_stats: Account<'info, MarketplaceStats>,
+
+    /// CHECK: Stats PDA of the NFT's verified collection, checked and written by the handler
+    #[account(mut)]
+    pub collection_stats: Option<UncheckedAccount<'info>>,
+
+    #[account(
+        seeds = [b"metadata".as_ref(), Metadata::id().as_ref(), mint.key().as_ref()],
+        bump,
+        seeds::program = Metadata::id(),
+    )]
+    pub metadata: Account<'info, MetadataAccount>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = program_pda,
+    )]
+    pub pda_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        associated_token::mint = mint,
+        associated_token::authority = buyer,
+    )]
+    pub buyer_token_account: Account<'info, TokenAccount>,
+
+    /// CHECK: Program Derived Address
+    #[account(
+        seeds = [NFT_MARKET_PLACE_SEED.as_bytes()],
+        bump,
+    )]
+    pub program_pda: UncheckedAccount<'info>,
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ForfeitInstallment<'info> {
+    // Anyone can crank the forfeiture once the deadline has passed
+    #[account(mut)]
+    pub caller: Signer<'info>,
+
+    /// CHECK: The seller's account, gets the escrow rent back
+    #[account(
+        mut,
+        address = installment_plan.seller,
+    )]
+    pub seller: UncheckedAccount<'info>,
+
+    /// CHECK: The buyer's account, gets the plan rent back
+    #[account(
+        mut,
+        address = installment_plan.buyer,
+    )]
+    pub buyer: UncheckedAccount<'info>,
+
+    pub mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [
+            INSTALLMENT_SEED.as_bytes(),
+            mint.key().as_ref()
+        ],
+        bump = installment_plan.bump,
+        close = buyer,
+    )]
+    pub installment_plan: Account<'info, InstallmentPlan>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = program_pda,
+    )]
+    pub pda_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        init_if_needed,
+        payer = caller,
+        associated_token::mint = mint,
+        associated_token::authority = seller,
+    )]
+    pub seller_token_account: Account<'info, TokenAccount>,
+
+    /// CHECK: Program Derived Address
+    #[account(
+        seeds = [NFT_MARKET_PLACE_SEED.as_bytes()],
+        bump,
+    )]
+    pub program_pda: UncheckedAccount<'info>,
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(AnchorDeserialize, AnchorSerialize, Clone)]
+pub enum InstallmentStatus {
+    Started,
+    Paid,
+    Completed,
+    Forfeited,
+}
+
+#[event]
+pub struct InstallmentEvent {
+    pub version: u8,
+    pub mint: Pubkey,
+    pub seller: Pubkey,
+    pub buyer: Pubkey,
+    pub price: u64,
+    // Amount paid in this instruction
+    pub amount: u64,
+    // Total paid so far
+    pub paid: u64,
+    pub deadline: i64,
+    pub status: InstallmentStatus,
+    pub slot: u64,
+    pub timestamp: i64,
+}
@@ -9,12 +9,48 @@ use {
 };
 
 use crate::state::*;
+use crate::errors::*;
+use crate::utils::*;
 
 pub fn list_nft(
     ctx: Context<ListNft>,
     price: u64,
+    requires_attestation: bool,
+) -> Result<()> {
+    create_listing(ctx, price, 0, requires_attestation)
+}
+
+// The lamport price of the listing is only known at purchase time, when
+// `buy_nft` converts the USD price with the feed pinned in the config then
+pub fn list_nft_usd(
+    ctx: Context<ListNft>,
+    usd_price: u64,
+    requires_attestation: bool,
+) -> Result<()> {
+    let marketplace = ctx.accounts.marketplace
+        .as_ref()
+        .ok_or(NftMarketplaceError::InvalidPriceFeed)?;
+    let price_feed = ctx.accounts.price_feed
+        .as_ref()
+        .ok_or(NftMarketplaceError::InvalidPriceFeed)?;
+    // Fails early on a wrong or dead feed instead of at the first purchase
+    usd_to_lamports(marketplace, price_feed, usd_price, Clock::get()?.unix_timestamp)?;
+
+    create_listing(ctx, 0, usd_price, requires_attestation)
+}
+
+fn create_listing(
+    ctx: Context<ListNft>,
+    price: u64,
+    usd_price: u64,
+    requires_attestation: bool,
 ) -> Result<()> {
     msg!("Listing NFT...");
+    // Soulbound NFTs stay frozen in their holder's account forever
+    require!(
+        !ctx.accounts.owner_token_account.is_frozen(),
+        NftMarketplaceError::NftNonTransferable
+    );
 
     // Transfer the NFT from the owner to the program PDA
     // Execute the token transfer (transfer 1 token, which represents the NFT)
@@ -35,13 +71,29 @@ pub fn list_nft(
     nft_listing_account.owner = ctx.accounts.owner.key();
     nft_listing_account.mint = ctx.accounts.mint.key();
     nft_listing_account.price = price;
+    nft_listing_account.requires_attestation = requires_attestation;
+    nft_listing_account.usd_price = usd_price;
+
+    let clock = Clock::get()?;
+    nft_listing_account.version = ListedNft::CURRENT_VERSION;
+    nft_listing_account.bump = ctx.bumps.nft_listing_account;
+    nft_listing_account.created_at = clock.unix_timestamp;
+
+    let marketplace_stats = &mut ctx.accounts.marketplace_stats;
+    marketplace_stats.bump = ctx.bumps.marketplace_stats;
+    marketplace_stats.record_listing();
 
     msg!("NFT listed successfully!");
     emit!(NftListedEvent {
+        version: EVENT_VERSION,
         nft: ctx.accounts.mint.key(),
         owner: ctx.accounts.owner.key(),
         marketplace: *ctx.program_id,
-        price: price,
+        price,
+        usd_price,
+        requires_attestation,
+        slot: clock.slot,
+        timestamp: clock.unix_timestamp,
     });
 
     Ok(())
@@ -76,6 +128,25 @@ pub struct ListNft<'info> {
     )]
     pub nft_listing_account: Account<'info, ListedNft>,
 
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = 8 + MarketplaceStats::MAX_SIZE,
+        seeds = [MARKETPLACE_STATS_SEED.as_bytes()],
+        bump,
+    )]
+    pub marketplace_stats: Account<'info, MarketplaceStats>,
+
+    // Pins the SOL/USD price account, only for `list_nft_usd`
+    #[account(
+        seeds = [MARKETPLACE_CONFIG_SEED.as_bytes()],
+        bump = marketplace.bump,
+    )]
+    pub marketplace: Option<Account<'info, Marketplace>>,
+
+    /// CHECK: SOL/USD price account, only for `list_nft_usd` which validates it
+    pub price_feed: Option<UncheckedAccount<'info>>,
+
     /// CHECK: PDA token account
     #[account(
         init_if_needed,
@@ -100,8 +171,14 @@ pub struct ListNft<'info> {
 
 #[event]
 pub struct NftListedEvent {
+    pub version: u8,
     pub nft: Pubkey,
     pub owner: Pubkey,
     pub marketplace: Pubkey,
     pub price: u64,
+    // Set instead of `price` for listings pegged to USD
+    pub usd_price: u64,
+    pub requires_attestation: bool,
+    pub slot: u64,
+    pub timestamp: i64,
 }
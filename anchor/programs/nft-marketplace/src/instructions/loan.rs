@@ -0,0 +1,448 @@
+use {
+    anchor_lang::{
+        prelude::*, system_program,
+    },
+    anchor_spl::{
+        associated_token::AssociatedToken,
+        token::{self, Token, TokenAccount, Mint},
+        metadata::{Metadata, MetadataAccount},
+    },
+};
+
+use crate::state::*;
+use crate::errors::*;
+use crate::utils::*;
+
+pub fn create_loan_offer(
+    ctx: Context<CreateLoanOffer>,
+    offer_id: u64,
+    principal: u64,
+    interest_bps: u16,
+    duration: i64,
+    collection: Option<Pubkey>,
+    nft_mint: Option<Pubkey>,
+) -> Result<()> {
+    msg!("Creating loan offer...");
+    require!(principal > 0, NftMarketplaceError::InvalidLoanTerms);
+    require!(duration > 0, NftMarketplaceError::InvalidLoanTerms);
+    // An open offer would lend against any token the borrower can mint
+    require!(
+        collection.is_some() || nft_mint.is_some(),
+        NftMarketplaceError::LoanCollateralUnrestricted
+    );
+
+    // The principal sits in the loan PDA until a borrower takes it
+    system_program::transfer(
+        CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            system_program::Transfer {
+                from: ctx.accounts.lender.to_account_info(),
+                to: ctx.accounts.loan.to_account_info(),
+            }
+        ),
+        principal,
+    )?;
+
+    let loan = &mut ctx.accounts.loan;
+    loan.lender = ctx.accounts.lender.key();
+    loan.offer_id = offer_id;
+    loan.principal = principal;
+    loan.interest_bps = interest_bps;
+    loan.duration = duration;
+    loan.collection = collection;
+    loan.borrower = None;
+    loan.nft_mint = nft_mint;
+    loan.deadline = 0;
+    loan.state = LoanState::Offered;
+    loan.bump = ctx.bumps.loan;
+
+    msg!("Loan offer {} created", loan.key());
+
+    emit_loan_event(loan, LoanState::Offered)
+}
+
+pub fn cancel_loan_offer(
+    ctx: Context<CancelLoanOffer>,
+) -> Result<()> {
+    msg!("Cancelling loan offer...");
+    require!(ctx.accounts.loan.state == LoanState::Offered, NftMarketplaceError::InvalidLoanState);
+
+    // Closing the account returns the escrowed principal with the rent
+    emit_loan_event(&ctx.accounts.loan, LoanState::Cancelled)
+}
+
+pub fn take_loan(
+    ctx: Context<TakeLoan>,
+) -> Result<()> {
+    msg!("Taking loan...");
+    let loan = &ctx.accounts.loan;
+    require!(loan.state == LoanState::Offered, NftMarketplaceError::InvalidLoanState);
+    require!(is_nft(&ctx.accounts.mint), NftMarketplaceError::NotAnNft);
+
+    if let Some(nft_mint) = loan.nft_mint {
+        require_keys_eq!(nft_mint, ctx.accounts.mint.key(), NftMarketplaceError::WrongLoanCollateral);
+    }
+    if let Some(collection) = loan.collection {
+        let metadata = ctx.accounts.metadata
+            .as_ref()
+            .ok_or(NftMarketplaceError::NotInCollection)?;
+        require!(
+            is_verified_collection_member(metadata, &collection),
+            NftMarketplaceError::NotInCollection
+        );
+    }
+
+    // Lock the collateral in the program PDA escrow
+    token::transfer(
+        CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            token::Transfer {
+                from: ctx.accounts.borrower_token_account.to_account_info(),
+                to: ctx.accounts.pda_token_account.to_account_info(),
+                authority: ctx.accounts.borrower.to_account_info(),
+            }),
+        1
+    )?;
+
+    let principal = loan.principal;
+    ctx.accounts.loan.sub_lamports(principal)?;
+    ctx.accounts.borrower.add_lamports(principal)?;
+
+    let clock = Clock::get()?;
+    let loan = &mut ctx.accounts.loan;
+    loan.borrower = Some(ctx.accounts.borrower.key());
+    loan.nft_mint = Some(ctx.accounts.mint.key());
+    loan.deadline = clock.unix_timestamp + loan.duration;
+    loan.state = LoanState::Active;
+
+    msg!("Loan active until {}", loan.deadline);
+
+    emit_loan_event(loan, LoanState::Active)
+}
+
+pub fn repay_loan(
+    ctx: Context<RepayLoan>,
+) -> Result<()> {
+    msg!("Repaying loan...");
+    let loan = &ctx.accounts.loan;
+    require!(loan.state == LoanState::Active, NftMarketplaceError::InvalidLoanState);
+    require!(Clock::get()?.unix_timestamp <= loan.deadline, NftMarketplaceError::LoanExpired);
+
+    let amount = loan.repayment_amount();
+    system_program::transfer(
+        CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            system_program::Transfer {
+                from: ctx.accounts.borrower.to_account_info(),
+                to: ctx.accounts.lender.to_account_info(),
+            }
+        ),
+        amount,
+    )?;
+    msg!("Repaid {} lamports to the lender.", amount);
+
+    release_collateral(
+        &ctx.accounts.pda_token_account,
+        &ctx.accounts.borrower_token_account,
+        &ctx.accounts.borrower.to_account_info(),
+        &ctx.accounts.program_pda,
+        ctx.bumps.program_pda,
+        &ctx.accounts.token_program,
+    )?;
+
+    emit_loan_event(&ctx.accounts.loan, LoanState::Repaid)
+}
+
+pub fn foreclose_loan(
+    ctx: Context<ForecloseLoan>,
+) -> Result<()> {
+    msg!("Foreclosing loan...");
+    let loan = &ctx.accounts.loan;
+    require!(loan.state == LoanState::Active, NftMarketplaceError::InvalidLoanState);
+    require!(Clock::get()?.unix_timestamp > loan.deadline, NftMarketplaceError::LoanNotExpired);
+
+    release_collateral(
+        &ctx.accounts.pda_token_account,
+        &ctx.accounts.lender_token_account,
+        &ctx.accounts.borrower.to_account_info(),
+        &ctx.accounts.program_pda,
+        ctx.bumps.program_pda,
+        &ctx.accounts.token_program,
+    )?;
+
+    emit_loan_event(&ctx.accounts.loan, LoanState::Foreclosed)
+}
+
+// Sends the collateral out of the escrow and refunds the escrow account
+// rent to the borrower, who paid it when taking the loan
+fn release_collateral<'info>(
+    pda_token_account: &Account<'info, TokenAccount>,
+    to: &Account<'info, TokenAccount>,
+    borrower: &AccountInfo<'info>,
+    program_pda: &UncheckedAccount<'info>,
+    program_pda_bump: u8,
+    token_program: &Program<'info, Token>,
+) -> Result<()> {
+    let program_pda_seeds : &[&[&[u8]]] = &[&[NFT_MARKET_PLACE_SEED.as_bytes(), &[program_pda_bump]]];
+
+    token::transfer(
+        CpiContext::new(
+            token_program.to_account_info(),
+            token::Transfer {
+                from: pda_token_account.to_account_info(),
+                to: to.to_account_info(),
+                authority: program_pda.to_account_info(),
+            })
+        .with_signer(program_pda_seeds),
+        1,
+    )?;
+
+    close_escrow_account(
+        pda_token_account,
+        borrower,
+        program_pda,
+        program_pda_bump,
+        token_program,
+    )?;
+
+    msg!("Collateral released.");
+
+    Ok(())
+}
+
+fn emit_loan_event(loan: &Account<Loan>, state: LoanState) -> Result<()> {
+    let clock = Clock::get()?;
+    emit!(LoanEvent {
+        version: EVENT_VERSION,
+        loan: loan.key(),
+        lender: loan.lender,
+        borrower: loan.borrower,
+        nft_mint: loan.nft_mint,
+        principal: loan.principal,
+        repayment: loan.repayment_amount(),
+        deadline: loan.deadline,
+        state,
+        slot: clock.slot,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(offer_id: u64)]
+pub struct CreateLoanOffer<'info> {
+    #[account(mut)]
+    pub lender: Signer<'info>,
+
+    #[account(
+        init,
+        payer = lender,
+        space = 8 + Loan::MAX_SIZE,
+        seeds = [
+            LOAN_SEED.as_bytes(),
+            lender.key().as_ref(),
+            &offer_id.to_le_bytes(),
+        ],
+        bump,
+    )]
+    pub loan: Account<'info, Loan>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelLoanOffer<'info> {
+    #[account(mut)]
+    pub lender: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            LOAN_SEED.as_bytes(),
+            lender.key().as_ref(),
+            &loan.offer_id.to_le_bytes(),
+        ],
+        bump = loan.bump,
+        has_one = lender,
+        close = lender,
+    )]
+    pub loan: Account<'info, Loan>,
+}
+
+#[derive(Accounts)]
+pub struct TakeLoan<'info> {
+    #[account(mut)]
+    pub borrower: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            LOAN_SEED.as_bytes(),
+            loan.lender.as_ref(),
+            &loan.offer_id.to_le_bytes(),
+        ],
+        bump = loan.bump,
+    )]
+    pub loan: Account<'info, Loan>,
+
+    pub mint: Account<'info, Mint>,
+
+    // Only needed when the offer is restricted to a collection
+    #[account(
+        seeds = [b"metadata".as_ref(), Metadata::id().as_ref(), mint.key().as_ref()],
+        bump,
+        seeds::program = Metadata::id(),
+    )]
+    pub metadata: Option<Account<'info, MetadataAccount>>,
+
+    #[account(
+        mut,
+        constraint = borrower_token_account.mint == mint.key(),
+        constraint = borrower_token_account.owner == borrower.key(),
+    )]
+    pub borrower_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        init_if_needed,
+        payer = borrower,
+        associated_token::mint = mint,
+        associated_token::authority = program_pda,
+    )]
+    pub pda_token_account: Account<'info, TokenAccount>,
+
+    /// CHECK: Program Derived Address
+    #[account(
+        seeds = [NFT_MARKET_PLACE_SEED.as_bytes()],
+        bump,
+    )]
+    pub program_pda: UncheckedAccount<'info>,
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RepayLoan<'info> {
+    #[account(mut)]
+    pub borrower: Signer<'info>,
+
+    /// CHECK: The lender's account, receives the repayment and the loan rent
+    #[account(mut)]
+    pub lender: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            LOAN_SEED.as_bytes(),
+            lender.key().as_ref(),
+            &loan.offer_id.to_le_bytes(),
+        ],
+        bump = loan.bump,
+        has_one = lender,
+        constraint = loan.borrower == Some(borrower.key()) @ NftMarketplaceError::Unauthorized,
+        constraint = loan.nft_mint == Some(mint.key()),
+        close = lender,
+    )]
+    pub loan: Account<'info, Loan>,
+
+    pub mint: Account<'info, Mint>,
+
+    #[account(
+        init_if_needed,
+        payer = borrower,
+        associated_token::mint = mint,
+        associated_token::authority = borrower,
+    )]
+    pub borrower_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = program_pda,
+    )]
+    pub pda_token_account: Account<'info, TokenAccount>,
+
+    /// CHECK: Program Derived Address
+    #[account(
+        seeds = [NFT_MARKET_PLACE_SEED.as_bytes()],
+        bump,
+    )]
+    pub program_pda: UncheckedAccount<'info>,
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ForecloseLoan<'info> {
+    #[account(mut)]
+    pub lender: Signer<'info>,
+
+    /// CHECK: The borrower's account, gets the escrow account rent back
+    #[account(
+        mut,
+        constraint = loan.borrower == Some(borrower.key()) @ NftMarketplaceError::Unauthorized,
+    )]
+    pub borrower: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            LOAN_SEED.as_bytes(),
+            lender.key().as_ref(),
+            &loan.offer_id.to_le_bytes(),
+        ],
+        bump = loan.bump,
+        has_one = lender,
+        constraint = loan.nft_mint == Some(mint.key()),
+        close = lender,
+    )]
+    pub loan: Account<'info, Loan>,
+
+    pub mint: Account<'info, Mint>,
+
+    #[account(
+        init_if_needed,
+        payer = lender,
+        associated_token::mint = mint,
+        associated_token::authority = lender,
+    )]
+    pub lender_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = program_pda,
+    )]
+    pub pda_token_account: Account<'info, TokenAccount>,
+
+    /// CHECK: Program Derived Address
+    #[account(
+        seeds = [NFT_MARKET_PLACE_SEED.as_bytes()],
+        bump,
+    )]
+    pub program_pda: UncheckedAccount<'info>,
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event]
+pub struct LoanEvent {
+    pub version: u8,
+    pub loan: Pubkey,
+    pub lender: Pubkey,
+    pub borrower: Option<Pubkey>,
+    pub nft_mint: Option<Pubkey>,
+    pub principal: u64,
+    // Principal plus interest owed to the lender
+    pub repayment: u64,
+    pub deadline: i64,
+    pub state: LoanState,
+    pub slot: u64,
+    pub timestamp: i64,
+}
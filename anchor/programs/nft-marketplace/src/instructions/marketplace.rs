@@ -0,0 +1,158 @@
+use anchor_lang::prelude::*;
+
+use crate::state::*;
+use crate::errors::*;
+use crate::program::NftMarketplace;
+
+pub fn initialize_marketplace(
+    ctx: Context<InitializeMarketplace>,
+    treasury: Pubkey,
+    fee_bps: u16,
+    referral_bps: u16,
+) -> Result<()> {
+    msg!("Initializing marketplace config...");
+    require!(fee_bps <= MAX_BPS, NftMarketplaceError::InvalidBasisPoints);
+    require!(referral_bps <= MAX_BPS, NftMarketplaceError::InvalidBasisPoints);
+
+    let marketplace = &mut ctx.accounts.marketplace;
+    marketplace.authority = ctx.accounts.authority.key();
+    marketplace.treasury = treasury;
+    marketplace.fee_bps = fee_bps;
+    marketplace.referral_bps = referral_bps;
+    marketplace.arbiter = ctx.accounts.authority.key();
+    marketplace.dispute_window = DEFAULT_DISPUTE_WINDOW;
+    marketplace.compliance_authority = ctx.accounts.authority.key();
+    marketplace.price_feed = Pubkey::default();
+    marketplace.max_price_age = DEFAULT_MAX_PRICE_AGE;
+    marketplace.bump = ctx.bumps.marketplace;
+
+    emit_config_event(marketplace)
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct UpdateMarketplaceParams {
+    pub new_authority: Option<Pubkey>,
+    pub treasury: Option<Pubkey>,
+    pub fee_bps: Option<u16>,
+    pub referral_bps: Option<u16>,
+    pub arbiter: Option<Pubkey>,
+    pub dispute_window: Option<i64>,
+    pub compliance_authority: Option<Pubkey>,
+    pub price_feed: Option<Pubkey>,
+    pub max_price_age: Option<i64>,
+}
+
+pub fn update_marketplace(
+    ctx: Context<UpdateMarketplace>,
+    params: UpdateMarketplaceParams,
+) -> Result<()> {
+    msg!("Updating marketplace config...");
+    let marketplace = &mut ctx.accounts.marketplace;
+
+    if let Some(fee_bps) = params.fee_bps {
+        require!(fee_bps <= MAX_BPS, NftMarketplaceError::InvalidBasisPoints);
+        marketplace.fee_bps = fee_bps;
+    }
+    if let Some(referral_bps) = params.referral_bps {
+        require!(referral_bps <= MAX_BPS, NftMarketplaceError::InvalidBasisPoints);
+        marketplace.referral_bps = referral_bps;
+    }
+    if let Some(dispute_window) = params.dispute_window {
+        require!(dispute_window >= 0, NftMarketplaceError::InvalidDisputeWindow);
+        marketplace.dispute_window = dispute_window;
+    }
+    if let Some(max_price_age) = params.max_price_age {
+        require!(max_price_age > 0, NftMarketplaceError::InvalidMaxPriceAge);
+        marketplace.max_price_age = max_price_age;
+    }
+    if let Some(treasury) = params.treasury {
+        marketplace.treasury = treasury;
+    }
+    if let Some(arbiter) = params.arbiter {
+        marketplace.arbiter = arbiter;
+    }
+    if let Some(compliance_authority) = params.compliance_authority {
+        marketplace.compliance_authority = compliance_authority;
+    }
+    if let Some(price_feed) = params.price_feed {
+        marketplace.price_feed = price_feed;
+    }
+    if let Some(new_authority) = params.new_authority {
+        marketplace.authority = new_authority;
+    }
+
+    emit_config_event(marketplace)
+}
+
+fn emit_config_event(marketplace: &Account<Marketplace>) -> Result<()> {
+    let clock = Clock::get()?;
+    emit!(MarketplaceConfigEvent {
+        version: EVENT_VERSION,
+        authority: marketplace.authority,
+        treasury: marketplace.treasury,
+        fee_bps: marketplace.fee_bps,
+        referral_bps: marketplace.referral_bps,
+        arbiter: marketplace.arbiter,
+        dispute_window: marketplace.dispute_window,
+        compliance_authority: marketplace.compliance_authority,
+        price_feed: marketplace.price_feed,
+        max_price_age: marketplace.max_price_age,
+        slot: clock.slot,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct InitializeMarketplace<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + Marketplace::MAX_SIZE,
+        seeds = [MARKETPLACE_CONFIG_SEED.as_bytes()],
+        bump,
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+
+    // Only the upgrade authority of the program may create the config
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()))]
+    pub program: Program<'info, NftMarketplace>,
+
+    #[account(constraint = program_data.upgrade_authority_address == Some(authority.key()) @ NftMarketplaceError::Unauthorized)]
+    pub program_data: Account<'info, ProgramData>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateMarketplace<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [MARKETPLACE_CONFIG_SEED.as_bytes()],
+        bump = marketplace.bump,
+        has_one = authority @ NftMarketplaceError::Unauthorized,
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+}
+
+#[event]
+pub struct MarketplaceConfigEvent {
+    pub version: u8,
+    pub authority: Pubkey,
+    pub treasury: Pubkey,
+    pub fee_bps: u16,
+    pub referral_bps: u16,
+    pub arbiter: Pubkey,
+    pub dispute_window: i64,
+    pub compliance_authority: Pubkey,
+    pub price_feed: Pubkey,
+    pub max_price_age: i64,
+    pub slot: u64,
+    pub timestamp: i64,
+}
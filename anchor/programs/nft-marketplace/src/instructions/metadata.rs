@@ -3,12 +3,16 @@ use {
         prelude::*,
     },
     anchor_spl::{
-        token::Token,
+        token::{self, Token, TokenAccount},
         metadata::{
             create_metadata_accounts_v3,
             CreateMetadataAccountsV3,
             create_master_edition_v3,
             CreateMasterEditionV3,
+            freeze_delegated_account,
+            FreezeDelegatedAccount,
+            sign_metadata,
+            SignMetadata,
             Metadata,
             mpl_token_metadata::types::{
                 DataV2, Creator
@@ -17,75 +21,240 @@ use {
     }
 };
 
+use crate::state::*;
+use crate::errors::*;
 
-pub fn create_metadata(
-    ctx: Context<CreateMetadata>,
+// Metaplex allows at most 5 creators, one of them is the mint authority
+pub const MAX_ADDITIONAL_CREATORS: usize = 4;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct CreatorShare {
+    pub address: Pubkey,
+    pub share: u8,
+}
+
+pub fn create_metadata<'info>(
+    ctx: Context<'_, '_, 'info, 'info, CreateMetadata<'info>>,
     title: String,
     uri: String,
+    non_transferable: bool,
+    additional_creators: Vec<CreatorShare>,
 ) -> Result<()> {
-    msg!("Creating metadata account...");
-    msg!("Metadata account address: {}", &ctx.accounts.metadata.to_account_info().key());
-    create_metadata_accounts_v3(
-        CpiContext::new(
-            ctx.accounts.metadata.to_account_info(),
-            CreateMetadataAccountsV3{
-                metadata: ctx.accounts.metadata.to_account_info(),
-                mint: ctx.accounts.mint.to_account_info(),
-                payer: ctx.accounts.mint_authority.to_account_info(),
-                mint_authority: ctx.accounts.mint_authority.to_account_info(),
-                update_authority: ctx.accounts.mint_authority.to_account_info(),
-                system_program: ctx.accounts.system_program.to_account_info(),
-                rent: ctx.accounts.rent.to_account_info(),
-            }
-        ),
+    require!(
+        additional_creators.len() <= MAX_ADDITIONAL_CREATORS,
+        NftMarketplaceError::TooManyCreators
+    );
+    let mut creators = vec![];
+    let mut remaining_share: u8 = 100;
+    for creator in &additional_creators {
+        require!(
+            creator.address != ctx.accounts.mint_authority.key()
+                && !creators.iter().any(|c: &Creator| c.address == creator.address),
+            NftMarketplaceError::DuplicateCreator
+        );
+        remaining_share = remaining_share
+            .checked_sub(creator.share)
+            .ok_or(NftMarketplaceError::InvalidCreatorShares)?;
+        // Metaplex only lets a creator verify themselves, see below
+        creators.push(Creator {
+            address: creator.address,
+            verified: false,
+            share: creator.share,
+        });
+    }
+    creators.insert(0, Creator {
+        address: ctx.accounts.mint_authority.key(),
+        verified: true,
+        share: remaining_share,
+    });
+
+    let mint_authority = ctx.accounts.mint_authority.to_account_info();
+    create_metadata_and_master_edition(
+        MetadataCpiAccounts {
+            metadata: &ctx.accounts.metadata,
+            master_edition: &ctx.accounts.master_edition,
+            mint: &ctx.accounts.mint,
+            mint_authority: &ctx.accounts.mint_authority,
+            update_authority: &mint_authority,
+            token_metadata_program: &ctx.accounts.token_metadata_program,
+            system_program: &ctx.accounts.system_program,
+            token_program: &ctx.accounts.token_program,
+            rent: &ctx.accounts.rent,
+        },
         DataV2 {
-            name: title,
-            symbol: "REAL-EST".to_string(),
-            uri: uri,
+            name: title.clone(),
+            symbol: NFT_SYMBOL.to_string(),
+            uri: uri.clone(),
             seller_fee_basis_points: 0,
-            creators: Some(vec![
-                Creator {
-                    address: ctx.accounts.mint_authority.key(),
-                    verified: true,
-                    share: 100,
-                },
-            ]),
+            creators: Some(creators),
             collection: None,
             uses: None,
         },
-        true,
-        true,
-        None,
+        &[],
     )?;
 
-    msg!("Creating master edition metadata account...");
-    msg!("Master edition metadata account address: {}", &ctx.accounts.master_edition.to_account_info().key());
-    create_master_edition_v3(
+    // Additional creators co-signing this transaction are verified right
+    // away, the others can do so later through `sign_as_creator`
+    for co_signer in ctx.remaining_accounts.iter().filter(|account| account.is_signer) {
+        if additional_creators.iter().any(|c| c.address == co_signer.key()) {
+            sign_metadata(
+                CpiContext::new(
+                    ctx.accounts.token_metadata_program.to_account_info(),
+                    SignMetadata {
+                        creator: co_signer.clone(),
+                        metadata: ctx.accounts.metadata.to_account_info(),
+                    }
+                ),
+            )?;
+            msg!("Creator {} verified", co_signer.key());
+        }
+    }
+
+    if non_transferable {
+        // Soulbound: the program PDA becomes the delegate and freezes the
+        // holder's account through the master edition. Nothing in the program
+        // ever thaws it again, so the token can no longer be moved.
+        token::approve(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token::Approve {
+                    to: ctx.accounts.token_account.to_account_info(),
+                    delegate: ctx.accounts.program_pda.to_account_info(),
+                    authority: ctx.accounts.mint_authority.to_account_info(),
+                }),
+            1,
+        )?;
+
+        let program_pda_seeds : &[&[&[u8]]] = &[&[NFT_MARKET_PLACE_SEED.as_bytes(), &[ctx.bumps.program_pda]]];
+        freeze_delegated_account(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_metadata_program.to_account_info(),
+                FreezeDelegatedAccount {
+                    metadata: ctx.accounts.metadata.to_account_info(),
+                    delegate: ctx.accounts.program_pda.to_account_info(),
+                    token_account: ctx.accounts.token_account.to_account_info(),
+                    edition: ctx.accounts.master_edition.to_account_info(),
+                    mint: ctx.accounts.mint.to_account_info(),
+                    token_program: ctx.accounts.token_program.to_account_info(),
+                },
+                program_pda_seeds,
+            ),
+        )?;
+
+        msg!("NFT made non-transferable");
+    }
+
+    msg!("Minted NFT successfully");
+
+    let clock = Clock::get()?;
+    emit!(MetadataCreatedEvent{
+        version: EVENT_VERSION,
+        mint: ctx.accounts.mint.key(),
+        creator: ctx.accounts.mint_authority.key(),
+        name: title,
+        uri,
+        seller_fee_basis_points: 0,
+        slot: clock.slot,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+pub fn sign_as_creator(
+    ctx: Context<SignAsCreator>,
+) -> Result<()> {
+    msg!("Verifying creator {}...", ctx.accounts.creator.key());
+
+    // Metaplex rejects creators that are not listed in the metadata
+    sign_metadata(
         CpiContext::new(
             ctx.accounts.token_metadata_program.to_account_info(),
-            CreateMasterEditionV3{
-                edition: ctx.accounts.master_edition.to_account_info(),
-                payer: ctx.accounts.mint_authority.to_account_info(),
-                mint: ctx.accounts.mint.to_account_info(),
+            SignMetadata {
+                creator: ctx.accounts.creator.to_account_info(),
                 metadata: ctx.accounts.metadata.to_account_info(),
-                mint_authority: ctx.accounts.mint_authority.to_account_info(),
-                update_authority: ctx.accounts.mint_authority.to_account_info(),
-                system_program: ctx.accounts.system_program.to_account_info(),
-                token_program: ctx.accounts.token_program.to_account_info(),
-                rent: ctx.accounts.rent.to_account_info(),
-            }),
-            Some(1),
+            }
+        ),
     )?;
 
-    msg!("Minted NFT successfully");
-
-    emit!(MetadataCreatedEvent{
+    let clock = Clock::get()?;
+    emit!(CreatorVerifiedEvent {
+        version: EVENT_VERSION,
         mint: ctx.accounts.mint.key(),
+        creator: ctx.accounts.creator.key(),
+        slot: clock.slot,
+        timestamp: clock.unix_timestamp,
     });
 
     Ok(())
 }
 
+pub(crate) struct MetadataCpiAccounts<'a, 'info> {
+    pub metadata: &'a UncheckedAccount<'info>,
+    pub master_edition: &'a UncheckedAccount<'info>,
+    pub mint: &'a Signer<'info>,
+    // Pays for both accounts as well
+    pub mint_authority: &'a Signer<'info>,
+    pub update_authority: &'a AccountInfo<'info>,
+    pub token_metadata_program: &'a Program<'info, Metadata>,
+    pub system_program: &'a Program<'info, System>,
+    pub token_program: &'a Program<'info, Token>,
+    pub rent: &'a Sysvar<'info, Rent>,
+}
+
+// Creates the Metaplex metadata and a master edition with a max supply of 1.
+// `signer_seeds` are only needed when the update authority is a PDA.
+pub(crate) fn create_metadata_and_master_edition(
+    accounts: MetadataCpiAccounts,
+    data: DataV2,
+    signer_seeds: &[&[&[u8]]],
+) -> Result<()> {
+    msg!("Creating metadata account...");
+    msg!("Metadata account address: {}", &accounts.metadata.key());
+    create_metadata_accounts_v3(
+        CpiContext::new_with_signer(
+            accounts.token_metadata_program.to_account_info(),
+            CreateMetadataAccountsV3{
+                metadata: accounts.metadata.to_account_info(),
+                mint: accounts.mint.to_account_info(),
+                payer: accounts.mint_authority.to_account_info(),
+                mint_authority: accounts.mint_authority.to_account_info(),
+                update_authority: accounts.update_authority.clone(),
+                system_program: accounts.system_program.to_account_info(),
+                rent: accounts.rent.to_account_info(),
+            },
+            signer_seeds,
+        ),
+        data,
+        true,
+        true,
+        None,
+    )?;
+
+    msg!("Creating master edition metadata account...");
+    msg!("Master edition metadata account address: {}", &accounts.master_edition.key());
+    create_master_edition_v3(
+        CpiContext::new_with_signer(
+            accounts.token_metadata_program.to_account_info(),
+            CreateMasterEditionV3{
+                edition: accounts.master_edition.to_account_info(),
+                payer: accounts.mint_authority.to_account_info(),
+                mint: accounts.mint.to_account_info(),
+                metadata: accounts.metadata.to_account_info(),
+                mint_authority: accounts.mint_authority.to_account_info(),
+                update_authority: accounts.update_authority.clone(),
+                system_program: accounts.system_program.to_account_info(),
+                token_program: accounts.token_program.to_account_info(),
+                rent: accounts.rent.to_account_info(),
+            },
+            signer_seeds,
+        ),
+        Some(1),
+    )?;
+
+    Ok(())
+}
+
 #[derive(Accounts)]
 pub struct CreateMetadata<'info> {
     #[account(mut)]
@@ -112,6 +281,21 @@ pub struct CreateMetadata<'info> {
     /// CHECK:
     pub master_edition: UncheckedAccount<'info>,
 
+    // Holder account of the freshly minted NFT, frozen for soulbound NFTs
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = mint_authority,
+    )]
+    pub token_account: Account<'info, TokenAccount>,
+
+    /// CHECK: Program Derived Address
+    #[account(
+        seeds = [NFT_MARKET_PLACE_SEED.as_bytes()],
+        bump,
+    )]
+    pub program_pda: UncheckedAccount<'info>,
+
     pub token_metadata_program: Program<'info, Metadata>,
 
     pub system_program: Program<'info, System>,
@@ -119,7 +303,42 @@ pub struct CreateMetadata<'info> {
     pub rent: Sysvar<'info, Rent>,
 }
 
+#[derive(Accounts)]
+pub struct SignAsCreator<'info> {
+    pub creator: Signer<'info>,
+
+    /// CHECK: Only used to derive the metadata address
+    pub mint: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"metadata".as_ref(), token_metadata_program.key().as_ref(), mint.key().as_ref()],
+        bump,
+        seeds::program = token_metadata_program.key()
+    )]
+    /// CHECK: Metaplex will do the check
+    pub metadata: UncheckedAccount<'info>,
+
+    pub token_metadata_program: Program<'info, Metadata>,
+}
+
 #[event]
 pub struct MetadataCreatedEvent {
+    pub version: u8,
+    pub mint: Pubkey,
+    pub creator: Pubkey,
+    pub name: String,
+    pub uri: String,
+    pub seller_fee_basis_points: u16,
+    pub slot: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct CreatorVerifiedEvent {
+    pub version: u8,
     pub mint: Pubkey,
+    pub creator: Pubkey,
+    pub slot: u64,
+    pub timestamp: i64,
 }
@@ -0,0 +1,117 @@
+use {
+    anchor_lang::{
+        prelude::*, system_program, Discriminator,
+    },
+    anchor_spl::token::Mint,
+};
+
+use crate::state::*;
+use crate::errors::*;
+
+// The fields every unversioned listing starts with
+#[derive(AnchorDeserialize)]
+struct LegacyListedNft {
+    mint: Pubkey,
+    owner: Pubkey,
+    price: u64,
+}
+
+// Grows an unversioned listing to the current layout. Anyone can crank it,
+// the payer covers the extra rent, which goes to the seller once the listing closes.
+pub fn migrate_listing(
+    ctx: Context<MigrateListing>,
+) -> Result<()> {
+    msg!("Migrating listing...");
+    let listing_info = ctx.accounts.nft_listing_account.to_account_info();
+    require_keys_eq!(*listing_info.owner, crate::ID, NftMarketplaceError::InvalidListingAccount);
+
+    let clock = Clock::get()?;
+    let migrated = {
+        let data = listing_info.try_borrow_data()?;
+        require!(
+            data.starts_with(&ListedNft::DISCRIMINATOR),
+            NftMarketplaceError::InvalidListingAccount
+        );
+        let body = &data[8..];
+        require!(
+            ListedNft::LEGACY_SIZES.contains(&body.len()),
+            NftMarketplaceError::ListingAlreadyMigrated
+        );
+
+        let legacy = LegacyListedNft::deserialize(&mut &body[..ListedNft::LEGACY_SIZES[0]])?;
+        ListedNft {
+            mint: legacy.mint,
+            owner: legacy.owner,
+            price: legacy.price,
+            // Missing in the oldest layout, those listings were never gated
+            requires_attestation: body.get(ListedNft::LEGACY_SIZES[0]) == Some(&1),
+            version: ListedNft::CURRENT_VERSION,
+            bump: ctx.bumps.nft_listing_account,
+            created_at: clock.unix_timestamp,
+            usd_price: 0,
+            reserved: [0u8; 56],
+        }
+    };
+
+    let new_len = 8 + ListedNft::MAX_SIZE;
+    let rent_due = Rent::get()?.minimum_balance(new_len).saturating_sub(listing_info.lamports());
+    if rent_due > 0 {
+        system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.payer.to_account_info(),
+                    to: listing_info.clone(),
+                }
+            ),
+            rent_due,
+        )?;
+    }
+
+    listing_info.realloc(new_len, true)?;
+    migrated.try_serialize(&mut &mut listing_info.try_borrow_mut_data()?[..])?;
+
+    msg!("Listing migrated to version {}", migrated.version);
+
+    emit!(ListingMigratedEvent {
+        version: EVENT_VERSION,
+        listing: listing_info.key(),
+        mint: migrated.mint,
+        layout_version: migrated.version,
+        slot: clock.slot,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct MigrateListing<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub mint: Account<'info, Mint>,
+
+    /// CHECK: Legacy listings don't deserialize as `ListedNft`, the handler checks owner, discriminator and size
+    #[account(
+        mut,
+        seeds = [
+            LISTED_NFT_SEED.as_bytes(),
+            mint.key().as_ref()
+        ],
+        bump,
+    )]
+    pub nft_listing_account: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[event]
+pub struct ListingMigratedEvent {
+    pub version: u8,
+    pub listing: Pubkey,
+    pub mint: Pubkey,
+    pub layout_version: u8,
+    pub slot: u64,
+    pub timestamp: i64,
+}
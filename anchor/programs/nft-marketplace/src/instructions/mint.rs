@@ -1,89 +1,122 @@
 use {
     anchor_lang::{
-        prelude::*, solana_program::native_token::LAMPORTS_PER_SOL, system_program
+        prelude::*, system_program
     }, anchor_spl::{
         associated_token::{
             self, AssociatedToken
         },
         token::{
-            self, Token,
+            self, Token, Mint,
         }
     }
 };
 
+use crate::state::*;
 
 pub fn mint(
     ctx: Context<MintNft>,
 ) -> Result<()> {
+    let mint_rent = create_mint_and_mint_one(
+        &ctx.accounts.mint_authority,
+        &ctx.accounts.mint,
+        &ctx.accounts.token_account,
+        &ctx.accounts.rent,
+        &ctx.accounts.system_program,
+        &ctx.accounts.token_program,
+        &ctx.accounts.associated_token_program,
+    )?;
+
+    let clock = Clock::get()?;
+    emit!(MintSucessfulEvent {
+        version: EVENT_VERSION,
+        mint: ctx.accounts.mint.key(),
+        owner: ctx.accounts.mint_authority.key(),
+        marketplace: *ctx.program_id,
+        mint_rent,
+        slot: clock.slot,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+// Creates the mint account, its associated token account for `mint_authority`
+// and mints the single token into it. Returns the lamports paid for the mint.
+pub(crate) fn create_mint_and_mint_one<'info>(
+    mint_authority: &Signer<'info>,
+    mint: &Signer<'info>,
+    token_account: &UncheckedAccount<'info>,
+    rent: &Sysvar<'info, Rent>,
+    system_program: &Program<'info, System>,
+    token_program: &Program<'info, Token>,
+    associated_token_program: &Program<'info, AssociatedToken>,
+) -> Result<u64> {
 
     msg!("Creating mint account...");
-    msg!("Mint: {}", &ctx.accounts.mint.key());
+    msg!("Mint: {}", &mint.key());
+    // Fund the mint with exactly the rent-exempt minimum, anything above
+    // it can never be withdrawn from a token program owned account
+    let mint_rent = rent.minimum_balance(Mint::LEN);
     system_program::create_account(
         CpiContext::new(
-            ctx.accounts.system_program.to_account_info(),
+            system_program.to_account_info(),
             system_program::CreateAccount {
-                from: ctx.accounts.mint_authority.to_account_info(),
-                to: ctx.accounts.mint.to_account_info(),
+                from: mint_authority.to_account_info(),
+                to: mint.to_account_info(),
             },
         ),
-        LAMPORTS_PER_SOL,
-        82,
-        &ctx.accounts.token_program.key(),
+        mint_rent,
+        Mint::LEN as u64,
+        &token_program.key(),
     )?;
 
     msg!("Initializing mint account...");
-    msg!("Mint: {}", &ctx.accounts.mint.key());
+    msg!("Mint: {}", &mint.key());
     token::initialize_mint(
         CpiContext::new(
-            ctx.accounts.token_program.to_account_info(),
+            token_program.to_account_info(),
             token::InitializeMint {
-                mint: ctx.accounts.mint.to_account_info(),
-                rent: ctx.accounts.rent.to_account_info(),
+                mint: mint.to_account_info(),
+                rent: rent.to_account_info(),
             },
         ),
         0,
-        &ctx.accounts.mint_authority.key(),
-        Some(&ctx.accounts.mint_authority.key()),
+        &mint_authority.key(),
+        Some(&mint_authority.key()),
     )?;
 
     msg!("Creating token account...");
-    msg!("Token Address: {}", &ctx.accounts.token_account.key());    
+    msg!("Token Address: {}", &token_account.key());
     associated_token::create(
         CpiContext::new(
-            ctx.accounts.associated_token_program.to_account_info(),
+            associated_token_program.to_account_info(),
             associated_token::Create {
-                payer: ctx.accounts.mint_authority.to_account_info(),
-                associated_token: ctx.accounts.token_account.to_account_info(),
-                authority: ctx.accounts.mint_authority.to_account_info(),
-                mint: ctx.accounts.mint.to_account_info(),
-                system_program: ctx.accounts.system_program.to_account_info(),
-                token_program: ctx.accounts.token_program.to_account_info(),
+                payer: mint_authority.to_account_info(),
+                associated_token: token_account.to_account_info(),
+                authority: mint_authority.to_account_info(),
+                mint: mint.to_account_info(),
+                system_program: system_program.to_account_info(),
+                token_program: token_program.to_account_info(),
             },
         ),
     )?;
 
     msg!("Minting token to token account...");
-    msg!("Mint: {}", &ctx.accounts.mint.to_account_info().key());
-    msg!("Token Address: {}", &ctx.accounts.token_account.key());
+    msg!("Mint: {}", &mint.key());
+    msg!("Token Address: {}", &token_account.key());
     token::mint_to(
         CpiContext::new(
-            ctx.accounts.token_program.to_account_info(),
+            token_program.to_account_info(),
             token::MintTo {
-                mint: ctx.accounts.mint.to_account_info(),
-                to: ctx.accounts.token_account.to_account_info(),
-                authority: ctx.accounts.mint_authority.to_account_info(),
+                mint: mint.to_account_info(),
+                to: token_account.to_account_info(),
+                authority: mint_authority.to_account_info(),
             },
         ),
         1,
     )?;
 
-    emit!(MintSucessfulEvent {
-        mint: ctx.accounts.mint.key(),
-        owner: ctx.accounts.mint_authority.key(),
-        marketplace: *ctx.program_id,
-    });
-
-    Ok(())
+    Ok(mint_rent)
 }
 
 
@@ -108,7 +141,12 @@ pub struct MintNft<'info> {
 
 #[event]
 pub struct MintSucessfulEvent {
+    pub version: u8,
     pub mint: Pubkey,
     pub owner: Pubkey,
     pub marketplace: Pubkey,
+    // Lamports the minter paid to fund the mint account
+    pub mint_rent: u64,
+    pub slot: u64,
+    pub timestamp: i64,
 }
@@ -9,3 +9,57 @@ pub mod list;
 
 pub use buy::*;
 pub mod buy;
+
+pub use update_metadata::*;
+pub mod update_metadata;
+
+pub use burn::*;
+pub mod burn;
+
+pub use drop::*;
+pub mod drop;
+
+pub use marketplace::*;
+pub mod marketplace;
+
+pub use stake::*;
+pub mod stake;
+
+pub use rent::*;
+pub mod rent;
+
+pub use fraction::*;
+pub mod fraction;
+
+pub use loan::*;
+pub mod loan;
+
+pub use installment::*;
+pub mod installment;
+
+pub use settlement::*;
+pub mod settlement;
+
+pub use bundle::*;
+pub mod bundle;
+
+pub use collection_bid::*;
+pub mod collection_bid;
+
+pub use review::*;
+pub mod review;
+
+pub use attestation::*;
+pub mod attestation;
+
+pub use raffle::*;
+pub mod raffle;
+
+pub use pool::*;
+pub mod pool;
+
+pub use migrate::*;
+pub mod migrate;
+
+pub use stats::*;
+pub mod stats;
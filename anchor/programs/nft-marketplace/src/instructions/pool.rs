@@ -0,0 +1,581 @@
+use {
+    anchor_lang::{
+        prelude::*, system_program,
+    },
+    anchor_spl::{
+        associated_token::AssociatedToken,
+        token::{self, Token, TokenAccount, Mint},
+        metadata::{Metadata, MetadataAccount},
+    },
+};
+
+use crate::state::*;
+use crate::errors::*;
+use crate::utils::*;
+use super::fraction::mint_fractions;
+
+pub fn create_purchase_pool(
+    ctx: Context<CreatePurchasePool>,
+    deadline: i64,
+    reserve_price: u64,
+) -> Result<()> {
+    msg!("Creating purchase pool...");
+    require!(
+        deadline > Clock::get()?.unix_timestamp,
+        NftMarketplaceError::InvalidPoolDeadline
+    );
+    // The pool PDA cannot hold a buyer attestation
+    require!(
+        !ctx.accounts.nft_listing_account.requires_attestation,
+        NftMarketplaceError::AttestationRequired
+    );
+    require!(
+        ctx.accounts.nft_listing_account.usd_price == 0,
+        NftMarketplaceError::UsdListingNotSupported
+    );
+    // A buyout right after the purchase must at least pay the contributors back
+    require!(
+        reserve_price >= ctx.accounts.nft_listing_account.price,
+        NftMarketplaceError::PoolReserveBelowTarget
+    );
+
+    let pool = &mut ctx.accounts.purchase_pool;
+    pool.organizer = ctx.accounts.organizer.key();
+    pool.mint = ctx.accounts.mint.key();
+    pool.target = ctx.accounts.nft_listing_account.price;
+    pool.raised = 0;
+    pool.deadline = deadline;
+    pool.reserve_price = reserve_price;
+    pool.executed = false;
+    pool.bump = ctx.bumps.purchase_pool;
+
+    msg!("Pool raising {} lamports until {}", pool.target, deadline);
+
+    emit_pool_event(pool, ctx.accounts.organizer.key(), 0, PoolStatus::Created)
+}
+
+pub fn contribute_to_pool(
+    ctx: Context<ContributeToPool>,
+    amount: u64,
+) -> Result<()> {
+    msg!("Contributing to purchase pool...");
+    let pool = &ctx.accounts.purchase_pool;
+    require!(
+        !pool.executed && Clock::get()?.unix_timestamp <= pool.deadline,
+        NftMarketplaceError::PoolClosed
+    );
+    require!(
+        amount > 0 && amount <= pool.target - pool.raised,
+        NftMarketplaceError::PoolTargetExceeded
+    );
+
+    system_program::transfer(
+        CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            system_program::Transfer {
+                from: ctx.accounts.contributor.to_account_info(),
+                to: ctx.accounts.purchase_pool.to_account_info(),
+            }
+        ),
+        amount,
+    )?;
+
+    let contribution = &mut ctx.accounts.contribution;
+    contribution.pool = ctx.accounts.purchase_pool.key();
+    contribution.contributor = ctx.accounts.contributor.key();
+    contribution.amount += amount;
+    contribution.bump = ctx.bumps.contribution;
+
+    ctx.accounts.purchase_pool.raised += amount;
+
+    emit_pool_event(&ctx.accounts.purchase_pool, ctx.accounts.contributor.key(), amount, PoolStatus::Contributed)
+}
+
+// Buys the listed NFT with the pooled lamports and locks it in a fraction
+// vault. The pool receives one fraction per contributed lamport, which the
+// contributors claim afterwards.
+pub fn execute_pool_purchase(
+    ctx: Context<ExecutePoolPurchase>,
+) -> Result<()> {
+    msg!("Executing pool purchase...");
+    let pool = &ctx.accounts.purchase_pool;
+    require!(!pool.executed, NftMarketplaceError::PoolAlreadyExecuted);
+    require!(pool.raised == pool.target, NftMarketplaceError::PoolNotFunded);
+    require!(
+        ctx.accounts.nft_listing_account.price == pool.target,
+        NftMarketplaceError::ListingPriceChanged
+    );
+
+    let price = pool.target;
+    let (seller_proceeds, marketplace_fee, _) = ctx.accounts.marketplace.split_price(price, false);
+
+    ctx.accounts.purchase_pool.sub_lamports(price)?;
+    ctx.accounts.seller.add_lamports(seller_proceeds)?;
+    ctx.accounts.treasury.add_lamports(marketplace_fee)?;
+
+    // The NFT already sits in the program PDA escrow since it was listed,
+    // which is exactly where a fraction vault keeps it
+    let vault = &mut ctx.accounts.fraction_vault;
+    vault.curator = ctx.accounts.purchase_pool.key();
+    vault.nft_mint = ctx.accounts.mint.key();
+    vault.fraction_mint = ctx.accounts.fraction_mint.key();
+    vault.fraction_supply = price;
+    vault.reserve_price = ctx.accounts.purchase_pool.reserve_price;
+    vault.state = FractionVaultState::Active;
+    vault.buyer = None;
+    vault.proceeds = 0;
+    vault.rent_payer = ctx.accounts.payer.key();
+    // The seller opened the escrow account when listing
+    vault.escrow_payer = ctx.accounts.seller.key();
+    vault.bump = ctx.bumps.fraction_vault;
+
+    mint_fractions(
+        vault,
+        &ctx.accounts.fraction_mint,
+        &ctx.accounts.pool_fraction_account,
+        &ctx.accounts.token_program,
+        price,
+    )?;
+
+    ctx.accounts.marketplace_stats.bump = ctx.bumps.marketplace_stats;
+    ctx.accounts.marketplace_stats.record_delisting();
+    ctx.accounts.marketplace_stats.record_sale(price);
+    record_collection_sale(
+        ctx.program_id,
+        &ctx.accounts.metadata,
+        ctx.accounts.collection_stats.as_deref(),
+        price,
+        Clock::get()?.unix_timestamp,
+    )?;
+
+    ctx.accounts.purchase_pool.executed = true;
+
+    msg!("Pool bought the NFT for {} lamports", price);
+
+    emit_pool_event(&ctx.accounts.purchase_pool, ctx.accounts.seller.key(), price, PoolStatus::Executed)
+}
+
+pub fn claim_pool_fractions(
+    ctx: Context<ClaimPoolFractions>,
+) -> Result<()> {
+    msg!("Claiming pool fractions...");
+    let pool = &ctx.accounts.purchase_pool;
+    require!(pool.executed, NftMarketplaceError::PoolNotExecuted);
+
+    let pool_seeds : &[&[&[u8]]] = &[&[
+        PURCHASE_POOL_SEED.as_bytes(),
+        pool.mint.as_ref(),
+        pool.organizer.as_ref(),
+        &[pool.bump],
+    ]];
+
+    let amount = ctx.accounts.contribution.amount;
+    token::transfer(
+        CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            token::Transfer {
+                from: ctx.accounts.pool_fraction_account.to_account_info(),
+                to: ctx.accounts.contributor_fraction_account.to_account_info(),
+                authority: ctx.accounts.purchase_pool.to_account_info(),
+            })
+        .with_signer(pool_seeds),
+        amount,
+    )?;
+
+    msg!("Claimed {} fractions", amount);
+
+    emit_pool_event(pool, ctx.accounts.contributor.key(), amount, PoolStatus::Claimed)?;
+
+    // The last claim empties the pool, hand its rent back to the organizer
+    ctx.accounts.pool_fraction_account.reload()?;
+    if ctx.accounts.pool_fraction_account.amount == 0 {
+        token::close_account(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token::CloseAccount {
+                    account: ctx.accounts.pool_fraction_account.to_account_info(),
+                    destination: ctx.accounts.organizer.to_account_info(),
+                    authority: ctx.accounts.purchase_pool.to_account_info(),
+                })
+            .with_signer(pool_seeds),
+        )?;
+        ctx.accounts.purchase_pool.close(ctx.accounts.organizer.to_account_info())?;
+    }
+
+    Ok(())
+}
+
+// Refunds a contribution once the deadline passed without a purchase
+pub fn withdraw_from_pool(
+    ctx: Context<WithdrawFromPool>,
+) -> Result<()> {
+    msg!("Withdrawing from purchase pool...");
+    let pool = &ctx.accounts.purchase_pool;
+    require!(!pool.executed, NftMarketplaceError::PoolAlreadyExecuted);
+    require!(
+        Clock::get()?.unix_timestamp > pool.deadline,
+        NftMarketplaceError::PoolDeadlineNotPassed
+    );
+
+    let amount = ctx.accounts.contribution.amount;
+    ctx.accounts.purchase_pool.sub_lamports(amount)?;
+    ctx.accounts.contributor.add_lamports(amount)?;
+    ctx.accounts.purchase_pool.raised -= amount;
+
+    emit_pool_event(&ctx.accounts.purchase_pool, ctx.accounts.contributor.key(), amount, PoolStatus::Withdrawn)?;
+
+    // Once every contribution is refunded the pool has nothing left to track
+    if ctx.accounts.purchase_pool.raised == 0 {
+        ctx.accounts.purchase_pool.close(ctx.accounts.organizer.to_account_info())?;
+    }
+
+    Ok(())
+}
+
+// Lets the organizer drop a pool nobody contributed to
+pub fn close_purchase_pool(
+    ctx: Context<ClosePurchasePool>,
+) -> Result<()> {
+    msg!("Closing purchase pool...");
+    let pool = &ctx.accounts.purchase_pool;
+    require!(!pool.executed, NftMarketplaceError::PoolAlreadyExecuted);
+    require!(pool.raised == 0, NftMarketplaceError::PoolHasContributions);
+
+    emit_pool_event(pool, ctx.accounts.organizer.key(), 0, PoolStatus::Closed)
+}
+
+fn emit_pool_event(pool: &Account<PurchasePool>, account: Pubkey, amount: u64, status: PoolStatus) -> Result<()> {
+    let clock = Clock::get()?;
+    emit!(PurchasePoolEvent {
+        version: EVENT_VERSION,
+        pool: pool.key(),
+        mint: pool.mint,
+        account,
+        amount,
+        raised: pool.raised,
+        target: pool.target,
+        status,
+        slot: clock.slot,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct CreatePurchasePool<'info> {
+    #[account(mut)]
+    pub organizer: Signer<'info>,
+
+    pub mint: Account<'info, Mint>,
+
+    #[account(
+        seeds = [
+            LISTED_NFT_SEED.as_bytes(),
+            mint.key().as_ref()
+        ],
+        bump = nft_listing_account.bump,
+    )]
+    pub nft_listing_account: Account<'info, ListedNft>,
+
+    #[account(
+        init,
+        payer = organizer,
+        space = 8 + PurchasePool::MAX_SIZE,
+        seeds = [
+            PURCHASE_POOL_SEED.as_bytes(),
+            mint.key().as_ref(),
+            organizer.key().as_ref(),
+        ],
+        bump,
+    )]
+    pub purchase_pool: Account<'info, PurchasePool>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ContributeToPool<'info> {
+    #[account(mut)]
+    pub contributor: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            PURCHASE_POOL_SEED.as_bytes(),
+            purchase_pool.mint.as_ref(),
+            purchase_pool.organizer.as_ref(),
+        ],
+        bump = purchase_pool.bump,
+    )]
+    pub purchase_pool: Account<'info, PurchasePool>,
+
+    #[account(
+        init_if_needed,
+        payer = contributor,
+        space = 8 + PoolContribution::MAX_SIZE,
+        seeds = [
+            POOL_CONTRIBUTION_SEED.as_bytes(),
+            purchase_pool.key().as_ref(),
+            contributor.key().as_ref(),
+        ],
+        bump,
+    )]
+    pub contribution: Account<'info, PoolContribution>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ExecutePoolPurchase<'info> {
+    // Anyone can execute a fully funded pool and pays for the vault accounts
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// CHECK: The seller's account, must be the owner of the listing
+    #[account(
+        mut,
+        address = nft_listing_account.owner,
+    )]
+    pub seller: UncheckedAccount<'info>,
+
+    pub mint: Account<'info, Mint>,
+
+    #[account(
+        seeds = [MARKETPLACE_CONFIG_SEED.as_bytes()],
+        bump = marketplace.bump,
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+
+    /// CHECK: Only receives the platform fee, must match the marketplace treasury
+    #[account(
+        mut,
+        address = marketplace.treasury,
+    )]
+    pub treasury: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            LISTED_NFT_SEED.as_bytes(),
+            mint.key().as_ref()
+        ],
+        bump = nft_listing_account.bump,
+        close = seller,
+    )]
+    pub nft_listing_account: Account<'info, ListedNft>,
+
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = 8 + MarketplaceStats::MAX_SIZE,
+        seeds = [MARKETPLACE_STATS_SEED.as_bytes()],
+        bump,
+    )]
+    pub marketplace_stats: Account<'info, MarketplaceStats>,
+
+    /// CHECK: Stats PDA of the NFT's verified collection, checked and written by the handler
+    #[account(mut)]
+    pub collection_stats: Option<UncheckedAccount<'info>>,
+
+    #[account(
+        seeds = [b"metadata".as_ref(), Metadata::id().as_ref(), mint.key().as_ref()],
+        bump,
+        seeds::program = Metadata::id(),
+    )]
+    pub metadata: Account<'info, MetadataAccount>,
+
+    #[account(
+        mut,
+        seeds = [
+            PURCHASE_POOL_SEED.as_bytes(),
+            mint.key().as_ref(),
+            purchase_pool.organizer.as_ref(),
+        ],
+        bump = purchase_pool.bump,
+    )]
+    pub purchase_pool: Account<'info, PurchasePool>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + FractionVault::MAX_SIZE,
+        seeds = [
+            FRACTION_VAULT_SEED.as_bytes(),
+            mint.key().as_ref(),
+        ],
+        bump,
+    )]
+    pub fraction_vault: Account<'info, FractionVault>,
+
+    // A fresh keypair, a mint can never be closed so an address derived
+    // from the NFT would block every later vault for it
+    #[account(
+        init,
+        payer = payer,
+        mint::decimals = 0,
+        mint::authority = fraction_vault,
+    )]
+    pub fraction_mint: Account<'info, Mint>,
+
+    #[account(
+        init,
+        payer = payer,
+        associated_token::mint = fraction_mint,
+        associated_token::authority = purchase_pool,
+    )]
+    pub pool_fraction_account: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimPoolFractions<'info> {
+    #[account(mut)]
+    pub contributor: Signer<'info>,
+
+    /// CHECK: Receives the pool rent after the last claim
+    #[account(
+        mut,
+        address = purchase_pool.organizer,
+    )]
+    pub organizer: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            PURCHASE_POOL_SEED.as_bytes(),
+            purchase_pool.mint.as_ref(),
+            purchase_pool.organizer.as_ref(),
+        ],
+        bump = purchase_pool.bump,
+    )]
+    pub purchase_pool: Account<'info, PurchasePool>,
+
+    #[account(
+        mut,
+        seeds = [
+            POOL_CONTRIBUTION_SEED.as_bytes(),
+            purchase_pool.key().as_ref(),
+            contributor.key().as_ref(),
+        ],
+        bump = contribution.bump,
+        close = contributor,
+    )]
+    pub contribution: Account<'info, PoolContribution>,
+
+    #[account(
+        seeds = [
+            FRACTION_VAULT_SEED.as_bytes(),
+            purchase_pool.mint.as_ref(),
+        ],
+        bump = fraction_vault.bump,
+        has_one = fraction_mint,
+        constraint = fraction_vault.curator == purchase_pool.key(),
+    )]
+    pub fraction_vault: Account<'info, FractionVault>,
+
+    pub fraction_mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        associated_token::mint = fraction_mint,
+        associated_token::authority = purchase_pool,
+    )]
+    pub pool_fraction_account: Account<'info, TokenAccount>,
+
+    #[account(
+        init_if_needed,
+        payer = contributor,
+        associated_token::mint = fraction_mint,
+        associated_token::authority = contributor,
+    )]
+    pub contributor_fraction_account: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawFromPool<'info> {
+    #[account(mut)]
+    pub contributor: Signer<'info>,
+
+    /// CHECK: Receives the pool rent after the last withdrawal
+    #[account(
+        mut,
+        address = purchase_pool.organizer,
+    )]
+    pub organizer: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            PURCHASE_POOL_SEED.as_bytes(),
+            purchase_pool.mint.as_ref(),
+            purchase_pool.organizer.as_ref(),
+        ],
+        bump = purchase_pool.bump,
+    )]
+    pub purchase_pool: Account<'info, PurchasePool>,
+
+    #[account(
+        mut,
+        seeds = [
+            POOL_CONTRIBUTION_SEED.as_bytes(),
+            purchase_pool.key().as_ref(),
+            contributor.key().as_ref(),
+        ],
+        bump = contribution.bump,
+        close = contributor,
+    )]
+    pub contribution: Account<'info, PoolContribution>,
+}
+
+#[derive(Accounts)]
+pub struct ClosePurchasePool<'info> {
+    #[account(mut)]
+    pub organizer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            PURCHASE_POOL_SEED.as_bytes(),
+            purchase_pool.mint.as_ref(),
+            organizer.key().as_ref(),
+        ],
+        bump = purchase_pool.bump,
+        has_one = organizer,
+        close = organizer,
+    )]
+    pub purchase_pool: Account<'info, PurchasePool>,
+}
+
+#[derive(AnchorDeserialize, AnchorSerialize, Clone)]
+pub enum PoolStatus {
+    Created,
+    Contributed,
+    Executed,
+    Claimed,
+    Withdrawn,
+    Closed,
+}
+
+#[event]
+pub struct PurchasePoolEvent {
+    pub version: u8,
+    pub pool: Pubkey,
+    pub mint: Pubkey,
+    // Organizer, contributor or seller, depending on the status
+    pub account: Pubkey,
+    pub amount: u64,
+    pub raised: u64,
+    pub target: u64,
+    pub status: PoolStatus,
+    pub slot: u64,
+    pub timestamp: i64,
+}
@@ -0,0 +1,623 @@
+use {
+    anchor_lang::{
+        prelude::*, system_program,
+        solana_program::{keccak, sysvar::slot_hashes},
+    },
+    anchor_spl::{
+        associated_token::AssociatedToken,
+        token::{self, Token, TokenAccount, Mint},
+        metadata::{Metadata, MetadataAccount},
+    },
+};
+
+use crate::state::*;
+use crate::errors::*;
+use crate::utils::*;
+
+pub fn create_raffle(
+    ctx: Context<CreateRaffle>,
+    ticket_price: u64,
+    max_tickets: u32,
+    end_time: i64,
+) -> Result<()> {
+    msg!("Creating raffle...");
+    let clock = Clock::get()?;
+    require!(
+        ticket_price > 0 && max_tickets > 0 && end_time > clock.unix_timestamp,
+        NftMarketplaceError::InvalidRaffleParams
+    );
+
+    // Same escrow as `list_nft`
+    token::transfer(
+        CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            token::Transfer {
+                from: ctx.accounts.seller_token_account.to_account_info(),
+                to: ctx.accounts.pda_token_account.to_account_info(),
+                authority: ctx.accounts.seller.to_account_info(),
+            }),
+        1
+    )?;
+
+    let raffle = &mut ctx.accounts.raffle;
+    raffle.seller = ctx.accounts.seller.key();
+    raffle.mint = ctx.accounts.mint.key();
+    raffle.ticket_price = ticket_price;
+    raffle.max_tickets = max_tickets;
+    raffle.tickets_sold = 0;
+    raffle.end_time = end_time;
+    raffle.winning_ticket = None;
+    raffle.draw_slot = 0;
+    raffle.bump = ctx.bumps.raffle;
+
+    msg!("Raffle of {} tickets open until {}", max_tickets, end_time);
+
+    emit_raffle_event(raffle, RaffleStatus::Created, None)
+}
+
+pub fn buy_raffle_tickets(
+    ctx: Context<BuyRaffleTickets>,
+    count: u32,
+) -> Result<()> {
+    msg!("Buying raffle tickets...");
+    let raffle = &ctx.accounts.raffle;
+    require!(
+        Clock::get()?.unix_timestamp <= raffle.end_time,
+        NftMarketplaceError::RaffleEnded
+    );
+    let tickets_sold = raffle.tickets_sold
+        .checked_add(count)
+        .ok_or(NftMarketplaceError::ArithmeticOverflow)?;
+    require!(count > 0 && tickets_sold <= raffle.max_tickets, NftMarketplaceError::RaffleSoldOut);
+
+    let cost = raffle.ticket_price
+        .checked_mul(count as u64)
+        .ok_or(NftMarketplaceError::ArithmeticOverflow)?;
+    system_program::transfer(
+        CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            system_program::Transfer {
+                from: ctx.accounts.buyer.to_account_info(),
+                to: ctx.accounts.raffle.to_account_info(),
+            }
+        ),
+        cost,
+    )?;
+
+    let tickets = &mut ctx.accounts.tickets;
+    tickets.raffle = ctx.accounts.raffle.key();
+    tickets.raffle_end_time = ctx.accounts.raffle.end_time;
+    tickets.buyer = ctx.accounts.buyer.key();
+    tickets.start = ctx.accounts.raffle.tickets_sold;
+    tickets.count = count;
+    tickets.bump = ctx.bumps.tickets;
+
+    ctx.accounts.raffle.tickets_sold = tickets_sold;
+
+    msg!("Tickets {} to {} bought", tickets.start, tickets_sold - 1);
+
+    emit_raffle_event(&ctx.accounts.raffle, RaffleStatus::TicketsBought, None)
+}
+
+// Returns the NFT to the seller as long as nobody bought a ticket
+pub fn cancel_raffle(
+    ctx: Context<CancelRaffle>,
+) -> Result<()> {
+    msg!("Cancelling raffle...");
+    require!(ctx.accounts.raffle.tickets_sold == 0, NftMarketplaceError::RaffleHasTickets);
+
+    transfer_from_escrow(
+        &ctx.accounts.pda_token_account,
+        &ctx.accounts.seller_token_account.to_account_info(),
+        &ctx.accounts.program_pda,
+        ctx.bumps.program_pda,
+        &ctx.accounts.token_program,
+    )?;
+    close_escrow_account(
+        &ctx.accounts.pda_token_account,
+        &ctx.accounts.seller.to_account_info(),
+        &ctx.accounts.program_pda,
+        ctx.bumps.program_pda,
+        &ctx.accounts.token_program,
+    )?;
+
+    emit_raffle_event(&ctx.accounts.raffle, RaffleStatus::Cancelled, None)
+}
+
+// Fixes the slot whose hash will pick the winner. Ticket sales are closed by
+// now and the hash of that slot is not known to anybody yet, so nobody can
+// check the outcome before committing. Anyone can commit again once the draw
+// slot has left SlotHashes, so a raffle nobody draws in time cannot get stuck.
+pub fn commit_raffle_draw(
+    ctx: Context<CommitRaffleDraw>,
+) -> Result<()> {
+    msg!("Committing raffle draw...");
+    let raffle = &ctx.accounts.raffle;
+    require!(raffle.winning_ticket.is_none(), NftMarketplaceError::RaffleAlreadyDrawn);
+    let clock = Clock::get()?;
+    require!(clock.unix_timestamp > raffle.end_time, NftMarketplaceError::RaffleNotEnded);
+    require!(raffle.tickets_sold > 0, NftMarketplaceError::NoTicketsSold);
+
+    if raffle.draw_slot != 0 {
+        let slot_hashes = ctx.accounts.slot_hashes.try_borrow_data()?;
+        let expired = draw_slot_hash(&slot_hashes, raffle.draw_slot)
+            .is_err_and(|err| err == NftMarketplaceError::RaffleDrawExpired.into());
+        require!(expired, NftMarketplaceError::RaffleDrawCommitted);
+    }
+
+    ctx.accounts.raffle.draw_slot = clock.slot + Raffle::DRAW_DELAY_SLOTS;
+
+    msg!("Winner is drawn from the hash of slot {}", ctx.accounts.raffle.draw_slot);
+
+    emit_raffle_event(&ctx.accounts.raffle, RaffleStatus::DrawCommitted, None)
+}
+
+// Picks the winning ticket from the hash of the committed draw slot and pays
+// the seller, so the ticket sales count as the sale of the NFT from here on.
+// The leader of that slot could in theory influence the hash, which is
+// acceptable for the lower-value items raffles are meant for.
+pub fn draw_raffle(
+    ctx: Context<DrawRaffle>,
+) -> Result<()> {
+    msg!("Drawing raffle...");
+    let raffle = &ctx.accounts.raffle;
+    require!(raffle.winning_ticket.is_none(), NftMarketplaceError::RaffleAlreadyDrawn);
+    require!(raffle.draw_slot != 0, NftMarketplaceError::RaffleDrawNotReady);
+
+    let slot_hashes = ctx.accounts.slot_hashes.try_borrow_data()?;
+    let draw_hash = draw_slot_hash(&slot_hashes, raffle.draw_slot)?;
+    let seed = keccak::hashv(&[&draw_hash, raffle.key().as_ref()]).to_bytes();
+    let random = u64::from_le_bytes(seed[..8].try_into().unwrap());
+    let winning_ticket = (random % raffle.tickets_sold as u64) as u32;
+
+    let total = raffle.ticket_price * raffle.tickets_sold as u64;
+    let (seller_proceeds, marketplace_fee, _) = ctx.accounts.marketplace.split_price(total, false);
+
+    ctx.accounts.raffle.sub_lamports(total)?;
+    ctx.accounts.seller.add_lamports(seller_proceeds)?;
+    ctx.accounts.treasury.add_lamports(marketplace_fee)?;
+
+    ctx.accounts.raffle.winning_ticket = Some(winning_ticket);
+
+    ctx.accounts.marketplace_stats.bump = ctx.bumps.marketplace_stats;
+    ctx.accounts.marketplace_stats.record_sale(total);
+    record_collection_sale(
+        ctx.program_id,
+        &ctx.accounts.metadata,
+        ctx.accounts.collection_stats.as_deref(),
+        total,
+        Clock::get()?.unix_timestamp,
+    )?;
+
+    msg!("Winning ticket: {}", winning_ticket);
+
+    emit_raffle_event(&ctx.accounts.raffle, RaffleStatus::Drawn, None)
+}
+
+// Anyone can deliver the NFT to the holder of the winning ticket
+pub fn claim_raffle_prize(
+    ctx: Context<ClaimRafflePrize>,
+) -> Result<()> {
+    msg!("Claiming raffle prize...");
+    let winning_ticket = ctx.accounts.raffle.winning_ticket
+        .ok_or(NftMarketplaceError::RaffleNotDrawn)?;
+    require!(
+        ctx.accounts.tickets.contains(winning_ticket),
+        NftMarketplaceError::NotWinningTicket
+    );
+
+    transfer_from_escrow(
+        &ctx.accounts.pda_token_account,
+        &ctx.accounts.winner_token_account.to_account_info(),
+        &ctx.accounts.program_pda,
+        ctx.bumps.program_pda,
+        &ctx.accounts.token_program,
+    )?;
+    close_escrow_account(
+        &ctx.accounts.pda_token_account,
+        &ctx.accounts.seller.to_account_info(),
+        &ctx.accounts.program_pda,
+        ctx.bumps.program_pda,
+        &ctx.accounts.token_program,
+    )?;
+
+    msg!("Raffle prize sent to {}", ctx.accounts.winner.key());
+
+    emit_raffle_event(&ctx.accounts.raffle, RaffleStatus::Claimed, Some(ctx.accounts.winner.key()))
+}
+
+// Gives the ticket rent back once the tickets can no longer win
+pub fn close_raffle_tickets(
+    ctx: Context<CloseRaffleTickets>,
+) -> Result<()> {
+    msg!("Closing raffle tickets...");
+    // The raffle is closed once the prize is claimed
+    if ctx.accounts.raffle.data_is_empty() {
+        return Ok(());
+    }
+
+    let raffle = Raffle::try_deserialize(&mut &ctx.accounts.raffle.try_borrow_data()?[..])?;
+    if raffle.end_time != ctx.accounts.tickets.raffle_end_time {
+        return Ok(());
+    }
+
+    let winning_ticket = raffle.winning_ticket.ok_or(NftMarketplaceError::RaffleNotDrawn)?;
+    require!(
+        !ctx.accounts.tickets.contains(winning_ticket),
+        NftMarketplaceError::PrizeNotClaimed
+    );
+
+    Ok(())
+}
+
+// SlotHashes layout: u64 length, then (slot: u64, hash: [u8; 32]) entries
+// with the most recent one first. Skipped slots have no entry, so this takes
+// the first slot produced at or after `draw_slot`, which is only certain while
+// the sysvar still reaches back before it.
+fn draw_slot_hash(slot_hashes: &[u8], draw_slot: u64) -> Result<[u8; 32]> {
+    let len = slot_hashes
+        .get(..8)
+        .map(|len| u64::from_le_bytes(len.try_into().unwrap()) as usize)
+        .ok_or(ProgramError::InvalidAccountData)?;
+    let entries = slot_hashes
+        .get(8..8 + len * 40)
+        .ok_or(ProgramError::InvalidAccountData)?;
+
+    let mut draw_hash = None;
+    for entry in entries.chunks_exact(40) {
+        if u64::from_le_bytes(entry[..8].try_into().unwrap()) < draw_slot {
+            return draw_hash.ok_or(error!(NftMarketplaceError::RaffleDrawNotReady));
+        }
+        draw_hash = Some(entry[8..].try_into().unwrap());
+    }
+
+    err!(NftMarketplaceError::RaffleDrawExpired)
+}
+
+fn emit_raffle_event(raffle: &Account<Raffle>, status: RaffleStatus, winner: Option<Pubkey>) -> Result<()> {
+    let clock = Clock::get()?;
+    emit!(RaffleEvent {
+        version: EVENT_VERSION,
+        raffle: raffle.key(),
+        mint: raffle.mint,
+        seller: raffle.seller,
+        ticket_price: raffle.ticket_price,
+        tickets_sold: raffle.tickets_sold,
+        end_time: raffle.end_time,
+        winning_ticket: raffle.winning_ticket,
+        winner,
+        status,
+        slot: clock.slot,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct CreateRaffle<'info> {
+    #[account(mut)]
+    pub seller: Signer<'info>,
+
+    pub mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        constraint = seller_token_account.mint == mint.key(),
+        constraint = seller_token_account.owner == seller.key(),
+    )]
+    pub seller_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        init,
+        payer = seller,
+        space = 8 + Raffle::MAX_SIZE,
+        seeds = [
+            RAFFLE_SEED.as_bytes(),
+            mint.key().as_ref()
+        ],
+        bump,
+    )]
+    pub raffle: Account<'info, Raffle>,
+
+    #[account(
+        init_if_needed,
+        payer = seller,
+        associated_token::mint = mint,
+        associated_token::authority = program_pda,
+    )]
+    pub pda_token_account: Account<'info, TokenAccount>,
+
+    /// CHECK: Program Derived Address
+    #[account(
+        seeds = [NFT_MARKET_PLACE_SEED.as_bytes()],
+        bump,
+    )]
+    pub program_pda: UncheckedAccount<'info>,
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct BuyRaffleTickets<'info> {
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            RAFFLE_SEED.as_bytes(),
+            raffle.mint.as_ref()
+        ],
+        bump = raffle.bump,
+    )]
+    pub raffle: Account<'info, Raffle>,
+
+    #[account(
+        init,
+        payer = buyer,
+        space = 8 + RaffleTickets::MAX_SIZE,
+        seeds = [
+            RAFFLE_TICKETS_SEED.as_bytes(),
+            raffle.key().as_ref(),
+            &raffle.end_time.to_le_bytes(),
+            &raffle.tickets_sold.to_le_bytes(),
+        ],
+        bump,
+    )]
+    pub tickets: Account<'info, RaffleTickets>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelRaffle<'info> {
+    #[account(mut)]
+    pub seller: Signer<'info>,
+
+    pub mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = seller,
+    )]
+    pub seller_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [
+            RAFFLE_SEED.as_bytes(),
+            mint.key().as_ref()
+        ],
+        bump = raffle.bump,
+        has_one = seller @ NftMarketplaceError::Unauthorized,
+        close = seller,
+    )]
+    pub raffle: Account<'info, Raffle>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = program_pda,
+    )]
+    pub pda_token_account: Account<'info, TokenAccount>,
+
+    /// CHECK: Program Derived Address
+    #[account(
+        seeds = [NFT_MARKET_PLACE_SEED.as_bytes()],
+        bump,
+    )]
+    pub program_pda: UncheckedAccount<'info>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct CommitRaffleDraw<'info> {
+    #[account(
+        mut,
+        seeds = [
+            RAFFLE_SEED.as_bytes(),
+            raffle.mint.as_ref()
+        ],
+        bump = raffle.bump,
+    )]
+    pub raffle: Account<'info, Raffle>,
+
+    /// CHECK: SlotHashes sysvar, too large to deserialize so it is read raw
+    #[account(address = slot_hashes::ID)]
+    pub slot_hashes: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct DrawRaffle<'info> {
+    // Anyone can draw, the caller only pays for the stats account if needed
+    #[account(mut)]
+    pub caller: Signer<'info>,
+
+    /// CHECK: The seller's account, receives the ticket sales
+    #[account(
+        mut,
+        address = raffle.seller,
+    )]
+    pub seller: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            RAFFLE_SEED.as_bytes(),
+            raffle.mint.as_ref()
+        ],
+        bump = raffle.bump,
+    )]
+    pub raffle: Account<'info, Raffle>,
+
+    #[account(
+        seeds = [MARKETPLACE_CONFIG_SEED.as_bytes()],
+        bump = marketplace.bump,
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+
+    /// CHECK: Only receives the platform fee, must match the marketplace treasury
+    #[account(
+        mut,
+        address = marketplace.treasury,
+    )]
+    pub treasury: UncheckedAccount<'info>,
+
+    #[account(address = raffle.mint)]
+    pub mint: Account<'info, Mint>,
+
+    // Proves the collection membership, so the sale lands in its stats
+    #[account(
+        seeds = [b"metadata".as_ref(), Metadata::id().as_ref(), mint.key().as_ref()],
+        bump,
+        seeds::program = Metadata::id(),
+    )]
+    pub metadata: Account<'info, MetadataAccount>,
+
+    #[account(
+        init_if_needed,
+        payer = caller,
+        space = 8 + MarketplaceStats::MAX_SIZE,
+        seeds = [MARKETPLACE_STATS_SEED.as_bytes()],
+        bump,
+    )]
+    pub marketplace_stats: Account<'info, MarketplaceStats>,
+
+    /// CHECK: Stats PDA of the NFT's verified collection, checked and written by the handler
+    #[account(mut)]
+    pub collection_stats: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: SlotHashes sysvar, too large to deserialize so it is read raw
+    #[account(address = slot_hashes::ID)]
+    pub slot_hashes: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimRafflePrize<'info> {
+    #[account(mut)]
+    pub caller: Signer<'info>,
+
+    /// CHECK: The ticket holder, gets the ticket rent back
+    #[account(
+        mut,
+        address = tickets.buyer,
+    )]
+    pub winner: UncheckedAccount<'info>,
+
+    /// CHECK: The seller's account, gets the raffle rent back
+    #[account(
+        mut,
+        address = raffle.seller,
+    )]
+    pub seller: UncheckedAccount<'info>,
+
+    #[account(address = raffle.mint)]
+    pub mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [
+            RAFFLE_SEED.as_bytes(),
+            mint.key().as_ref()
+        ],
+        bump = raffle.bump,
+        close = seller,
+    )]
+    pub raffle: Account<'info, Raffle>,
+
+    #[account(
+        mut,
+        seeds = [
+            RAFFLE_TICKETS_SEED.as_bytes(),
+            raffle.key().as_ref(),
+            &raffle.end_time.to_le_bytes(),
+            &tickets.start.to_le_bytes(),
+        ],
+        bump = tickets.bump,
+        close = winner,
+    )]
+    pub tickets: Account<'info, RaffleTickets>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = program_pda,
+    )]
+    pub pda_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        init_if_needed,
+        payer = caller,
+        associated_token::mint = mint,
+        associated_token::authority = winner,
+    )]
+    pub winner_token_account: Account<'info, TokenAccount>,
+
+    /// CHECK: Program Derived Address
+    #[account(
+        seeds = [NFT_MARKET_PLACE_SEED.as_bytes()],
+        bump,
+    )]
+    pub program_pda: UncheckedAccount<'info>,
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CloseRaffleTickets<'info> {
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    /// CHECK: The raffle of the tickets, may already be closed
+    #[account(address = tickets.raffle)]
+    pub raffle: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            RAFFLE_TICKETS_SEED.as_bytes(),
+            tickets.raffle.as_ref(),
+            &tickets.raffle_end_time.to_le_bytes(),
+            &tickets.start.to_le_bytes(),
+        ],
+        bump = tickets.bump,
+        has_one = buyer @ NftMarketplaceError::Unauthorized,
+        close = buyer,
+    )]
+    pub tickets: Account<'info, RaffleTickets>,
+}
+
+#[derive(AnchorDeserialize, AnchorSerialize, Clone)]
+pub enum RaffleStatus {
+    Created,
+    TicketsBought,
+    Cancelled,
+    Drawn,
+    Claimed,
+    DrawCommitted,
+}
+
+#[event]
+pub struct RaffleEvent {
+    pub version: u8,
+    pub raffle: Pubkey,
+    pub mint: Pubkey,
+    pub seller: Pubkey,
+    pub ticket_price: u64,
+    pub tickets_sold: u32,
+    pub end_time: i64,
+    pub winning_ticket: Option<u32>,
+    pub winner: Option<Pubkey>,
+    pub status: RaffleStatus,
+    pub slot: u64,
+    pub timestamp: i64,
+}
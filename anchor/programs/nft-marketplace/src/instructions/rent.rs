@@ -0,0 +1,474 @@
+use {
+    anchor_lang::{
+        prelude::*, system_program,
+    },
+    anchor_spl::{
+        associated_token::AssociatedToken,
+        token::{self, Token, TokenAccount, Mint},
+        metadata::{
+            freeze_delegated_account,
+            FreezeDelegatedAccount,
+            thaw_delegated_account,
+            ThawDelegatedAccount,
+            Metadata,
+        },
+    },
+};
+
+use crate::state::*;
+use crate::errors::*;
+use crate::utils::*;
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+pub fn list_for_rent(
+    ctx: Context<ListForRent>,
+    daily_rate: u64,
+    max_days: u16,
+) -> Result<()> {
+    msg!("Listing NFT for rent...");
+    require!(max_days > 0, NftMarketplaceError::InvalidRentalDuration);
+
+    // Escrow the NFT in the program PDA until it is rented out
+    token::transfer(
+        CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            token::Transfer {
+                from: ctx.accounts.owner_token_account.to_account_info(),
+                to: ctx.accounts.pda_token_account.to_account_info(),
+                authority: ctx.accounts.owner.to_account_info(),
+            }),
+        1
+    )?;
+
+    let rental = &mut ctx.accounts.rental;
+    rental.owner = ctx.accounts.owner.key();
+    rental.mint = ctx.accounts.mint.key();
+    rental.daily_rate = daily_rate;
+    rental.max_days = max_days;
+    rental.renter = None;
+    rental.rented_until = 0;
+    rental.bump = ctx.bumps.rental;
+
+    msg!("NFT listed for rent successfully!");
+
+    let clock = Clock::get()?;
+    emit!(RentalListedEvent {
+        version: EVENT_VERSION,
+        mint: rental.mint,
+        owner: rental.owner,
+        daily_rate,
+        max_days,
+        slot: clock.slot,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+pub fn rent_nft(
+    ctx: Context<RentNft>,
+    days: u16,
+) -> Result<()> {
+    msg!("Renting NFT...");
+    let rental = &ctx.accounts.rental;
+    require!(rental.renter.is_none(), NftMarketplaceError::AlreadyRented);
+    require!(days > 0 && days <= rental.max_days, NftMarketplaceError::InvalidRentalDuration);
+
+    let total_rent = rental.daily_rate
+        .checked_mul(days as u64)
+        .ok_or(NftMarketplaceError::ArithmeticOverflow)?;
+
+    // The whole lease is paid up-front to the owner
+    system_program::transfer(
+        CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            system_program::Transfer {
+                from: ctx.accounts.renter.to_account_info(),
+                to: ctx.accounts.owner.to_account_info(),
+            }
+        ),
+        total_rent,
+    )?;
+
+    let bump_seed = ctx.bumps.program_pda;
+    let program_pda_seeds : &[&[&[u8]]] = &[&[NFT_MARKET_PLACE_SEED.as_bytes(), &[bump_seed]]];
+
+    token::transfer(
+        CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            token::Transfer {
+                from: ctx.accounts.pda_token_account.to_account_info(),
+                to: ctx.accounts.renter_token_account.to_account_info(),
+                authority: ctx.accounts.program_pda.to_account_info(),
+            })
+        .with_signer(program_pda_seeds),
+        1,
+    )?;
+
+    // The NFT comes back straight into the owner's account, the escrow is done
+    close_escrow_account(
+        &ctx.accounts.pda_token_account,
+        &ctx.accounts.owner.to_account_info(),
+        &ctx.accounts.program_pda,
+        bump_seed,
+        &ctx.accounts.token_program,
+    )?;
+
+    // The renter holds the token, but the program PDA stays its delegate
+    // and freezes the account, so it can neither be moved nor revoked
+    token::approve(
+        CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            token::Approve {
+                to: ctx.accounts.renter_token_account.to_account_info(),
+                delegate: ctx.accounts.program_pda.to_account_info(),
+                authority: ctx.accounts.renter.to_account_info(),
+            }),
+        1,
+    )?;
+
+    freeze_delegated_account(
+        CpiContext::new_with_signer(
+            ctx.accounts.token_metadata_program.to_account_info(),
+            FreezeDelegatedAccount {
+                metadata: ctx.accounts.metadata.to_account_info(),
+                delegate: ctx.accounts.program_pda.to_account_info(),
+                token_account: ctx.accounts.renter_token_account.to_account_info(),
+                edition: ctx.accounts.master_edition.to_account_info(),
+                mint: ctx.accounts.mint.to_account_info(),
+                token_program: ctx.accounts.token_program.to_account_info(),
+            },
+            program_pda_seeds,
+        ),
+    )?;
+
+    let clock = Clock::get()?;
+    let rental = &mut ctx.accounts.rental;
+    rental.renter = Some(ctx.accounts.renter.key());
+    rental.rented_until = clock.unix_timestamp + days as i64 * SECONDS_PER_DAY;
+
+    msg!("NFT rented until {}", rental.rented_until);
+
+    emit!(NftRentedEvent {
+        version: EVENT_VERSION,
+        mint: rental.mint,
+        owner: rental.owner,
+        renter: ctx.accounts.renter.key(),
+        days,
+        total_rent,
+        rented_until: rental.rented_until,
+        slot: clock.slot,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+pub fn end_rental(
+    ctx: Context<EndRental>,
+) -> Result<()> {
+    msg!("Ending rental...");
+    let clock = Clock::get()?;
+    let rental = &ctx.accounts.rental;
+
+    let bump_seed = ctx.bumps.program_pda;
+    let program_pda_seeds : &[&[&[u8]]] = &[&[NFT_MARKET_PLACE_SEED.as_bytes(), &[bump_seed]]];
+
+    // While rented out only the expiry ends the lease, and then anyone may crank it.
+    // An NFT that was never rented can only be withdrawn by its owner.
+    let source = match rental.renter {
+        Some(renter) => {
+            require!(clock.unix_timestamp >= rental.rented_until, NftMarketplaceError::RentalNotExpired);
+            let renter_token_account = ctx.accounts.renter_token_account
+                .as_ref()
+                .ok_or(NftMarketplaceError::MissingRenterTokenAccount)?;
+            require_keys_eq!(renter_token_account.owner, renter, NftMarketplaceError::MissingRenterTokenAccount);
+            require_keys_eq!(renter_token_account.mint, rental.mint, NftMarketplaceError::MissingRenterTokenAccount);
+
+            thaw_delegated_account(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_metadata_program.to_account_info(),
+                    ThawDelegatedAccount {
+                        metadata: ctx.accounts.metadata.to_account_info(),
+                        delegate: ctx.accounts.program_pda.to_account_info(),
+                        token_account: renter_token_account.to_account_info(),
+                        edition: ctx.accounts.master_edition.to_account_info(),
+                        mint: ctx.accounts.mint.to_account_info(),
+                        token_program: ctx.accounts.token_program.to_account_info(),
+                    },
+                    program_pda_seeds,
+                ),
+            )?;
+            renter_token_account.to_account_info()
+        }
+        None => {
+            require_keys_eq!(ctx.accounts.caller.key(), rental.owner, NftMarketplaceError::Unauthorized);
+            ctx.accounts.pda_token_account
+                .as_ref()
+                .ok_or(NftMarketplaceError::MissingEscrowAccount)?
+                .to_account_info()
+        }
+    };
+
+    // The program PDA is either the escrow owner or the renter's delegate
+    token::transfer(
+        CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            token::Transfer {
+                from: source,
+                to: ctx.accounts.owner_token_account.to_account_info(),
+                authority: ctx.accounts.program_pda.to_account_info(),
+            })
+        .with_signer(program_pda_seeds),
+        1,
+    )?;
+
+    if rental.renter.is_none() {
+        if let Some(pda_token_account) = &ctx.accounts.pda_token_account {
+            close_escrow_account(
+                pda_token_account,
+                &ctx.accounts.owner.to_account_info(),
+                &ctx.accounts.program_pda,
+                bump_seed,
+                &ctx.accounts.token_program,
+            )?;
+        }
+    }
+
+    msg!("NFT returned to the owner.");
+
+    emit!(RentalEndedEvent {
+        version: EVENT_VERSION,
+        mint: rental.mint,
+        owner: rental.owner,
+        renter: rental.renter,
+        slot: clock.slot,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct ListForRent<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        constraint = owner_token_account.mint == mint.key(),
+        constraint = owner_token_account.owner == owner.key(),
+    )]
+    pub owner_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + Rental::MAX_SIZE,
+        seeds = [
+            RENTAL_SEED.as_bytes(),
+            mint.key().as_ref()
+        ],
+        bump,
+    )]
+    pub rental: Account<'info, Rental>,
+
+    #[account(
+        init_if_needed,
+        payer = owner,
+        associated_token::mint = mint,
+        associated_token::authority = program_pda,
+    )]
+    pub pda_token_account: Account<'info, TokenAccount>,
+
+    /// CHECK: Program Derived Address
+    #[account(
+        seeds = [NFT_MARKET_PLACE_SEED.as_bytes()],
+        bump,
+    )]
+    pub program_pda: UncheckedAccount<'info>,
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RentNft<'info> {
+    #[account(mut)]
+    pub renter: Signer<'info>,
+
+    /// CHECK: The owner's account, receives the rent
+    #[account(
+        mut,
+        address = rental.owner,
+    )]
+    pub owner: UncheckedAccount<'info>,
+
+    pub mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [
+            RENTAL_SEED.as_bytes(),
+            mint.key().as_ref()
+        ],
+        bump = rental.bump,
+    )]
+    pub rental: Account<'info, Rental>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = program_pda,
+    )]
+    pub pda_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        init_if_needed,
+        payer = renter,
+        associated_token::mint = mint,
+        associated_token::authority = renter,
+    )]
+    pub renter_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        seeds = [b"metadata".as_ref(), token_metadata_program.key().as_ref(), mint.key().as_ref()],
+        bump,
+        seeds::program = token_metadata_program.key()
+    )]
+    /// CHECK: Metaplex will do the check
+    pub metadata: UncheckedAccount<'info>,
+
+    #[account(
+        seeds = [b"metadata".as_ref(), token_metadata_program.key().as_ref(), mint.key().as_ref(), b"edition".as_ref()],
+        bump,
+        seeds::program = token_metadata_program.key()
+    )]
+    /// CHECK: Metaplex will do the check
+    pub master_edition: UncheckedAccount<'info>,
+
+    /// CHECK: Program Derived Address
+    #[account(
+        seeds = [NFT_MARKET_PLACE_SEED.as_bytes()],
+        bump,
+    )]
+    pub program_pda: UncheckedAccount<'info>,
+
+    pub token_metadata_program: Program<'info, Metadata>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct EndRental<'info> {
+    // Anyone once the lease has expired, otherwise the owner
+    #[account(mut)]
+    pub caller: Signer<'info>,
+
+    /// CHECK: The owner's account, gets the NFT and the rental rent back
+    #[account(
+        mut,
+        address = rental.owner,
+    )]
+    pub owner: UncheckedAccount<'info>,
+
+    pub mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [
+            RENTAL_SEED.as_bytes(),
+            mint.key().as_ref()
+        ],
+        bump = rental.bump,
+        close = owner,
+    )]
+    pub rental: Account<'info, Rental>,
+
+    // Only needed while the NFT is not rented out, `rent_nft` closes it
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = program_pda,
+    )]
+    pub pda_token_account: Option<Account<'info, TokenAccount>>,
+
+    // Only needed while the NFT is rented out
+    #[account(mut)]
+    pub renter_token_account: Option<Account<'info, TokenAccount>>,
+
+    #[account(
+        init_if_needed,
+        payer = caller,
+        associated_token::mint = mint,
+        associated_token::authority = owner,
+    )]
+    pub owner_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        seeds = [b"metadata".as_ref(), token_metadata_program.key().as_ref(), mint.key().as_ref()],
+        bump,
+        seeds::program = token_metadata_program.key()
+    )]
+    /// CHECK: Metaplex will do the check
+    pub metadata: UncheckedAccount<'info>,
+
+    #[account(
+        seeds = [b"metadata".as_ref(), token_metadata_program.key().as_ref(), mint.key().as_ref(), b"edition".as_ref()],
+        bump,
+        seeds::program = token_metadata_program.key()
+    )]
+    /// CHECK: Metaplex will do the check
+    pub master_edition: UncheckedAccount<'info>,
+
+    /// CHECK: Program Derived Address
+    #[account(
+        seeds = [NFT_MARKET_PLACE_SEED.as_bytes()],
+        bump,
+    )]
+    pub program_pda: UncheckedAccount<'info>,
+
+    pub token_metadata_program: Program<'info, Metadata>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event]
+pub struct RentalListedEvent {
+    pub version: u8,
+    pub mint: Pubkey,
+    pub owner: Pubkey,
+    pub daily_rate: u64,
+    pub max_days: u16,
+    pub slot: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct NftRentedEvent {
+    pub version: u8,
+    pub mint: Pubkey,
+    pub owner: Pubkey,
+    pub renter: Pubkey,
+    pub days: u16,
+    pub total_rent: u64,
+    pub rented_until: i64,
+    pub slot: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct RentalEndedEvent {
+    pub version: u8,
+    pub mint: Pubkey,
+    pub owner: Pubkey,
+    pub renter: Option<Pubkey>,
+    pub slot: u64,
+    pub timestamp: i64,
+}
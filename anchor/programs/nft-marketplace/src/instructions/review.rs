@@ -0,0 +1,92 @@
+use anchor_lang::prelude::*;
+
+use crate::state::*;
+use crate::errors::*;
+
+pub fn leave_review(
+    ctx: Context<LeaveReview>,
+    rating: u8,
+) -> Result<()> {
+    msg!("Leaving review...");
+    require!(
+        (Reputation::MIN_RATING..=Reputation::MAX_RATING).contains(&rating),
+        NftMarketplaceError::InvalidRating
+    );
+
+    // The receipt is closed below, so every sale can be reviewed only once
+    let reputation = &mut ctx.accounts.reputation;
+    reputation.seller = ctx.accounts.seller.key();
+    reputation.bump = ctx.bumps.reputation;
+    reputation.add_rating(rating);
+
+    msg!("Seller {} now rated {} over {} reviews", reputation.seller, reputation.average_rating, reputation.review_count);
+
+    let clock = Clock::get()?;
+    emit!(ReviewEvent {
+        version: EVENT_VERSION,
+        seller: reputation.seller,
+        buyer: ctx.accounts.buyer.key(),
+        mint: ctx.accounts.sale_receipt.mint,
+        rating,
+        review_count: reputation.review_count,
+        average_rating: reputation.average_rating,
+        slot: clock.slot,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct LeaveReview<'info> {
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    /// CHECK: The reviewed seller, taken from the receipt. Receipts of
+    /// self-purchases from before `buy_nft` rejected them cannot be used.
+    #[account(
+        address = sale_receipt.seller,
+        constraint = seller.key() != buyer.key() @ NftMarketplaceError::SelfPurchase,
+    )]
+    pub seller: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            SALE_RECEIPT_SEED.as_bytes(),
+            sale_receipt.mint.as_ref(),
+            buyer.key().as_ref(),
+        ],
+        bump = sale_receipt.bump,
+        has_one = buyer @ NftMarketplaceError::Unauthorized,
+        close = buyer,
+    )]
+    pub sale_receipt: Account<'info, SaleReceipt>,
+
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        space = 8 + Reputation::MAX_SIZE,
+        seeds = [
+            REPUTATION_SEED.as_bytes(),
+            seller.key().as_ref(),
+        ],
+        bump,
+    )]
+    pub reputation: Account<'info, Reputation>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[event]
+pub struct ReviewEvent {
+    pub version: u8,
+    pub seller: Pubkey,
+    pub buyer: Pubkey,
+    pub mint: Pubkey,
+    pub rating: u8,
+    pub review_count: u64,
+    pub average_rating: u16,
+    pub slot: u64,
+    pub timestamp: i64,
+}
@@ -0,0 +1,404 @@
+use {
+    anchor_lang::{
+        prelude::*, system_program,
+    },
+    anchor_spl::{
+        associated_token::AssociatedToken,
+        token::{Token, TokenAccount, Mint},
+        metadata::{Metadata, MetadataAccount},
+    },
+};
+
+use crate::state::*;
+use crate::errors::*;
+use crate::utils::*;
+
+pub fn buy_nft_escrowed(
+    ctx: Context<BuyNftEscrowed>,
+) -> Result<()> {
+    msg!("Buying NFT with escrowed settlement...");
+    check_buyer_attestation(
+        &ctx.accounts.marketplace,
+        &ctx.accounts.nft_listing_account,
+        ctx.accounts.buyer_attestation.as_ref(),
+        &ctx.accounts.buyer.key(),
+    )?;
+    // These flows lock in the lamport price, USD listings only sell through `buy_nft`
+    require!(
+        ctx.accounts.nft_listing_account.usd_price == 0,
+        NftMarketplaceError::UsdListingNotSupported
+    );
+    let price = ctx.accounts.nft_listing_account.price;
+
+    require!(
+        ctx.accounts.buyer.lamports() >= price,
+        NftMarketplaceError::InsufficientFunds
+    );
+
+    // Lamports wait in the settlement PDA, the NFT stays in the program escrow
+    system_program::transfer(
+        CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            system_program::Transfer {
+                from: ctx.accounts.buyer.to_account_info(),
+                to: ctx.accounts.settlement.to_account_info(),
+            }
+        ),
+        price,
+    )?;
+
+    // Counted as a sale once it settles, a refund is no sale
+    ctx.accounts.marketplace_stats.bump = ctx.bumps.marketplace_stats;
+    ctx.accounts.marketplace_stats.record_delisting();
+
+    let clock = Clock::get()?;
+    let settlement = &mut ctx.accounts.settlement;
+    settlement.mint = ctx.accounts.mint.key();
+    settlement.seller = ctx.accounts.seller.key();
+    settlement.buyer = ctx.accounts.buyer.key();
+    settlement.price = price;
+    settlement.dispute_deadline = clock.unix_timestamp + ctx.accounts.marketplace.dispute_window;
+    settlement.disputed = false;
+    settlement.bump = ctx.bumps.settlement;
+
+    msg!("Payment escrowed until {}", settlement.dispute_deadline);
+
+    emit_settlement_event(settlement, SettlementStatus::Escrowed, 0)
+}
+
+pub fn release_settlement(
+    ctx: Context<SettleEscrow>,
+) -> Result<()> {
+    msg!("Releasing settlement...");
+    let settlement = &ctx.accounts.settlement;
+    require!(!settlement.disputed, NftMarketplaceError::SettlementDisputed);
+
+    // The buyer can confirm at any time, everybody else has to wait out the window
+    if ctx.accounts.caller.key() != settlement.buyer {
+        require!(
+            Clock::get()?.unix_timestamp > settlement.dispute_deadline,
+            NftMarketplaceError::Unauthorized
+        );
+    }
+
+    pay_seller_and_deliver(ctx)
+}
+
+pub fn open_dispute(
+    ctx: Context<OpenDispute>,
+) -> Result<()> {
+    msg!("Opening dispute...");
+    let settlement = &mut ctx.accounts.settlement;
+    require!(!settlement.disputed, NftMarketplaceError::SettlementDisputed);
+    require!(
+        Clock::get()?.unix_timestamp <= settlement.dispute_deadline,
+        NftMarketplaceError::DisputeWindowClosed
+    );
+
+    settlement.disputed = true;
+
+    emit_settlement_event(settlement, SettlementStatus::Disputed, 0)
+}
+
+pub fn resolve_dispute(
+    ctx: Context<SettleEscrow>,
+    refund_buyer: bool,
+) -> Result<()> {
+    msg!("Resolving dispute...");
+    require!(ctx.accounts.settlement.disputed, NftMarketplaceError::SettlementNotDisputed);
+    require_keys_eq!(
+        ctx.accounts.caller.key(),
+        ctx.accounts.marketplace.arbiter,
+        NftMarketplaceError::Unauthorized
+    );
+
+    if !refund_buyer {
+        return pay_seller_and_deliver(ctx);
+    }
+
+    // Refund: the buyer gets the lamports back and the seller the NFT
+    let seller_token_account = ctx.accounts.seller_token_account
+        .as_ref()
+        .ok_or(NftMarketplaceError::MissingSellerTokenAccount)?;
+    require_keys_eq!(seller_token_account.owner, ctx.accounts.seller.key(), NftMarketplaceError::MissingSellerTokenAccount);
+    require_keys_eq!(seller_token_account.mint, ctx.accounts.mint.key(), NftMarketplaceError::MissingSellerTokenAccount);
+
+    transfer_from_escrow(
+        &ctx.accounts.pda_token_account,
+        &seller_token_account.to_account_info(),
+        &ctx.accounts.program_pda,
+        ctx.bumps.program_pda,
+        &ctx.accounts.token_program,
+    )?;
+    close_escrow_account(
+        &ctx.accounts.pda_token_account,
+        &ctx.accounts.seller.to_account_info(),
+        &ctx.accounts.program_pda,
+        ctx.bumps.program_pda,
+        &ctx.accounts.token_program,
+    )?;
+
+    let price = ctx.accounts.settlement.price;
+    ctx.accounts.settlement.sub_lamports(price)?;
+    ctx.accounts.buyer.add_lamports(price)?;
+
+    msg!("Buyer refunded, NFT returned to the seller.");
+
+    emit_settlement_event(&ctx.accounts.settlement, SettlementStatus::Refunded, 0)
+}
+
+fn pay_seller_and_deliver(
+    ctx: Context<SettleEscrow>,
+) -> Result<()> {
+    let price = ctx.accounts.settlement.price;
+    let (seller_proceeds, marketplace_fee, _) = ctx.accounts.marketplace.split_price(price, false);
+
+    transfer_from_escrow(
+        &ctx.accounts.pda_token_account,
+        &ctx.accounts.buyer_token_account.to_account_info(),
+        &ctx.accounts.program_pda,
+        ctx.bumps.program_pda,
+        &ctx.accounts.token_program,
+    )?;
+    close_escrow_account(
+        &ctx.accounts.pda_token_account,
+        &ctx.accounts.seller.to_account_info(),
+        &ctx.accounts.program_pda,
+        ctx.bumps.program_pda,
+        &ctx.accounts.token_program,
+    )?;
+
+    ctx.accounts.settlement.sub_lamports(price)?;
+    ctx.accounts.seller.add_lamports(seller_proceeds)?;
+    ctx.accounts.treasury.add_lamports(marketplace_fee)?;
+
+    ctx.accounts.marketplace_stats.record_sale(price);
+    record_collection_sale(
+        ctx.program_id,
+        &ctx.accounts.metadata,
+        ctx.accounts.collection_stats.as_deref(),
+        price,
+        Clock::get()?.unix_timestamp,
+    )?;
+
+    msg!("Settlement released to the seller.");
+
+    emit_settlement_event(&ctx.accounts.settlement, SettlementStatus::Released, marketplace_fee)
+}
+
+fn emit_settlement_event(settlement: &Account<Settlement>, status: SettlementStatus, marketplace_fee: u64) -> Result<()> {
+    let clock = Clock::get()?;
+    emit!(SettlementEvent {
+        version: EVENT_VERSION,
+        settlement: settlement.key(),
+        mint: settlement.mint,
+        seller: settlement.seller,
+        buyer: settlement.buyer,
+        price: settlement.price,
+        marketplace_fee,
+        dispute_deadline: settlement.dispute_deadline,
+        status,
+        slot: clock.slot,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct BuyNftEscrowed<'info> {
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    /// CHECK: The seller's account, gets the listing rent back
+    #[account(
+        mut,
+        address = nft_listing_account.owner,
+    )]
+    pub seller: UncheckedAccount<'info>,
+
+    pub mint: Account<'info, Mint>,
+
+    #[account(
+        seeds = [MARKETPLACE_CONFIG_SEED.as_bytes()],
+        bump = marketplace.bump,
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+
+    #[account(
+        mut,
+        seeds = [
+            LISTED_NFT_SEED.as_bytes(),
+            mint.key().as_ref()
+        ],
+        bump = nft_listing_account.bump,
+        close = seller,
+    )]
+    pub nft_listing_account: Account<'info, ListedNft>,
+
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        space = 8 + MarketplaceStats::MAX_SIZE,
+        seeds = [MARKETPLACE_STATS_SEED.as_bytes()],
+        bump,
+    )]
+    pub marketplace_stats: Account<'info, MarketplaceStats>,
+
+    #[account(
+        seeds = [
+            BUYER_ATTESTATION_SEED.as_bytes(),
+            buyer.key().as_ref(),
+        ],
+        bump = buyer_attestation.bump,
+    )]
+    pub buyer_attestation: Option<Account<'info, BuyerAttestation>>,
+
+    #[account(
+        init,
+        payer = buyer,
+        space = 8 + Settlement::MAX_SIZE,
+        seeds = [
+            SETTLEMENT_SEED.as_bytes(),
+            mint.key().as_ref()
+        ],
+        bump,
+    )]
+    pub settlement: Account<'info, Settlement>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct OpenDispute<'info> {
+    pub buyer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            SETTLEMENT_SEED.as_bytes(),
+            settlement.mint.as_ref()
+        ],
+        bump = settlement.bump,
+        has_one = buyer @ NftMarketplaceError::Unauthorized,
+    )]
+    pub settlement: Account<'info, Settlement>,
+}
+
+#[derive(Accounts)]
+pub struct SettleEscrow<'info> {
+    // Buyer, arbiter or, after the window, anyone
+    #[account(mut)]
+    pub caller: Signer<'info>,
+
+    /// CHECK: The seller's account
+    #[account(
+        mut,
+        address = settlement.seller,
+    )]
+    pub seller: UncheckedAccount<'info>,
+
+    /// CHECK: The buyer's account, gets the settlement rent back
+    #[account(
+        mut,
+        address = settlement.buyer,
+    )]
+    pub buyer: UncheckedAccount<'info>,
+
+    pub mint: Account<'info, Mint>,
+
+    #[account(
+        seeds = [MARKETPLACE_CONFIG_SEED.as_bytes()],
+        bump = marketplace.bump,
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+
+    /// CHECK: Only receives the platform fee, must match the marketplace treasury
+    #[account(
+        mut,
+        address = marketplace.treasury,
+    )]
+    pub treasury: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            SETTLEMENT_SEED.as_bytes(),
+            mint.key().as_ref()
+        ],
+        bump = settlement.bump,
+        close = buyer,
+    )]
+    pub settlement: Account<'info, Settlement>,
+
+    #[account(
+        mut,
+        seeds = [MARKETPLACE_STATS_SEED.as_bytes()],
+        bump = marketplace_stats.bump,
+    )]
+    pub marketplace_stats: Account<'info, MarketplaceStats>,
+
+    /// CHECK: Stats PDA of the NFT's verified collection, checked and written by the handler
+    #[account(mut)]
+    pub collection_stats: Option<UncheckedAccount<'info>>,
+
+    #[account(
+        seeds = [b"metadata".as_ref(), Metadata::id().as_ref(), mint.key().as_ref()],
+        bump,
+        seeds::program = Metadata::id(),
+    )]
+    pub metadata: Account<'info, MetadataAccount>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = program_pda,
+    )]
+    pub pda_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        init_if_needed,
+        payer = caller,
+        associated_token::mint = mint,
+        associated_token::authority = buyer,
+    )]
+    pub buyer_token_account: Account<'info, TokenAccount>,
+
+    // Only needed when the arbiter refunds the buyer
+    #[account(mut)]
+    pub seller_token_account: Option<Account<'info, TokenAccount>>,
+
+    /// CHECK: Program Derived Address
+    #[account(
+        seeds = [NFT_MARKET_PLACE_SEED.as_bytes()],
+        bump,
+    )]
+    pub program_pda: UncheckedAccount<'info>,
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(AnchorDeserialize, AnchorSerialize, Clone)]
+pub enum SettlementStatus {
+    Escrowed,
+    Disputed,
+    Released,
+    Refunded,
+}
+
+#[event]
+pub struct SettlementEvent {
+    pub version: u8,
+    pub settlement: Pubkey,
+    pub mint: Pubkey,
+    pub seller: Pubkey,
+    pub buyer: Pubkey,
+    pub price: u64,
+    pub marketplace_fee: u64,
+    pub dispute_deadline: i64,
+    pub status: SettlementStatus,
+    pub slot: u64,
+    pub timestamp: i64,
+}
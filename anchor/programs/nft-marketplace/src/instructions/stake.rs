@@ -0,0 +1,454 @@
+use {
+    anchor_lang::prelude::*,
+    anchor_spl::{
+        associated_token::AssociatedToken,
+        token::{self, Token, TokenAccount, Mint},
+        metadata::{Metadata, MetadataAccount},
+    },
+};
+
+use crate::state::*;
+use crate::errors::*;
+use crate::utils::*;
+
+pub fn initialize_stake_pool(
+    ctx: Context<InitializeStakePool>,
+    reward_rate: u64,
+) -> Result<()> {
+    msg!("Initializing stake pool...");
+
+    let pool = &mut ctx.accounts.stake_pool;
+    pool.authority = ctx.accounts.authority.key();
+    pool.collection = ctx.accounts.collection_mint.key();
+    pool.reward_mint = ctx.accounts.reward_mint.key();
+    pool.reward_rate = reward_rate;
+    pool.total_staked = 0;
+    pool.bump = ctx.bumps.stake_pool;
+
+    msg!("Stake pool {} initialized", pool.key());
+
+    Ok(())
+}
+
+pub fn stake_nft(
+    ctx: Context<StakeNft>,
+) -> Result<()> {
+    msg!("Staking NFT...");
+    require!(
+        is_verified_collection_member(&ctx.accounts.metadata, &ctx.accounts.stake_pool.collection),
+        NftMarketplaceError::NotInCollection
+    );
+
+    // Lock the NFT in the same program PDA escrow used for listings
+    token::transfer(
+        CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            token::Transfer {
+                from: ctx.accounts.owner_token_account.to_account_info(),
+                to: ctx.accounts.pda_token_account.to_account_info(),
+                authority: ctx.accounts.owner.to_account_info(),
+            }),
+        1
+    )?;
+
+    let now = Clock::get()?.unix_timestamp;
+    let stake_entry = &mut ctx.accounts.stake_entry;
+    stake_entry.pool = ctx.accounts.stake_pool.key();
+    stake_entry.owner = ctx.accounts.owner.key();
+    stake_entry.mint = ctx.accounts.mint.key();
+    stake_entry.staked_at = now;
+    stake_entry.last_claimed_at = now;
+    stake_entry.bump = ctx.bumps.stake_entry;
+
+    ctx.accounts.stake_pool.total_staked += 1;
+
+    msg!("NFT staked successfully!");
+
+    let clock = Clock::get()?;
+    emit!(NftStakedEvent {
+        version: EVENT_VERSION,
+        pool: ctx.accounts.stake_pool.key(),
+        mint: ctx.accounts.mint.key(),
+        owner: ctx.accounts.owner.key(),
+        slot: clock.slot,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+pub fn claim_rewards(
+    ctx: Context<ClaimRewards>,
+) -> Result<()> {
+    pay_rewards(
+        &ctx.accounts.stake_pool,
+        &mut ctx.accounts.stake_entry,
+        &ctx.accounts.reward_mint,
+        &ctx.accounts.owner_reward_account,
+        &ctx.accounts.token_program,
+    )?;
+
+    Ok(())
+}
+
+pub fn unstake_nft(
+    ctx: Context<UnstakeNft>,
+) -> Result<()> {
+    msg!("Unstaking NFT...");
+
+    pay_rewards(
+        &ctx.accounts.stake_pool,
+        &mut ctx.accounts.stake_entry,
+        &ctx.accounts.reward_mint,
+        &ctx.accounts.owner_reward_account,
+        &ctx.accounts.token_program,
+    )?;
+
+    let bump_seed = ctx.bumps.program_pda;
+    let program_pda_seeds : &[&[&[u8]]] = &[&[NFT_MARKET_PLACE_SEED.as_bytes(), &[bump_seed]]];
+
+    token::transfer(
+        CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            token::Transfer {
+                from: ctx.accounts.pda_token_account.to_account_info(),
+                to: ctx.accounts.owner_token_account.to_account_info(),
+                authority: ctx.accounts.program_pda.to_account_info(),
+            })
+        .with_signer(program_pda_seeds),
+        1,
+    )?;
+
+    close_escrow_account(
+        &ctx.accounts.pda_token_account,
+        &ctx.accounts.owner.to_account_info(),
+        &ctx.accounts.program_pda,
+        bump_seed,
+        &ctx.accounts.token_program,
+    )?;
+
+    ctx.accounts.stake_pool.total_staked -= 1;
+
+    msg!("NFT unstaked successfully!");
+
+    let clock = Clock::get()?;
+    emit!(NftUnstakedEvent {
+        version: EVENT_VERSION,
+        pool: ctx.accounts.stake_pool.key(),
+        mint: ctx.accounts.mint.key(),
+        owner: ctx.accounts.owner.key(),
+        staked_seconds: clock.unix_timestamp - ctx.accounts.stake_entry.staked_at,
+        slot: clock.slot,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+// Mints the rewards accrued since the last claim to the owner
+fn pay_rewards<'info>(
+    stake_pool: &Account<'info, StakePool>,
+    stake_entry: &mut Account<'info, StakeEntry>,
+    reward_mint: &Account<'info, Mint>,
+    owner_reward_account: &Account<'info, TokenAccount>,
+    token_program: &Program<'info, Token>,
+) -> Result<()> {
+    let clock = Clock::get()?;
+    let elapsed = clock.unix_timestamp.saturating_sub(stake_entry.last_claimed_at) as u64;
+    let amount = elapsed
+        .checked_mul(stake_pool.reward_rate)
+        .ok_or(NftMarketplaceError::ArithmeticOverflow)?;
+
+    stake_entry.last_claimed_at = clock.unix_timestamp;
+
+    if amount == 0 {
+        return Ok(());
+    }
+
+    let pool_seeds : &[&[&[u8]]] = &[&[
+        STAKE_POOL_SEED.as_bytes(),
+        stake_pool.collection.as_ref(),
+        &[stake_pool.bump],
+    ]];
+
+    token::mint_to(
+        CpiContext::new(
+            token_program.to_account_info(),
+            token::MintTo {
+                mint: reward_mint.to_account_info(),
+                to: owner_reward_account.to_account_info(),
+                authority: stake_pool.to_account_info(),
+            })
+        .with_signer(pool_seeds),
+        amount,
+    )?;
+
+    msg!("Claimed {} reward tokens", amount);
+
+    emit!(RewardsClaimedEvent {
+        version: EVENT_VERSION,
+        pool: stake_pool.key(),
+        mint: stake_entry.mint,
+        owner: stake_entry.owner,
+        amount,
+        slot: clock.slot,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct InitializeStakePool<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub collection_mint: Account<'info, Mint>,
+
+    // Only the update authority of the collection may open a pool for it
+    #[account(
+        seeds = [b"metadata".as_ref(), token_metadata_program.key().as_ref(), collection_mint.key().as_ref()],
+        bump,
+        seeds::program = token_metadata_program.key(),
+        constraint = collection_metadata.update_authority == authority.key() @ NftMarketplaceError::Unauthorized,
+    )]
+    pub collection_metadata: Account<'info, MetadataAccount>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + StakePool::MAX_SIZE,
+        seeds = [
+            STAKE_POOL_SEED.as_bytes(),
+            collection_mint.key().as_ref(),
+        ],
+        bump,
+    )]
+    pub stake_pool: Account<'info, StakePool>,
+
+    #[account(
+        init,
+        payer = authority,
+        mint::decimals = 0,
+        mint::authority = stake_pool,
+        seeds = [
+            REWARD_MINT_SEED.as_bytes(),
+            stake_pool.key().as_ref(),
+        ],
+        bump,
+    )]
+    pub reward_mint: Account<'info, Mint>,
+
+    pub token_metadata_program: Program<'info, Metadata>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct StakeNft<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            STAKE_POOL_SEED.as_bytes(),
+            stake_pool.collection.as_ref(),
+        ],
+        bump = stake_pool.bump,
+    )]
+    pub stake_pool: Account<'info, StakePool>,
+
+    pub mint: Account<'info, Mint>,
+
+    #[account(
+        seeds = [b"metadata".as_ref(), token_metadata_program.key().as_ref(), mint.key().as_ref()],
+        bump,
+        seeds::program = token_metadata_program.key(),
+    )]
+    pub metadata: Account<'info, MetadataAccount>,
+
+    #[account(
+        mut,
+        constraint = owner_token_account.mint == mint.key(),
+        constraint = owner_token_account.owner == owner.key(),
+    )]
+    pub owner_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + StakeEntry::MAX_SIZE,
+        seeds = [
+            STAKE_ENTRY_SEED.as_bytes(),
+            mint.key().as_ref(),
+        ],
+        bump,
+    )]
+    pub stake_entry: Account<'info, StakeEntry>,
+
+    #[account(
+        init_if_needed,
+        payer = owner,
+        associated_token::mint = mint,
+        associated_token::authority = program_pda,
+    )]
+    pub pda_token_account: Account<'info, TokenAccount>,
+
+    /// CHECK: Program Derived Address
+    #[account(
+        seeds = [NFT_MARKET_PLACE_SEED.as_bytes()],
+        bump,
+    )]
+    pub program_pda: UncheckedAccount<'info>,
+
+    pub token_metadata_program: Program<'info, Metadata>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimRewards<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        seeds = [
+            STAKE_POOL_SEED.as_bytes(),
+            stake_pool.collection.as_ref(),
+        ],
+        bump = stake_pool.bump,
+        has_one = reward_mint,
+    )]
+    pub stake_pool: Account<'info, StakePool>,
+
+    #[account(
+        mut,
+        seeds = [
+            STAKE_ENTRY_SEED.as_bytes(),
+            stake_entry.mint.as_ref(),
+        ],
+        bump = stake_entry.bump,
+        has_one = owner @ NftMarketplaceError::Unauthorized,
+        constraint = stake_entry.pool == stake_pool.key(),
+    )]
+    pub stake_entry: Account<'info, StakeEntry>,
+
+    #[account(mut)]
+    pub reward_mint: Account<'info, Mint>,
+
+    #[account(
+        init_if_needed,
+        payer = owner,
+        associated_token::mint = reward_mint,
+        associated_token::authority = owner,
+    )]
+    pub owner_reward_account: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UnstakeNft<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            STAKE_POOL_SEED.as_bytes(),
+            stake_pool.collection.as_ref(),
+        ],
+        bump = stake_pool.bump,
+        has_one = reward_mint,
+    )]
+    pub stake_pool: Account<'info, StakePool>,
+
+    #[account(
+        mut,
+        seeds = [
+            STAKE_ENTRY_SEED.as_bytes(),
+            mint.key().as_ref(),
+        ],
+        bump = stake_entry.bump,
+        has_one = owner @ NftMarketplaceError::Unauthorized,
+        constraint = stake_entry.pool == stake_pool.key(),
+        close = owner,
+    )]
+    pub stake_entry: Account<'info, StakeEntry>,
+
+    pub mint: Account<'info, Mint>,
+
+    #[account(mut)]
+    pub reward_mint: Account<'info, Mint>,
+
+    #[account(
+        init_if_needed,
+        payer = owner,
+        associated_token::mint = reward_mint,
+        associated_token::authority = owner,
+    )]
+    pub owner_reward_account: Account<'info, TokenAccount>,
+
+    #[account(
+        init_if_needed,
+        payer = owner,
+        associated_token::mint = mint,
+        associated_token::authority = owner,
+    )]
+    pub owner_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = program_pda,
+    )]
+    pub pda_token_account: Account<'info, TokenAccount>,
+
+    /// CHECK: Program Derived Address
+    #[account(
+        seeds = [NFT_MARKET_PLACE_SEED.as_bytes()],
+        bump,
+    )]
+    pub program_pda: UncheckedAccount<'info>,
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event]
+pub struct NftStakedEvent {
+    pub version: u8,
+    pub pool: Pubkey,
+    pub mint: Pubkey,
+    pub owner: Pubkey,
+    pub slot: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct RewardsClaimedEvent {
+    pub version: u8,
+    pub pool: Pubkey,
+    pub mint: Pubkey,
+    pub owner: Pubkey,
+    pub amount: u64,
+    pub slot: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct NftUnstakedEvent {
+    pub version: u8,
+    pub pool: Pubkey,
+    pub mint: Pubkey,
+    pub owner: Pubkey,
+    pub staked_seconds: i64,
+    pub slot: u64,
+    pub timestamp: i64,
+}
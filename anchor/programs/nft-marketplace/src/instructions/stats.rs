@@ -0,0 +1,40 @@
+use anchor_lang::prelude::*;
+
+use crate::state::*;
+
+// Anyone can start tracking a collection, every sale of a verified member
+// then has to pass the stats account and is recorded in it
+pub fn initialize_collection_stats(
+    ctx: Context<InitializeCollectionStats>,
+    collection: Pubkey,
+) -> Result<()> {
+    msg!("Initializing collection stats...");
+    let collection_stats = &mut ctx.accounts.collection_stats;
+    collection_stats.collection = collection;
+    collection_stats.bump = ctx.bumps.collection_stats;
+
+    msg!("Tracking sales of collection {}", collection);
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(collection: Pubkey)]
+pub struct InitializeCollectionStats<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + CollectionStats::MAX_SIZE,
+        seeds = [
+            COLLECTION_STATS_SEED.as_bytes(),
+            collection.as_ref(),
+        ],
+        bump,
+    )]
+    pub collection_stats: Account<'info, CollectionStats>,
+
+    pub system_program: Program<'info, System>,
+}
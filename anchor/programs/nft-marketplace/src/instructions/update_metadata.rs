@@ -0,0 +1,107 @@
+use {
+    anchor_lang::prelude::*,
+    anchor_spl::{
+        token::Mint,
+        metadata::{
+            update_metadata_accounts_v2,
+            UpdateMetadataAccountsV2,
+            Metadata,
+            MetadataAccount,
+            mpl_token_metadata::types::DataV2,
+        },
+    }
+};
+
+use crate::state::*;
+use crate::errors::*;
+
+pub fn update_metadata(
+    ctx: Context<UpdateMetadata>,
+    title: Option<String>,
+    uri: Option<String>,
+    seller_fee_basis_points: Option<u16>,
+    primary_sale_happened: Option<bool>,
+    new_update_authority: Option<Pubkey>,
+) -> Result<()> {
+    msg!("Updating metadata account...");
+    msg!("Metadata account address: {}", &ctx.accounts.metadata.key());
+
+    if let Some(fee) = seller_fee_basis_points {
+        require!(fee <= 10_000, NftMarketplaceError::InvalidBasisPoints);
+    }
+
+    // Metaplex pads the stored strings with zeroes, so strip them
+    // before handing the untouched fields back
+    let current = &ctx.accounts.metadata;
+    let data = DataV2 {
+        name: title.unwrap_or_else(|| current.name.trim_matches('\0').to_string()),
+        symbol: current.symbol.trim_matches('\0').to_string(),
+        uri: uri.unwrap_or_else(|| current.uri.trim_matches('\0').to_string()),
+        seller_fee_basis_points: seller_fee_basis_points.unwrap_or(current.seller_fee_basis_points),
+        creators: current.creators.clone(),
+        collection: current.collection.clone(),
+        uses: current.uses.clone(),
+    };
+
+    update_metadata_accounts_v2(
+        CpiContext::new(
+            ctx.accounts.token_metadata_program.to_account_info(),
+            UpdateMetadataAccountsV2 {
+                metadata: ctx.accounts.metadata.to_account_info(),
+                update_authority: ctx.accounts.update_authority.to_account_info(),
+            }
+        ),
+        new_update_authority,
+        Some(data.clone()),
+        primary_sale_happened,
+        None,
+    )?;
+
+    msg!("Metadata updated successfully");
+
+    let clock = Clock::get()?;
+    emit!(MetadataUpdatedEvent {
+        version: EVENT_VERSION,
+        mint: ctx.accounts.mint.key(),
+        update_authority: new_update_authority.unwrap_or(ctx.accounts.update_authority.key()),
+        name: data.name,
+        uri: data.uri,
+        seller_fee_basis_points: data.seller_fee_basis_points,
+        primary_sale_happened: primary_sale_happened.unwrap_or(current.primary_sale_happened),
+        slot: clock.slot,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct UpdateMetadata<'info> {
+    pub update_authority: Signer<'info>,
+
+    pub mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [b"metadata".as_ref(), token_metadata_program.key().as_ref(), mint.key().as_ref()],
+        bump,
+        seeds::program = token_metadata_program.key(),
+        constraint = metadata.update_authority == update_authority.key() @ NftMarketplaceError::InvalidUpdateAuthority,
+    )]
+    pub metadata: Account<'info, MetadataAccount>,
+
+    pub token_metadata_program: Program<'info, Metadata>,
+}
+
+#[event]
+pub struct MetadataUpdatedEvent {
+    pub version: u8,
+    pub mint: Pubkey,
+    pub update_authority: Pubkey,
+    pub name: String,
+    pub uri: String,
+    pub seller_fee_basis_points: u16,
+    pub primary_sale_happened: bool,
+    pub slot: u64,
+    pub timestamp: i64,
+}
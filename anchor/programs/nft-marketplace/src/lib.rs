@@ -1,3 +1,6 @@
+// Anchor 0.30 macros expand to cfgs this crate does not declare as features
+#![allow(unexpected_cfgs)]
+
 use anchor_lang::prelude::*;
 
 use crate::instructions::*;
@@ -5,6 +8,7 @@ use crate::instructions::*;
 pub mod instructions;
 pub mod state;
 pub mod errors;
+pub mod utils;
 
 declare_id!("hPd5fM2UuWmU36aE1Cx3HmhScY9fWFswVwe53R2HWZs");
 
@@ -12,6 +16,23 @@ declare_id!("hPd5fM2UuWmU36aE1Cx3HmhScY9fWFswVwe53R2HWZs");
 pub mod nft_marketplace {
     use super::*;
 
+    // One-time marketplace config, callable by the program upgrade authority
+    pub fn initialize_marketplace(
+        ctx: Context<InitializeMarketplace>,
+        treasury: Pubkey,
+        fee_bps: u16,
+        referral_bps: u16,
+    ) -> Result<()> {
+        marketplace::initialize_marketplace(ctx, treasury, fee_bps, referral_bps)
+    }
+
+    pub fn update_marketplace(
+        ctx: Context<UpdateMarketplace>,
+        params: UpdateMarketplaceParams,
+    ) -> Result<()> {
+        marketplace::update_marketplace(ctx, params)
+    }
+
     // XXX: These 2 intructions - mint and metadata were
     //      separated because transaction size was exceeded
     pub fn mint(
@@ -20,26 +41,440 @@ pub mod nft_marketplace {
         mint::mint(ctx)
     }
 
-    pub fn create_metadata(
-        ctx: Context<CreateMetadata>,
+    pub fn create_metadata<'info>(
+        ctx: Context<'_, '_, 'info, 'info, CreateMetadata<'info>>,
         title: String,
         uri: String,
+        non_transferable: bool,
+        additional_creators: Vec<CreatorShare>,
+    ) -> Result<()> {
+        metadata::create_metadata(ctx, title, uri, non_transferable, additional_creators)
+    }
+
+    // Verify yourself as a creator listed in the metadata of an NFT
+    pub fn sign_as_creator(
+        ctx: Context<SignAsCreator>,
+    ) -> Result<()> {
+        metadata::sign_as_creator(ctx)
+    }
+
+    // Create a launchpad drop for primary sales
+    pub fn initialize_drop(
+        ctx: Context<InitializeDrop>,
+        drop_id: u64,
+        params: DropParams,
+    ) -> Result<()> {
+        drop::initialize_drop(ctx, drop_id, params)
+    }
+
+    // Pay the drop price and mint + create metadata in one go,
+    // gated by the allowlist merkle proof when the drop has one
+    pub fn mint_from_drop(
+        ctx: Context<MintFromDrop>,
+        proof: Vec<[u8; 32]>,
+    ) -> Result<()> {
+        drop::mint_from_drop(ctx, proof)
+    }
+
+    // Update the metadata of a drop NFT, signed by the drop PDA
+    pub fn update_drop_metadata(
+        ctx: Context<UpdateDropMetadata>,
+        uri: Option<String>,
+        new_update_authority: Option<Pubkey>,
+    ) -> Result<()> {
+        drop::update_drop_metadata(ctx, uri, new_update_authority)
+    }
+
+    // Change title, URI, royalties or hand off the update authority
+    pub fn update_metadata(
+        ctx: Context<UpdateMetadata>,
+        title: Option<String>,
+        uri: Option<String>,
+        seller_fee_basis_points: Option<u16>,
+        primary_sale_happened: Option<bool>,
+        new_update_authority: Option<Pubkey>,
     ) -> Result<()> {
-        metadata::create_metadata(ctx, title, uri)
+        update_metadata::update_metadata(
+            ctx,
+            title,
+            uri,
+            seller_fee_basis_points,
+            primary_sale_happened,
+            new_update_authority,
+        )
     }
 
-    // List NFT for sale
+    // Burn NFT and reclaim the rent of its token, metadata and edition accounts
+    pub fn burn_nft(
+        ctx: Context<BurnNft>,
+    ) -> Result<()> {
+        burn::burn(ctx)
+    }
+
+    // List NFT for sale, the owner may be a PDA signing through CPI
     pub fn list_nft(
         ctx: Context<ListNft>,
         price: u64,
+        requires_attestation: bool,
     ) -> Result<()> {
-        list::list_nft(ctx, price)
+        list::list_nft(ctx, price, requires_attestation)
     }
 
-    // Buy listed NFT
+    // List NFT at a USD price, paid in SOL at the feed price of the purchase
+    pub fn list_nft_usd(
+        ctx: Context<ListNft>,
+        usd_price: u64,
+        requires_attestation: bool,
+    ) -> Result<()> {
+        list::list_nft_usd(ctx, usd_price, requires_attestation)
+    }
+
+    // Buy listed NFT, the buyer may be a PDA signing through CPI
     pub fn buy_nft(
         ctx: Context<BuyNft>,
+        max_price: Option<u64>,
+    ) -> Result<()> {
+        buy::buy(ctx, max_price)
+    }
+
+    // Staking pool for a verified collection, rewards are minted by the pool
+    pub fn initialize_stake_pool(
+        ctx: Context<InitializeStakePool>,
+        reward_rate: u64,
+    ) -> Result<()> {
+        stake::initialize_stake_pool(ctx, reward_rate)
+    }
+
+    pub fn stake_nft(
+        ctx: Context<StakeNft>,
+    ) -> Result<()> {
+        stake::stake_nft(ctx)
+    }
+
+    pub fn claim_rewards(
+        ctx: Context<ClaimRewards>,
+    ) -> Result<()> {
+        stake::claim_rewards(ctx)
+    }
+
+    pub fn unstake_nft(
+        ctx: Context<UnstakeNft>,
+    ) -> Result<()> {
+        stake::unstake_nft(ctx)
+    }
+
+    // Escrow NFT to be leased out for a daily rate
+    pub fn list_for_rent(
+        ctx: Context<ListForRent>,
+        daily_rate: u64,
+        max_days: u16,
+    ) -> Result<()> {
+        rent::list_for_rent(ctx, daily_rate, max_days)
+    }
+
+    // Pay the whole lease up-front and receive the NFT frozen in place
+    pub fn rent_nft(
+        ctx: Context<RentNft>,
+        days: u16,
+    ) -> Result<()> {
+        rent::rent_nft(ctx, days)
+    }
+
+    // Return the NFT to its owner, permissionless once the lease expired
+    pub fn end_rental(
+        ctx: Context<EndRental>,
+    ) -> Result<()> {
+        rent::end_rental(ctx)
+    }
+
+    // Lock NFT in the vault and mint fraction tokens to the owner
+    pub fn fractionalize(
+        ctx: Context<Fractionalize>,
+        fraction_supply: u64,
+        reserve_price: u64,
+    ) -> Result<()> {
+        fraction::fractionalize(ctx, fraction_supply, reserve_price)
+    }
+
+    // Pay the reserve price and take the NFT out of the vault
+    pub fn start_buyout(
+        ctx: Context<StartBuyout>,
+    ) -> Result<()> {
+        fraction::start_buyout(ctx)
+    }
+
+    // Burn fractions for a pro-rata share of the buyout
+    pub fn redeem(
+        ctx: Context<Redeem>,
+        amount: u64,
+    ) -> Result<()> {
+        fraction::redeem(ctx, amount)
+    }
+
+    // Lender escrows SOL to be borrowed against an NFT
+    pub fn create_loan_offer(
+        ctx: Context<CreateLoanOffer>,
+        offer_id: u64,
+        principal: u64,
+        interest_bps: u16,
+        duration: i64,
+        collection: Option<Pubkey>,
+        nft_mint: Option<Pubkey>,
+    ) -> Result<()> {
+        loan::create_loan_offer(ctx, offer_id, principal, interest_bps, duration, collection, nft_mint)
+    }
+
+    pub fn cancel_loan_offer(
+        ctx: Context<CancelLoanOffer>,
+    ) -> Result<()> {
+        loan::cancel_loan_offer(ctx)
+    }
+
+    // Lock NFT as collateral and receive the principal
+    pub fn take_loan(
+        ctx: Context<TakeLoan>,
+    ) -> Result<()> {
+        loan::take_loan(ctx)
+    }
+
+    // Pay back principal plus interest before the deadline to get the NFT back
+    pub fn repay_loan(
+        ctx: Context<RepayLoan>,
+    ) -> Result<()> {
+        loan::repay_loan(ctx)
+    }
+
+    // Lender claims the collateral after a missed deadline
+    pub fn foreclose_loan(
+        ctx: Context<ForecloseLoan>,
+    ) -> Result<()> {
+        loan::foreclose_loan(ctx)
+    }
+
+    // Lock a listing to the buyer with a deposit
+    pub fn start_installment_purchase(
+        ctx: Context<StartInstallmentPurchase>,
+        deposit: u64,
+        duration: i64,
+    ) -> Result<()> {
+        installment::start_installment_purchase(ctx, deposit, duration)
+    }
+
+    // Forward a payment to the seller, the last one releases the NFT
+    pub fn pay_installment(
+        ctx: Context<PayInstallment>,
+        amount: u64,
+    ) -> Result<()> {
+        installment::pay_installment(ctx, amount)
+    }
+
+    // Return the NFT to the seller after a missed deadline, payments are forfeited
+    pub fn forfeit_installment(
+        ctx: Context<ForfeitInstallment>,
+    ) -> Result<()> {
+        installment::forfeit_installment(ctx)
+    }
+
+    // Buy listed NFT with the payment held in escrow for the dispute window
+    pub fn buy_nft_escrowed(
+        ctx: Context<BuyNftEscrowed>,
+    ) -> Result<()> {
+        settlement::buy_nft_escrowed(ctx)
+    }
+
+    // Buyer confirms early, or anyone once an undisputed window is over
+    pub fn release_settlement(
+        ctx: Context<SettleEscrow>,
+    ) -> Result<()> {
+        settlement::release_settlement(ctx)
+    }
+
+    pub fn open_dispute(
+        ctx: Context<OpenDispute>,
+    ) -> Result<()> {
+        settlement::open_dispute(ctx)
+    }
+
+    // Arbiter either pays the seller or refunds the buyer
+    pub fn resolve_dispute(
+        ctx: Context<SettleEscrow>,
+        refund_buyer: bool,
+    ) -> Result<()> {
+        settlement::resolve_dispute(ctx, refund_buyer)
+    }
+
+    // List several NFTs as one package for a single price
+    pub fn list_bundle<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ListBundle<'info>>,
+        bundle_id: u64,
+        price: u64,
+    ) -> Result<()> {
+        bundle::list_bundle(ctx, bundle_id, price)
+    }
+
+    // Buy every NFT of a bundle in one transaction
+    pub fn buy_bundle<'info>(
+        ctx: Context<'_, '_, 'info, 'info, BuyBundle<'info>>,
+    ) -> Result<()> {
+        bundle::buy_bundle(ctx)
+    }
+
+    // Delist a bundle and take its NFTs back
+    pub fn cancel_bundle<'info>(
+        ctx: Context<'_, '_, 'info, 'info, CancelBundle<'info>>,
+    ) -> Result<()> {
+        bundle::cancel_bundle(ctx)
+    }
+
+    // Escrow lamports for any verified NFT of a collection, optionally
+    // limited to the mints under `trait_root`
+    pub fn place_collection_bid(
+        ctx: Context<PlaceCollectionBid>,
+        collection: Pubkey,
+        trait_root: [u8; 32],
+        price: u64,
+    ) -> Result<()> {
+        collection_bid::place_collection_bid(ctx, collection, trait_root, price)
+    }
+
+    pub fn cancel_collection_bid(
+        ctx: Context<CancelCollectionBid>,
+    ) -> Result<()> {
+        collection_bid::cancel_collection_bid(ctx)
+    }
+
+    // Sell a collection member into an open bid
+    pub fn accept_collection_bid(
+        ctx: Context<AcceptCollectionBid>,
+        proof: Vec<[u8; 32]>,
+    ) -> Result<()> {
+        collection_bid::accept_collection_bid(ctx, proof)
+    }
+
+    // Rate the seller of a completed purchase, consumes the sale receipt
+    pub fn leave_review(
+        ctx: Context<LeaveReview>,
+        rating: u8,
+    ) -> Result<()> {
+        review::leave_review(ctx, rating)
+    }
+
+    // Compliance authority attests that the buyer may buy gated listings
+    pub fn issue_attestation(
+        ctx: Context<IssueAttestation>,
+        expires_at: i64,
+    ) -> Result<()> {
+        attestation::issue_attestation(ctx, expires_at)
+    }
+
+    pub fn revoke_attestation(
+        ctx: Context<RevokeAttestation>,
+    ) -> Result<()> {
+        attestation::revoke_attestation(ctx)
+    }
+
+    // Escrow the NFT and sell raffle tickets until `end_time`
+    pub fn create_raffle(
+        ctx: Context<CreateRaffle>,
+        ticket_price: u64,
+        max_tickets: u32,
+        end_time: i64,
+    ) -> Result<()> {
+        raffle::create_raffle(ctx, ticket_price, max_tickets, end_time)
+    }
+
+    pub fn buy_raffle_tickets(
+        ctx: Context<BuyRaffleTickets>,
+        count: u32,
+    ) -> Result<()> {
+        raffle::buy_raffle_tickets(ctx, count)
+    }
+
+    pub fn cancel_raffle(
+        ctx: Context<CancelRaffle>,
+    ) -> Result<()> {
+        raffle::cancel_raffle(ctx)
+    }
+
+    // Fix the future slot whose hash draws the winner, once the raffle ended
+    pub fn commit_raffle_draw(
+        ctx: Context<CommitRaffleDraw>,
+    ) -> Result<()> {
+        raffle::commit_raffle_draw(ctx)
+    }
+
+    // Permissionless once the draw slot is produced, pays the seller
+    pub fn draw_raffle(
+        ctx: Context<DrawRaffle>,
+    ) -> Result<()> {
+        raffle::draw_raffle(ctx)
+    }
+
+    pub fn claim_raffle_prize(
+        ctx: Context<ClaimRafflePrize>,
+    ) -> Result<()> {
+        raffle::claim_raffle_prize(ctx)
+    }
+
+    pub fn close_raffle_tickets(
+        ctx: Context<CloseRaffleTickets>,
+    ) -> Result<()> {
+        raffle::close_raffle_tickets(ctx)
+    }
+
+    // Pool lamports from several buyers towards a listed NFT
+    pub fn create_purchase_pool(
+        ctx: Context<CreatePurchasePool>,
+        deadline: i64,
+        reserve_price: u64,
+    ) -> Result<()> {
+        pool::create_purchase_pool(ctx, deadline, reserve_price)
+    }
+
+    pub fn contribute_to_pool(
+        ctx: Context<ContributeToPool>,
+        amount: u64,
+    ) -> Result<()> {
+        pool::contribute_to_pool(ctx, amount)
+    }
+
+    // Buy the NFT into a fraction vault once the pool is fully funded
+    pub fn execute_pool_purchase(
+        ctx: Context<ExecutePoolPurchase>,
+    ) -> Result<()> {
+        pool::execute_pool_purchase(ctx)
+    }
+
+    pub fn claim_pool_fractions(
+        ctx: Context<ClaimPoolFractions>,
+    ) -> Result<()> {
+        pool::claim_pool_fractions(ctx)
+    }
+
+    pub fn withdraw_from_pool(
+        ctx: Context<WithdrawFromPool>,
+    ) -> Result<()> {
+        pool::withdraw_from_pool(ctx)
+    }
+
+    pub fn close_purchase_pool(
+        ctx: Context<ClosePurchasePool>,
+    ) -> Result<()> {
+        pool::close_purchase_pool(ctx)
+    }
+
+    // Realloc a listing created before listings were versioned
+    pub fn migrate_listing(
+        ctx: Context<MigrateListing>,
+    ) -> Result<()> {
+        migrate::migrate_listing(ctx)
+    }
+
+    // Start recording the sales of a collection
+    pub fn initialize_collection_stats(
+        ctx: Context<InitializeCollectionStats>,
+        collection: Pubkey,
     ) -> Result<()> {
-        buy::buy(ctx)
+        stats::initialize_collection_stats(ctx, collection)
     }
 }
@@ -2,6 +2,50 @@ use anchor_lang::prelude::*;
 
 pub const NFT_MARKET_PLACE_SEED: &str = "NFT_MARKETPLACE_EMSKIQ";
 pub const LISTED_NFT_SEED: &str = "LISTED_NFT_EMSKIQ_SEED";
+pub const MARKETPLACE_CONFIG_SEED: &str = "MARKETPLACE_CONFIG_EMSKIQ_SEED";
+pub const DROP_SEED: &str = "DROP_EMSKIQ_SEED";
+pub const DROP_MINT_RECORD_SEED: &str = "DROP_MINT_RECORD_EMSKIQ_SEED";
+pub const STAKE_POOL_SEED: &str = "STAKE_POOL_EMSKIQ_SEED";
+pub const STAKE_ENTRY_SEED: &str = "STAKE_ENTRY_EMSKIQ_SEED";
+pub const REWARD_MINT_SEED: &str = "REWARD_MINT_EMSKIQ_SEED";
+pub const RENTAL_SEED: &str = "RENTAL_EMSKIQ_SEED";
+pub const FRACTION_VAULT_SEED: &str = "FRACTION_VAULT_EMSKIQ_SEED";
+pub const LOAN_SEED: &str = "LOAN_EMSKIQ_SEED";
+pub const INSTALLMENT_SEED: &str = "INSTALLMENT_EMSKIQ_SEED";
+pub const SETTLEMENT_SEED: &str = "SETTLEMENT_EMSKIQ_SEED";
+pub const BUNDLE_SEED: &str = "BUNDLE_EMSKIQ_SEED";
+pub const COLLECTION_BID_SEED: &str = "COLLECTION_BID_EMSKIQ_SEED";
+pub const SALE_RECEIPT_SEED: &str = "SALE_RECEIPT_EMSKIQ_SEED";
+pub const REPUTATION_SEED: &str = "REPUTATION_EMSKIQ_SEED";
+pub const BUYER_ATTESTATION_SEED: &str = "BUYER_ATTESTATION_EMSKIQ_SEED";
+pub const RAFFLE_SEED: &str = "RAFFLE_EMSKIQ_SEED";
+pub const RAFFLE_TICKETS_SEED: &str = "RAFFLE_TICKETS_EMSKIQ_SEED";
+pub const PURCHASE_POOL_SEED: &str = "PURCHASE_POOL_EMSKIQ_SEED";
+pub const POOL_CONTRIBUTION_SEED: &str = "POOL_CONTRIBUTION_EMSKIQ_SEED";
+pub const MARKETPLACE_STATS_SEED: &str = "MARKETPLACE_STATS_EMSKIQ_SEED";
+pub const COLLECTION_STATS_SEED: &str = "COLLECTION_STATS_EMSKIQ_SEED";
+
+pub const NFT_SYMBOL: &str = "REAL-EST";
+
+pub const MAX_BPS: u16 = 10_000;
+
+// Installment purchases need at least a 10% deposit and must be paid off within 180 days
+pub const INSTALLMENT_MIN_DEPOSIT_BPS: u16 = 1_000;
+pub const INSTALLMENT_MAX_DURATION: i64 = 180 * 24 * 60 * 60;
+
+pub const DEFAULT_DISPUTE_WINDOW: i64 = 7 * 24 * 60 * 60;
+
+// Pyth oracle program owning the SOL/USD price accounts of USD listings
+pub const PRICE_FEED_PROGRAM_ID: Pubkey = pubkey!("FsJ3A3u2vn5cTVofAjvy6y5kwABJAqYWpe4975bi2epH");
+// USD listings only sell against a recent price with a confidence interval below 2%.
+// New configs start with the default max price age, the authority can change it
+pub const DEFAULT_MAX_PRICE_AGE: i64 = 60;
+pub const MAX_PRICE_CONF_BPS: u16 = 200;
+pub const USD_PRICE_DECIMALS: u32 = 6;
+
+// Bumped whenever the layout of any emitted event changes,
+// so off-chain decoders can tell the versions apart
+pub const EVENT_VERSION: u8 = 7;
 
 #[derive(AnchorDeserialize, AnchorSerialize, Clone)]
 pub enum ReactionType {
@@ -9,13 +53,453 @@ pub enum ReactionType {
     Dislike,
 }
 
+#[account]
+pub struct Marketplace {
+    pub authority: Pubkey,
+    // Receives the platform fee of every sale
+    pub treasury: Pubkey,
+    // Platform fee, in basis points of the sale price
+    pub fee_bps: u16,
+    // Part of the platform fee paid to the referrer, in basis points of the fee
+    pub referral_bps: u16,
+    // Resolves disputes of escrowed settlements
+    pub arbiter: Pubkey,
+    // Seconds an escrowed settlement can be disputed by the buyer
+    pub dispute_window: i64,
+    // Issues buyer attestations for listings that require them
+    pub compliance_authority: Pubkey,
+    // SOL/USD price account of USD listings, unset until the authority pins one
+    pub price_feed: Pubkey,
+    // Seconds a price of the feed stays fresh enough to price USD listings
+    pub max_price_age: i64,
+    pub bump: u8,
+}
+
+impl Marketplace {
+    pub const MAX_SIZE: usize = 32 + 32 + 2 + 2 + 32 + 8 + 32 + 32 + 8 + 1;
+
+    // Splits the sale price into (seller proceeds, treasury fee, referral fee)
+    pub fn split_price(&self, price: u64, with_referrer: bool) -> (u64, u64, u64) {
+        let fee = (price as u128 * self.fee_bps as u128 / MAX_BPS as u128) as u64;
+        let referral_fee = if with_referrer {
+            (fee as u128 * self.referral_bps as u128 / MAX_BPS as u128) as u64
+        } else {
+            0
+        };
+        (price - fee, fee - referral_fee, referral_fee)
+    }
+}
+
 #[account]
 pub struct ListedNft {
     pub mint: Pubkey,
     pub owner: Pubkey,
     pub price: u64,
+    // Only buyers with a valid attestation may buy the NFT
+    pub requires_attestation: bool,
+    // Layout version, unversioned listings have to go through `migrate_listing`
+    pub version: u8,
+    pub bump: u8,
+    // Migrated listings record the migration time, the original one is unknown
+    pub created_at: i64,
+    // Price in micro-USD for listings pegged to USD, 0 for lamport listings,
+    // converted with the SOL/USD feed pinned in the marketplace config
+    pub usd_price: u64,
+    // Room for new fields without another realloc
+    pub reserved: [u8; 56],
 }
 
 impl ListedNft {
-    pub const MAX_SIZE: usize = 32 + 32 + 8;
+    pub const CURRENT_VERSION: u8 = 1;
+    pub const MAX_SIZE: usize = 32 + 32 + 8 + 1 + 1 + 1 + 8 + 8 + 56;
+    // Unversioned layouts, before and after `requires_attestation` was added
+    pub const LEGACY_SIZES: [usize; 2] = [32 + 32 + 8, 32 + 32 + 8 + 1];
+}
+
+#[account]
+pub struct Drop {
+    pub creator: Pubkey,
+    // Receives the mint price of every NFT minted from the drop
+    pub treasury: Pubkey,
+    pub drop_id: u64,
+    pub supply_cap: u64,
+    pub minted: u64,
+    pub price: u64,
+    pub start_time: i64,
+    // 0 means no limit
+    pub per_wallet_limit: u16,
+    // All zeroes means the drop is open to everyone
+    pub merkle_root: [u8; 32],
+    pub name: String,
+    pub base_uri: String,
+    pub bump: u8,
+}
+
+impl Drop {
+    pub const MAX_NAME_LEN: usize = 24;
+    pub const MAX_BASE_URI_LEN: usize = 180;
+    pub const MAX_SIZE: usize = 32 + 32 + 8 + 8 + 8 + 8 + 8 + 2 + 32
+        + (4 + Self::MAX_NAME_LEN) + (4 + Self::MAX_BASE_URI_LEN) + 1;
+}
+
+#[account]
+pub struct DropMintRecord {
+    pub minted: u16,
+}
+
+impl DropMintRecord {
+    pub const MAX_SIZE: usize = 2;
+}
+
+#[account]
+pub struct StakePool {
+    pub authority: Pubkey,
+    // Only verified members of this collection can be staked
+    pub collection: Pubkey,
+    pub reward_mint: Pubkey,
+    // Reward tokens accrued per staked NFT per second
+    pub reward_rate: u64,
+    pub total_staked: u64,
+    pub bump: u8,
+}
+
+impl StakePool {
+    pub const MAX_SIZE: usize = 32 + 32 + 32 + 8 + 8 + 1;
+}
+
+#[account]
+pub struct StakeEntry {
+    pub pool: Pubkey,
+    pub owner: Pubkey,
+    pub mint: Pubkey,
+    pub staked_at: i64,
+    pub last_claimed_at: i64,
+    pub bump: u8,
+}
+
+impl StakeEntry {
+    pub const MAX_SIZE: usize = 32 + 32 + 32 + 8 + 8 + 1;
+}
+
+#[account]
+pub struct Rental {
+    pub owner: Pubkey,
+    pub mint: Pubkey,
+    pub daily_rate: u64,
+    pub max_days: u16,
+    // `None` while the NFT waits in escrow for a renter
+    pub renter: Option<Pubkey>,
+    pub rented_until: i64,
+    pub bump: u8,
+}
+
+impl Rental {
+    pub const MAX_SIZE: usize = 32 + 32 + 8 + 2 + (1 + 32) + 8 + 1;
+}
+
+#[derive(AnchorDeserialize, AnchorSerialize, Clone, PartialEq, Eq)]
+pub enum FractionVaultState {
+    Active,
+    BoughtOut,
+}
+
+#[account]
+pub struct FractionVault {
+    pub curator: Pubkey,
+    pub nft_mint: Pubkey,
+    pub fraction_mint: Pubkey,
+    pub fraction_supply: u64,
+    // Price at which anyone can buy the NFT out of the vault
+    pub reserve_price: u64,
+    pub state: FractionVaultState,
+    pub buyer: Option<Pubkey>,
+    // Buyout lamports not yet redeemed by fraction holders
+    pub proceeds: u64,
+    // Paid for the vault, gets the rent back once every fraction is redeemed
+    pub rent_payer: Pubkey,
+    // Paid for the escrow token account, gets its rent back on the buyout
+    pub escrow_payer: Pubkey,
+    pub bump: u8,
+}
+
+impl FractionVault {
+    pub const MAX_SIZE: usize = 32 + 32 + 32 + 8 + 8 + 1 + (1 + 32) + 8 + 32 + 32 + 1;
+}
+
+#[derive(AnchorDeserialize, AnchorSerialize, Clone, PartialEq, Eq)]
+pub enum LoanState {
+    Offered,
+    Active,
+    Repaid,
+    Foreclosed,
+    Cancelled,
+}
+
+#[account]
+pub struct Loan {
+    pub lender: Pubkey,
+    pub offer_id: u64,
+    pub principal: u64,
+    // Flat interest over the whole loan, in basis points of the principal
+    pub interest_bps: u16,
+    // Loan duration in seconds, counted from when the borrower takes it
+    pub duration: i64,
+    // When set, only verified members of this collection are accepted
+    pub collection: Option<Pubkey>,
+    pub borrower: Option<Pubkey>,
+    // Set on offers for one specific NFT, otherwise once the loan is taken
+    pub nft_mint: Option<Pubkey>,
+    pub deadline: i64,
+    pub state: LoanState,
+    pub bump: u8,
+}
+
+impl Loan {
+    pub const MAX_SIZE: usize = 32 + 8 + 8 + 2 + 8 + (1 + 32) + (1 + 32) + (1 + 32) + 8 + 1 + 1;
+
+    pub fn repayment_amount(&self) -> u64 {
+        let interest = self.principal as u128 * self.interest_bps as u128 / MAX_BPS as u128;
+        self.principal + interest as u64
+    }
+}
+
+#[account]
+pub struct InstallmentPlan {
+    pub mint: Pubkey,
+    pub seller: Pubkey,
+    pub buyer: Pubkey,
+    pub price: u64,
+    pub paid: u64,
+    pub deadline: i64,
+    pub bump: u8,
+}
+
+impl InstallmentPlan {
+    pub const MAX_SIZE: usize = 32 + 32 + 32 + 8 + 8 + 8 + 1;
+}
+
+#[account]
+pub struct Settlement {
+    pub mint: Pubkey,
+    pub seller: Pubkey,
+    pub buyer: Pubkey,
+    // Lamports held in this account until the settlement is resolved
+    pub price: u64,
+    pub dispute_deadline: i64,
+    pub disputed: bool,
+    pub bump: u8,
+}
+
+impl Settlement {
+    pub const MAX_SIZE: usize = 32 + 32 + 32 + 8 + 8 + 1 + 1;
+}
+
+#[account]
+pub struct Bundle {
+    pub owner: Pubkey,
+    pub bundle_id: u64,
+    pub price: u64,
+    // Every mint is held by the program escrow until the bundle is bought
+    pub mints: Vec<Pubkey>,
+    pub bump: u8,
+}
+
+impl Bundle {
+    pub const MAX_MINTS: usize = 10;
+    pub const MAX_SIZE: usize = 32 + 8 + 8 + (4 + 32 * Self::MAX_MINTS) + 1;
+}
+
+#[account]
+pub struct CollectionBid {
+    pub bidder: Pubkey,
+    pub collection: Pubkey,
+    // Lamports held in this account on top of its rent
+    pub price: u64,
+    // Merkle root over the mints carrying the wanted attributes, zero for any member
+    pub trait_root: [u8; 32],
+    pub bump: u8,
+}
+
+impl CollectionBid {
+    pub const MAX_SIZE: usize = 32 + 32 + 8 + 32 + 1;
+}
+
+// Proof of a completed `buy_nft`, consumed by the buyer's review
+#[account]
+pub struct SaleReceipt {
+    pub mint: Pubkey,
+    pub seller: Pubkey,
+    pub buyer: Pubkey,
+    pub price: u64,
+    pub bump: u8,
+}
+
+impl SaleReceipt {
+    pub const MAX_SIZE: usize = 32 + 32 + 32 + 8 + 1;
+}
+
+#[account]
+pub struct Reputation {
+    pub seller: Pubkey,
+    pub review_count: u64,
+    pub rating_total: u64,
+    // Number of reviews per rating, index 0 holds the one-star reviews
+    pub rating_counts: [u64; 5],
+    // Average rating times 100, so 450 stands for 4.50 stars
+    pub average_rating: u16,
+    pub bump: u8,
+}
+
+impl Reputation {
+    pub const MIN_RATING: u8 = 1;
+    pub const MAX_RATING: u8 = 5;
+    pub const MAX_SIZE: usize = 32 + 8 + 8 + 8 * 5 + 2 + 1;
+
+    pub fn add_rating(&mut self, rating: u8) {
+        self.review_count += 1;
+        self.rating_total += rating as u64;
+        self.rating_counts[(rating - Self::MIN_RATING) as usize] += 1;
+        self.average_rating = (self.rating_total * 100 / self.review_count) as u16;
+    }
+}
+
+// Issued by the compliance authority once a buyer passed KYC
+#[account]
+pub struct BuyerAttestation {
+    pub buyer: Pubkey,
+    pub issuer: Pubkey,
+    pub expires_at: i64,
+    pub bump: u8,
+}
+
+impl BuyerAttestation {
+    pub const MAX_SIZE: usize = 32 + 32 + 8 + 1;
+}
+
+#[account]
+pub struct Raffle {
+    pub seller: Pubkey,
+    pub mint: Pubkey,
+    pub ticket_price: u64,
+    pub max_tickets: u32,
+    // Ticket sales are held in this account until the draw
+    pub tickets_sold: u32,
+    pub end_time: i64,
+    pub winning_ticket: Option<u32>,
+    // Slot whose hash picks the winner, committed after the end time, 0 until then
+    pub draw_slot: u64,
+    pub bump: u8,
+}
+
+impl Raffle {
+    // The draw slot lies far enough ahead that its hash is unknown when committing
+    pub const DRAW_DELAY_SLOTS: u64 = 4;
+    pub const MAX_SIZE: usize = 32 + 32 + 8 + 4 + 4 + 8 + (1 + 4) + 8 + 1;
+}
+
+// Tickets `start..start + count` bought by `buyer` in a single purchase
+#[account]
+pub struct RaffleTickets {
+    pub raffle: Pubkey,
+    // A later raffle of the same mint always ends later, so the end time
+    // tells apart tickets of different raffles sharing the raffle PDA
+    pub raffle_end_time: i64,
+    pub buyer: Pubkey,
+    pub start: u32,
+    pub count: u32,
+    pub bump: u8,
+}
+
+impl RaffleTickets {
+    pub const MAX_SIZE: usize = 32 + 8 + 32 + 4 + 4 + 1;
+
+    pub fn contains(&self, ticket: u32) -> bool {
+        ticket >= self.start && ticket - self.start < self.count
+    }
+}
+
+#[account]
+pub struct PurchasePool {
+    pub organizer: Pubkey,
+    pub mint: Pubkey,
+    // Listing price when the pool was created, the purchase only goes
+    // through at exactly this price
+    pub target: u64,
+    // Contributions held in this account until the purchase is executed
+    pub raised: u64,
+    pub deadline: i64,
+    // Buyout price of the fraction vault created by the purchase
+    pub reserve_price: u64,
+    pub executed: bool,
+    pub bump: u8,
+}
+
+impl PurchasePool {
+    pub const MAX_SIZE: usize = 32 + 32 + 8 + 8 + 8 + 8 + 1 + 1;
+}
+
+#[account]
+pub struct PoolContribution {
+    pub pool: Pubkey,
+    pub contributor: Pubkey,
+    // In lamports, also the number of fractions the contributor receives
+    pub amount: u64,
+    pub bump: u8,
+}
+
+impl PoolContribution {
+    pub const MAX_SIZE: usize = 32 + 32 + 8 + 1;
+}
+
+// Aggregate counters, created by the first listing or sale that needs them.
+// Listings that predate the account are not counted as active.
+#[account]
+pub struct MarketplaceStats {
+    pub total_listings: u64,
+    pub active_listings: u64,
+    // Completed sales of every kind, bundles included
+    pub total_sales: u64,
+    // Cumulative sale prices in lamports
+    pub total_volume: u64,
+    pub bump: u8,
+}
+
+impl MarketplaceStats {
+    pub const MAX_SIZE: usize = 8 + 8 + 8 + 8 + 1;
+
+    pub fn record_listing(&mut self) {
+        self.total_listings += 1;
+        self.active_listings += 1;
+    }
+
+    // Any flow that closes a listing, sold or not
+    pub fn record_delisting(&mut self) {
+        self.active_listings = self.active_listings.saturating_sub(1);
+    }
+
+    pub fn record_sale(&mut self, price: u64) {
+        self.total_sales += 1;
+        self.total_volume += price;
+    }
+}
+
+#[account]
+pub struct CollectionStats {
+    pub collection: Pubkey,
+    pub total_sales: u64,
+    pub total_volume: u64,
+    pub last_sale_price: u64,
+    pub last_sale_at: i64,
+    pub bump: u8,
+}
+
+impl CollectionStats {
+    pub const MAX_SIZE: usize = 32 + 8 + 8 + 8 + 8 + 1;
+
+    pub fn record_sale(&mut self, price: u64, timestamp: i64) {
+        self.total_sales += 1;
+        self.total_volume += price;
+        self.last_sale_price = price;
+        self.last_sale_at = timestamp;
+    }
 }
@@ -0,0 +1,214 @@
+use anchor_lang::{
+    prelude::*,
+    solana_program::keccak,
+};
+use anchor_spl::{
+    metadata::MetadataAccount,
+    token::{self, Mint, Token, TokenAccount},
+};
+
+use crate::state::*;
+use crate::errors::*;
+
+// Verifies a keccak256 merkle proof where each pair of nodes is hashed in
+// sorted order, which is what the common JS merkle tree libraries produce
+// with `sortPairs: true`.
+pub fn verify_merkle_proof(proof: &[[u8; 32]], root: &[u8; 32], leaf: [u8; 32]) -> bool {
+    let computed = proof.iter().fold(leaf, |node, sibling| {
+        if node <= *sibling {
+            keccak::hashv(&[&node, sibling]).to_bytes()
+        } else {
+            keccak::hashv(&[sibling, &node]).to_bytes()
+        }
+    });
+    computed == *root
+}
+
+// Leaf of an allowlist merkle tree is the hash of the wallet address
+pub fn merkle_leaf(key: &Pubkey) -> [u8; 32] {
+    keccak::hashv(&[key.as_ref()]).to_bytes()
+}
+
+// True when the metadata belongs to `collection` and the collection
+// authority has verified the membership
+pub fn is_verified_collection_member(metadata: &MetadataAccount, collection: &Pubkey) -> bool {
+    metadata
+        .collection
+        .as_ref()
+        .is_some_and(|c| c.verified && c.key == *collection)
+}
+
+// Sales of a verified collection member always move the collection stats
+// once somebody started tracking the collection, the buyer cannot leave them out
+pub fn record_collection_sale(
+    program_id: &Pubkey,
+    metadata: &MetadataAccount,
+    collection_stats: Option<&AccountInfo>,
+    price: u64,
+    timestamp: i64,
+) -> Result<()> {
+    let Some(collection) = metadata.collection.as_ref().filter(|c| c.verified) else {
+        require!(collection_stats.is_none(), NftMarketplaceError::NotInCollection);
+        return Ok(());
+    };
+
+    let (stats_key, _) = Pubkey::find_program_address(
+        &[COLLECTION_STATS_SEED.as_bytes(), collection.key.as_ref()],
+        program_id,
+    );
+    let collection_stats = collection_stats.ok_or(NftMarketplaceError::CollectionStatsRequired)?;
+    require_keys_eq!(collection_stats.key(), stats_key, NftMarketplaceError::NotInCollection);
+
+    // Still owned by the system program while the collection is not tracked
+    if collection_stats.owner != program_id {
+        return Ok(());
+    }
+
+    let mut data = collection_stats.try_borrow_mut_data()?;
+    let mut stats = CollectionStats::try_deserialize(&mut &data[..])?;
+    stats.record_sale(price, timestamp);
+    stats.try_serialize(&mut &mut data[..])?;
+
+    Ok(())
+}
+
+// A single token without decimals, anything else is a fungible or
+// multi-edition token that must not pass as an NFT
+pub fn is_nft(mint: &Mint) -> bool {
+    mint.decimals == 0 && mint.supply == 1
+}
+
+// Listings flagged with `requires_attestation` can only be bought by a
+// buyer holding an unexpired attestation from the current compliance authority
+pub fn check_buyer_attestation(
+    marketplace: &Marketplace,
+    listing: &ListedNft,
+    attestation: Option<&Account<BuyerAttestation>>,
+    buyer: &Pubkey,
+) -> Result<()> {
+    if !listing.requires_attestation {
+        return Ok(());
+    }
+
+    let attestation = attestation.ok_or(NftMarketplaceError::AttestationRequired)?;
+    require_keys_eq!(attestation.buyer, *buyer, NftMarketplaceError::AttestationRequired);
+    // Rotating the compliance authority revokes everything the old key issued
+    require_keys_eq!(
+        attestation.issuer,
+        marketplace.compliance_authority,
+        NftMarketplaceError::AttestationIssuerRevoked
+    );
+    require!(
+        attestation.expires_at > Clock::get()?.unix_timestamp,
+        NftMarketplaceError::AttestationExpired
+    );
+
+    Ok(())
+}
+
+// Sends an escrowed NFT out of the program PDA's token account
+pub fn transfer_from_escrow<'info>(
+    pda_token_account: &Account<'info, TokenAccount>,
+    to: &AccountInfo<'info>,
+    program_pda: &UncheckedAccount<'info>,
+    program_pda_bump: u8,
+    token_program: &Program<'info, Token>,
+) -> Result<()> {
+    let program_pda_seeds : &[&[&[u8]]] = &[&[NFT_MARKET_PLACE_SEED.as_bytes(), &[program_pda_bump]]];
+
+    token::transfer(
+        CpiContext::new(
+            token_program.to_account_info(),
+            token::Transfer {
+                from: pda_token_account.to_account_info(),
+                to: to.clone(),
+                authority: program_pda.to_account_info(),
+            })
+        .with_signer(program_pda_seeds),
+        1,
+    )
+}
+
+// Closes the emptied escrow token account so its rent goes back to whoever
+// paid for it when the NFT was escrowed. Listing flows recreate it on demand.
+pub fn close_escrow_account<'info>(
+    pda_token_account: &Account<'info, TokenAccount>,
+    destination: &AccountInfo<'info>,
+    program_pda: &UncheckedAccount<'info>,
+    program_pda_bump: u8,
+    token_program: &Program<'info, Token>,
+) -> Result<()> {
+    let program_pda_seeds : &[&[&[u8]]] = &[&[NFT_MARKET_PLACE_SEED.as_bytes(), &[program_pda_bump]]];
+
+    token::close_account(
+        CpiContext::new(
+            token_program.to_account_info(),
+            token::CloseAccount {
+                account: pda_token_account.to_account_info(),
+                destination: destination.clone(),
+                authority: program_pda.to_account_info(),
+            })
+        .with_signer(program_pda_seeds),
+    )
+}
+
+// Layout of a Pyth v2 price account, only the fields read below
+const PRICE_ACCOUNT_MAGIC: u32 = 0xa1b2c3d4;
+const PRICE_ACCOUNT_TYPE: u32 = 3;
+const PRICE_STATUS_TRADING: u32 = 1;
+const PRICE_ACCOUNT_MIN_LEN: usize = 240;
+
+fn read_le<const N: usize>(data: &[u8], offset: usize) -> [u8; N] {
+    data[offset..offset + N].try_into().unwrap()
+}
+
+// Converts a micro-USD amount to lamports with the aggregate price of the
+// SOL/USD account pinned in the marketplace config. Prices that are not trading,
+// older than the configured max price age or with a confidence interval wider than
+// `MAX_PRICE_CONF_BPS` are rejected.
+pub fn usd_to_lamports(
+    marketplace: &Marketplace,
+    price_feed: &AccountInfo,
+    usd_price: u64,
+    now: i64,
+) -> Result<u64> {
+    // Any Pyth account has the right owner, only the pinned key is SOL/USD
+    require_keys_eq!(price_feed.key(), marketplace.price_feed, NftMarketplaceError::InvalidPriceFeed);
+    require_keys_eq!(*price_feed.owner, PRICE_FEED_PROGRAM_ID, NftMarketplaceError::InvalidPriceFeed);
+    let data = price_feed.try_borrow_data()?;
+    require!(data.len() >= PRICE_ACCOUNT_MIN_LEN, NftMarketplaceError::InvalidPriceFeed);
+    require!(
+        u32::from_le_bytes(read_le(&data, 0)) == PRICE_ACCOUNT_MAGIC
+            && u32::from_le_bytes(read_le(&data, 8)) == PRICE_ACCOUNT_TYPE,
+        NftMarketplaceError::InvalidPriceFeed
+    );
+
+    let expo = i32::from_le_bytes(read_le(&data, 20));
+    let publish_time = i64::from_le_bytes(read_le(&data, 96));
+    let price = i64::from_le_bytes(read_le(&data, 208));
+    let conf = u64::from_le_bytes(read_le(&data, 216));
+    let status = u32::from_le_bytes(read_le(&data, 224));
+
+    require!(
+        status == PRICE_STATUS_TRADING && price > 0 && (-18..=0).contains(&expo),
+        NftMarketplaceError::InvalidPriceFeed
+    );
+    // A publish time ahead of the clock would otherwise count as fresh forever
+    require!(publish_time <= now, NftMarketplaceError::InvalidPriceFeed);
+    require!(now - publish_time <= marketplace.max_price_age, NftMarketplaceError::StalePrice);
+    require!(
+        conf as u128 * MAX_BPS as u128 <= price as u128 * MAX_PRICE_CONF_BPS as u128,
+        NftMarketplaceError::PriceConfidenceTooWide
+    );
+
+    // lamports = usd_price / 10^USD_PRICE_DECIMALS / (price * 10^expo) * 10^9
+    let lamports = 10u128
+        .checked_pow(9 + expo.unsigned_abs())
+        .and_then(|scale| (usd_price as u128).checked_mul(scale))
+        .map(|scaled| scaled / (price as u128 * 10u128.pow(USD_PRICE_DECIMALS)))
+        .and_then(|lamports| u64::try_from(lamports).ok())
+        .ok_or(NftMarketplaceError::InvalidUsdPrice)?;
+    require!(lamports > 0, NftMarketplaceError::InvalidUsdPrice);
+
+    Ok(lamports)
+}
@@ -0,0 +1,17 @@
+// Writes the fresh SOL/USD price account the test validator loads, published
+// now so it stays within the max price age the USD tests configure for the
+// whole run. It copies the stale fixture and only moves its publish time.
+const fs = require("fs");
+const path = require("path");
+
+const PUBLISH_TIME_OFFSET = 96;
+
+const stale = JSON.parse(fs.readFileSync(path.join(__dirname, "sol-usd-price-stale.json"), "utf8"));
+const data = Buffer.from(stale.account.data[0], "base64");
+data.writeBigInt64LE(BigInt(Math.floor(Date.now() / 1000)), PUBLISH_TIME_OFFSET);
+
+const fresh = {
+  pubkey: "Be4dy7EqixSFmNVHuhaQqC5bVC7WQQA9WiH8dwFDHer1",
+  account: { ...stale.account, data: [data.toString("base64"), "base64"] },
+};
+fs.writeFileSync(path.join(__dirname, "sol-usd-price.json"), JSON.stringify(fresh, null, 2));
@@ -1,9 +1,15 @@
 import * as anchor from "@coral-xyz/anchor";
 import { Program } from "@coral-xyz/anchor";
 import { ComputeBudgetProgram } from '@solana/web3.js';
+import { keccak_256 } from "@noble/hashes/sha3";
 import { NftMarketplace } from "../target/types/nft_marketplace";
+import { DaoTreasury } from "../target/types/dao_treasury";
 import { assert } from "chai";
 
+const TOKEN_METADATA_PROGRAM_ID = new anchor.web3.PublicKey(
+    "metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s"
+);
+
 describe("NFT Marketplace", () => {
     // Configure the client to use the local cluster.
     const testNftTitle = "EMSKIQQQ";
@@ -21,9 +27,187 @@ describe("NFT Marketplace", () => {
 
     const program = anchor.workspace.NftMarketplace as Program<NftMarketplace>;
 
-    const TOKEN_METADATA_PROGRAM_ID = new anchor.web3.PublicKey(
-        "metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s"
+    const MARKETPLACE_CONFIG_SEED = "MARKETPLACE_CONFIG_EMSKIQ_SEED";
+    const [marketplacePda] = anchor.web3.PublicKey.findProgramAddressSync(
+        [Buffer.from(MARKETPLACE_CONFIG_SEED)],
+        program.programId
     );
+    const treasuryKeypair = anchor.web3.Keypair.generate();
+
+    before(async () => {
+        // Treasury has to be rent exempt before it can receive small fees
+        await airdrop(provider.connection, treasuryKeypair.publicKey);
+
+        const [programData] = anchor.web3.PublicKey.findProgramAddressSync(
+            [program.programId.toBuffer()],
+            new anchor.web3.PublicKey("BPFLoaderUpgradeab1e11111111111111111111111")
+        );
+
+        await program.methods.initializeMarketplace(treasuryKeypair.publicKey, 0, 0)
+        .accounts({
+            authority: wallet.publicKey,
+            marketplace: marketplacePda,
+            program: program.programId,
+            programData: programData,
+        })
+        .rpc();
+    });
+
+    describe("USD listings", () => {
+        // Local SOL/USD accounts in the Pyth layout at $150, see tests/fixtures. The pre-test
+        // hook publishes the fresh one when the run starts, the stale one dates from 1970
+        // and the future one from 2100.
+        const priceFeed = new anchor.web3.PublicKey("Be4dy7EqixSFmNVHuhaQqC5bVC7WQQA9WiH8dwFDHer1");
+        const stalePriceFeed = new anchor.web3.PublicKey("9w1PgVLz6pvtFvr7Wsf35cnuqHvMjYcxRgPuow7ofG1M");
+        const futurePriceFeed = new anchor.web3.PublicKey("9CauTM5pJL5WUtff8SUudpmAy7FtXZ1cnrXxJo9qRzZz");
+        const updatePriceConfig = (feed: anchor.web3.PublicKey | null, maxPriceAge: anchor.BN | null) =>
+            program.methods.updateMarketplace({
+                newAuthority: null,
+                treasury: null,
+                feeBps: null,
+                referralBps: null,
+                arbiter: null,
+                disputeWindow: null,
+                complianceAuthority: null,
+                priceFeed: feed,
+                maxPriceAge: maxPriceAge,
+            })
+            .accounts({
+                authority: wallet.publicKey,
+                marketplace: marketplacePda,
+            })
+            .rpc();
+        const pinPriceFeed = (feed: anchor.web3.PublicKey) => updatePriceConfig(feed, null);
+
+        // The fresh account is never republished during the run, a day of max price age
+        // keeps it fresh wherever these tests end up in the suite
+        before(async () => {
+            let rejected = false;
+            try {
+                await updatePriceConfig(null, new anchor.BN(0));
+            } catch (err) {
+                rejected = true;
+                assert.include(err.message, "InvalidMaxPriceAge");
+            }
+            assert.isTrue(rejected, "A max price age of zero should have failed");
+
+            await updatePriceConfig(null, new anchor.BN(24 * 60 * 60));
+        });
+
+        after(async () => {
+            await updatePriceConfig(null, new anchor.BN(60));
+        });
+
+        it("List an NFT at a USD price and buy it at the feed price", async () => {
+            const { mintKeypair } = await mintNftWithMetadata(program, wallet.payer, "Pegged", testNftUri);
+            const mint = mintKeypair.publicKey;
+            const [nftAccountPda] = anchor.web3.PublicKey.findProgramAddressSync(
+                [Buffer.from(LISTED_NFT_SEED), mint.toBuffer()],
+                program.programId
+            );
+            const [programPda] = anchor.web3.PublicKey.findProgramAddressSync(
+                [Buffer.from(NFT_MARKET_PLACE_SEED)],
+                program.programId
+            );
+            const ownerTokenAccount = await anchor.utils.token.associatedAddress({ mint: mint, owner: wallet.publicKey });
+            const pdaTokenAccount = await anchor.utils.token.associatedAddress({ mint: mint, owner: programPda });
+            const listAccounts = (feed: anchor.web3.PublicKey) => ({
+                owner: wallet.publicKey,
+                mint: mint,
+                ownerTokenAccount: ownerTokenAccount,
+                nftListingAccount: nftAccountPda,
+                pdaTokenAccount: pdaTokenAccount,
+                programPda: programPda,
+                marketplace: marketplacePda,
+                priceFeed: feed,
+            });
+
+            // $75 in micro-USD, half a SOL at $150
+            const usdPrice = new anchor.BN(75_000_000);
+            const expectedLamports = anchor.web3.LAMPORTS_PER_SOL / 2;
+
+            // Each case pins the feed first, only the pinned account prices listings
+            const rejectedFeeds: [anchor.web3.PublicKey, anchor.web3.PublicKey, string][] = [
+                [stalePriceFeed, stalePriceFeed, "StalePrice"],
+                [futurePriceFeed, futurePriceFeed, "InvalidPriceFeed"],
+                [priceFeed, stalePriceFeed, "InvalidPriceFeed"],
+            ];
+            for (const [pinnedFeed, feed, error] of rejectedFeeds) {
+                await pinPriceFeed(pinnedFeed);
+                let rejected = false;
+                try {
+                    await program.methods.listNftUsd(usdPrice, false).accounts(listAccounts(feed)).rpc();
+                } catch (err) {
+                    rejected = true;
+                    assert.include(err.message, error);
+                }
+                assert.isTrue(rejected, `Listing against ${feed.toBase58()} should have failed`);
+            }
+
+            await program.methods.listNftUsd(usdPrice, false).accounts(listAccounts(priceFeed)).rpc();
+            const listing = await program.account.listedNft.fetch(nftAccountPda);
+            assert.strictEqual(listing.usdPrice.toString(), usdPrice.toString());
+
+            const buyerKeypair = anchor.web3.Keypair.generate();
+            await airdrop(provider.connection, buyerKeypair.publicKey);
+            const buyerTokenAccount = await anchor.utils.token.associatedAddress({
+                mint: mint,
+                owner: buyerKeypair.publicKey
+            });
+            const buyAccounts = {
+                buyer: buyerKeypair.publicKey,
+                seller: wallet.publicKey,
+                mint: mint,
+                marketplace: marketplacePda,
+                treasury: treasuryKeypair.publicKey,
+                nftListingAccount: nftAccountPda,
+                pdaTokenAccount: pdaTokenAccount,
+                buyerTokenAccount: buyerTokenAccount,
+                programPda: programPda,
+                priceFeed: priceFeed,
+            };
+
+            // The lamport price moves with SOL, so the buyer has to cap it
+            let rejected = false;
+            try {
+                await program.methods.buyNft(null)
+                .accounts(buyAccounts)
+                .signers([buyerKeypair])
+                .rpc();
+            } catch (err) {
+                rejected = true;
+                assert.include(err.message, "MaxPriceRequired");
+            }
+            assert.isTrue(rejected, "Buying a USD listing without a max price should have failed");
+
+            rejected = false;
+            try {
+                await program.methods.buyNft(new anchor.BN(expectedLamports - 1))
+                .accounts(buyAccounts)
+                .signers([buyerKeypair])
+                .rpc();
+            } catch (err) {
+                rejected = true;
+                assert.include(err.message, "PriceAboveMax");
+            }
+            assert.isTrue(rejected, "Buying above the max price should have failed");
+
+            await program.methods.buyNft(new anchor.BN(expectedLamports))
+            .accounts(buyAccounts)
+            .signers([buyerKeypair])
+            .rpc();
+
+            const [saleReceipt] = anchor.web3.PublicKey.findProgramAddressSync(
+                [Buffer.from("SALE_RECEIPT_EMSKIQ_SEED"), mint.toBuffer(), buyerKeypair.publicKey.toBuffer()],
+                program.programId
+            );
+            const receipt = await program.account.saleReceipt.fetch(saleReceipt);
+            assert.strictEqual(receipt.price.toNumber(), expectedLamports);
+            assert.strictEqual(
+                (await provider.connection.getTokenAccountBalance(buyerTokenAccount)).value.amount, "1"
+            );
+        }).timeout(20000);
+    });
 
     it("Mint and assign metadata to NFT", async () => {
         // Derive the mint address and the associated token account address
@@ -73,7 +257,7 @@ describe("NFT Marketplace", () => {
         .rpc();
 
         const tx_sig_metadata = await program.methods.createMetadata(
-            testNftTitle, testNftUri
+            testNftTitle, testNftUri, false, []
         )
         .accounts({
             mint: mintKeypair.publicKey,
@@ -108,6 +292,232 @@ describe("NFT Marketplace", () => {
         assert.isTrue(logsEmitted);
     }).timeout(9000);
 
+    it("Mint account is funded with the rent-exempt minimum only", async () => {
+        const { mintKeypair } = await mintNftWithMetadata(
+            program, wallet.payer, testNftTitle, testNftUri
+        );
+
+        const mintAccountInfo = await provider.connection.getAccountInfo(mintKeypair.publicKey);
+        const rentExemptMinimum = await provider.connection.getMinimumBalanceForRentExemption(82);
+        assert.strictEqual(mintAccountInfo.lamports, rentExemptMinimum);
+    }).timeout(9000);
+
+    it("Update metadata URI as the update authority", async () => {
+        const { mintKeypair, metadataAddress } = await mintNftWithMetadata(
+            program, wallet.payer, testNftTitle, testNftUri
+        );
+
+        const newUri = testNftUri + "?v=2";
+        const txSignatureUpdate = await program.methods.updateMetadata(
+            null, newUri, 500, null, null
+        )
+        .accounts({
+            updateAuthority: wallet.publicKey,
+            mint: mintKeypair.publicKey,
+            metadata: metadataAddress,
+            tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+        })
+        .rpc();
+
+        const txUpdate = await provider.connection.getParsedTransaction(txSignatureUpdate, "confirmed");
+        const eventParser = new anchor.EventParser(program.programId, new anchor.BorshCoder(program.idl));
+        let logsEmitted = false;
+        for (let event of eventParser.parseLogs(txUpdate.meta.logMessages)) {
+            logsEmitted = true;
+            assert.equal(event.data.uri, newUri);
+            assert.equal(event.data.name, testNftTitle);
+            assert.equal(event.data.sellerFeeBasisPoints, 500);
+        }
+        assert.isTrue(logsEmitted);
+
+        // Anyone else must be rejected
+        const stranger = anchor.web3.Keypair.generate();
+        try {
+            await program.methods.updateMetadata(null, testNftUri, null, null, null)
+            .accounts({
+                updateAuthority: stranger.publicKey,
+                mint: mintKeypair.publicKey,
+                metadata: metadataAddress,
+                tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+            })
+            .signers([stranger])
+            .rpc();
+            assert.fail("Update should have failed for a non update authority");
+        } catch (err: any) {
+            assert.include(err.message, "InvalidUpdateAuthority");
+        }
+    }).timeout(9000);
+
+    it("Burn NFT and reclaim rent", async () => {
+        const { mintKeypair, tokenAddress, metadataAddress, masterEditionAddress } = await mintNftWithMetadata(
+            program, wallet.payer, testNftTitle, testNftUri
+        );
+
+        const [nftAccountPda] = await anchor.web3.PublicKey.findProgramAddress(
+            [Buffer.from(LISTED_NFT_SEED), mintKeypair.publicKey.toBuffer()],
+            program.programId
+        );
+
+        await program.methods.burnNft()
+        .accounts({
+            owner: wallet.publicKey,
+            mint: mintKeypair.publicKey,
+            ownerTokenAccount: tokenAddress,
+            metadata: metadataAddress,
+            masterEdition: masterEditionAddress,
+            collectionMetadata: null,
+            nftListingAccount: nftAccountPda,
+            tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+        })
+        .rpc();
+
+        assert.isNull(await provider.connection.getAccountInfo(tokenAddress));
+        assert.isNull(await provider.connection.getAccountInfo(metadataAddress));
+        assert.isNull(await provider.connection.getAccountInfo(masterEditionAddress));
+    }).timeout(9000);
+
+    it("Mint from a paid drop and reject wallets outside the allowlist", async () => {
+        const DROP_SEED = "DROP_EMSKIQ_SEED";
+        const DROP_MINT_RECORD_SEED = "DROP_MINT_RECORD_EMSKIQ_SEED";
+
+        const treasury = anchor.web3.Keypair.generate();
+        const minterKeypair = anchor.web3.Keypair.generate();
+        await airdrop(provider.connection, minterKeypair.publicKey, anchor.web3.LAMPORTS_PER_SOL * 2);
+
+        const dropPrice = new anchor.BN(anchor.web3.LAMPORTS_PER_SOL * 0.25);
+
+        const createDrop = async (dropId: anchor.BN, merkleRoot: number[]) => {
+            const [dropPda] = await anchor.web3.PublicKey.findProgramAddress(
+                [Buffer.from(DROP_SEED), wallet.publicKey.toBuffer(), dropId.toArrayLike(Buffer, "le", 8)],
+                program.programId
+            );
+            await program.methods.initializeDrop(dropId, {
+                treasury: treasury.publicKey,
+                supplyCap: new anchor.BN(10),
+                price: dropPrice,
+                startTime: new anchor.BN(0),
+                perWalletLimit: 1,
+                merkleRoot,
+                name: "Tower A",
+                baseUri: "https://example.com/tower-a/",
+            })
+            .accounts({
+                creator: wallet.publicKey,
+                drop: dropPda,
+            })
+            .rpc();
+            return dropPda;
+        };
+
+        const mintFromDrop = async (dropPda: anchor.web3.PublicKey) => {
+            const mintKeypair = anchor.web3.Keypair.generate();
+            const tokenAddress = await anchor.utils.token.associatedAddress({
+                mint: mintKeypair.publicKey,
+                owner: minterKeypair.publicKey
+            });
+            const [mintRecord] = await anchor.web3.PublicKey.findProgramAddress(
+                [Buffer.from(DROP_MINT_RECORD_SEED), dropPda.toBuffer(), minterKeypair.publicKey.toBuffer()],
+                program.programId
+            );
+            const metadataAddress = (await anchor.web3.PublicKey.findProgramAddress(
+                [Buffer.from("metadata"), TOKEN_METADATA_PROGRAM_ID.toBuffer(), mintKeypair.publicKey.toBuffer()],
+                TOKEN_METADATA_PROGRAM_ID
+            ))[0];
+            const masterEditionAddress = (await anchor.web3.PublicKey.findProgramAddress(
+                [Buffer.from("metadata"), TOKEN_METADATA_PROGRAM_ID.toBuffer(), mintKeypair.publicKey.toBuffer(), Buffer.from("edition")],
+                TOKEN_METADATA_PROGRAM_ID
+            ))[0];
+
+            await program.methods.mintFromDrop([])
+            .accounts({
+                minter: minterKeypair.publicKey,
+                drop: dropPda,
+                mintRecord: mintRecord,
+                treasury: treasury.publicKey,
+                mint: mintKeypair.publicKey,
+                tokenAccount: tokenAddress,
+                metadata: metadataAddress,
+                masterEdition: masterEditionAddress,
+                tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+            })
+            .preInstructions([ComputeBudgetProgram.setComputeUnitLimit({ units: 400_000 })])
+            .signers([minterKeypair, mintKeypair])
+            .rpc();
+            return { mintKeypair, tokenAddress, metadataAddress };
+        };
+
+        // The name of the last NFT must still fit into the Metaplex limit
+        const longNameDropId = new anchor.BN(Date.now() + 2);
+        const [longNameDrop] = anchor.web3.PublicKey.findProgramAddressSync(
+            [Buffer.from(DROP_SEED), wallet.publicKey.toBuffer(), longNameDropId.toArrayLike(Buffer, "le", 8)],
+            program.programId
+        );
+        let rejected = false;
+        try {
+            await program.methods.initializeDrop(longNameDropId, {
+                treasury: treasury.publicKey,
+                supplyCap: new anchor.BN(10_000_000),
+                price: dropPrice,
+                startTime: new anchor.BN(0),
+                perWalletLimit: 1,
+                merkleRoot: new Array(32).fill(0),
+                name: "Tower A Residences North",
+                baseUri: "https://example.com/tower-a/",
+            })
+            .accounts({
+                creator: wallet.publicKey,
+                drop: longNameDrop,
+            })
+            .rpc();
+        } catch (err) {
+            rejected = true;
+            assert.include(err.message, "DropNameTooLong");
+        }
+        assert.isTrue(rejected, "A name too long for the last NFT should have failed");
+
+        // Public drop: anyone can mint once
+        const publicDrop = await createDrop(new anchor.BN(Date.now()), new Array(32).fill(0));
+        const { mintKeypair, tokenAddress, metadataAddress } = await mintFromDrop(publicDrop);
+
+        const tokenBalance = await provider.connection.getTokenAccountBalance(tokenAddress);
+        assert.strictEqual(tokenBalance.value.amount, "1");
+        assert.strictEqual(await provider.connection.getBalance(treasury.publicKey), dropPrice.toNumber());
+
+        const dropAccount = await program.account.drop.fetch(publicDrop);
+        assert.strictEqual(dropAccount.minted.toNumber(), 1);
+
+        // The creator updates the NFT through the drop PDA, the update authority
+        const newUri = "https://example.com/tower-a/v2/1.json";
+        await program.methods.updateDropMetadata(newUri, null)
+        .accounts({
+            creator: wallet.publicKey,
+            drop: publicDrop,
+            mint: mintKeypair.publicKey,
+            metadata: metadataAddress,
+            tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+        })
+        .rpc();
+        const metadataInfo = await provider.connection.getAccountInfo(metadataAddress);
+        assert.include(metadataInfo.data.toString(), newUri);
+
+        // Second mint by the same wallet hits the per-wallet limit
+        try {
+            await mintFromDrop(publicDrop);
+            assert.fail("Second mint should have failed");
+        } catch (err: any) {
+            assert.include(err.message, "WalletMintLimitReached");
+        }
+
+        // Allowlisted drop with a root the minter is not part of
+        const allowlistDrop = await createDrop(new anchor.BN(Date.now() + 1), new Array(32).fill(7));
+        try {
+            await mintFromDrop(allowlistDrop);
+            assert.fail("Mint should have failed for a wallet outside the allowlist");
+        } catch (err: any) {
+            assert.include(err.message, "NotOnAllowlist");
+        }
+    }).timeout(20000);
+
     
     it("List and sell NFT", async () => {
         // **Setup**
@@ -189,7 +599,7 @@ describe("NFT Marketplace", () => {
             owner: programPda
         });
 
-        const txSignatureList = await program.methods.listNft(priceInLamports)
+        const txSignatureList = await program.methods.listNft(priceInLamports, false)
         .accounts({
             owner: sellerKeypair.publicKey,
             mint: mintKeypair.publicKey,
@@ -213,11 +623,14 @@ describe("NFT Marketplace", () => {
         const sellerInitialBalance = await provider.connection.getBalance(sellerKeypair.publicKey);
 
         // Buy the NFT
-        await program.methods.buyNft()
+        const txSignatureBuy = await program.methods.buyNft(null)
         .accounts({
             buyer: buyerKeypair.publicKey,
             seller: sellerKeypair.publicKey,
             mint: mintKeypair.publicKey,
+            marketplace: marketplacePda,
+            treasury: treasuryKeypair.publicKey,
+            referrer: null,
             nftListingAccount: nftAccountPda,
             pdaTokenAccount: pdaTokenAccount,
             buyerTokenAccount: buyerTokenAccount,
@@ -239,6 +652,21 @@ describe("NFT Marketplace", () => {
 
         assert.isTrue(sellerFinalBalance > expectedSellerBalance, "Seller should receive at least the sale amount");
 
+        // Verify the sale event carries the price breakdown
+        const txBuy = await provider.connection.getParsedTransaction(txSignatureBuy, "confirmed");
+        const eventParserBuy = new anchor.EventParser(program.programId, new anchor.BorshCoder(program.idl));
+        let soldEventEmitted = false;
+        for (let event of eventParserBuy.parseLogs(txBuy.meta.logMessages)) {
+            if (event.name !== "nftSoldEvent") continue;
+            soldEventEmitted = true;
+            assert.equal(event.data.version, 7);
+            assert.equal(event.data.price.toString(), priceInLamports.toString());
+            assert.equal(event.data.sellerProceeds.toString(), priceInLamports.toString());
+            assert.isNull(event.data.paymentMint);
+            assert.isTrue(event.data.timestamp.toNumber() > 0);
+        }
+        assert.isTrue(soldEventEmitted);
+
         // console.log("Seller initial balance:", sellerInitialBalance / anchor.web3.LAMPORTS_PER_SOL, "SOL");
         // console.log("Seller final balance:", sellerFinalBalance / anchor.web3.LAMPORTS_PER_SOL, "SOL");
 
@@ -252,112 +680,2198 @@ describe("NFT Marketplace", () => {
         }
     }).timeout(9000);
 
-     it("Attempt to buy an NFT with insufficient SOL", async () => {
-        try {
-            // Mint and list an NFT
-            const mintKeypair: anchor.web3.Keypair = anchor.web3.Keypair.generate();
-            const tokenAddress = await anchor.utils.token.associatedAddress({
-                mint: mintKeypair.publicKey,
-                owner: wallet.publicKey 
-            });
-
+    it("Buy NFT through a referrer and split the platform fee", async () => {
+        const { mintKeypair } = await mintNftWithMetadata(
+            program, wallet.payer, testNftTitle, testNftUri
+        );
+        const priceInLamports = new anchor.BN(anchor.web3.LAMPORTS_PER_SOL * 1);
+        const { nftAccountPda, programPda, pdaTokenAccount } = await listNft(
+            program, wallet.payer, mintKeypair.publicKey, priceInLamports
+        );
 
-            // Derive the metadata address
-            const metadataAddress = (await anchor.web3.PublicKey.findProgramAddress(
-                [
-                    Buffer.from("metadata"),
-                    TOKEN_METADATA_PROGRAM_ID.toBuffer(),
-                    mintKeypair.publicKey.toBuffer(),
-                ],
-                TOKEN_METADATA_PROGRAM_ID
-            ))[0];
+        // 5% platform fee, 20% of which goes to the referrer
+        await program.methods.updateMarketplace({
+            newAuthority: null,
+            treasury: null,
+            feeBps: 500,
+            referralBps: 2000,
+            arbiter: null,
+            disputeWindow: null,
+            complianceAuthority: null,
+            priceFeed: null,
+            maxPriceAge: null,
+        })
+        .accounts({
+            authority: wallet.publicKey,
+            marketplace: marketplacePda,
+        })
+        .rpc();
 
-            // Derive the master edition address
-            const masterEditionAddress = (await anchor.web3.PublicKey.findProgramAddress(
-                [
-                    Buffer.from("metadata"),
-                    TOKEN_METADATA_PROGRAM_ID.toBuffer(),
-                    mintKeypair.publicKey.toBuffer(),
-                    Buffer.from("edition"),
-                ],
-                TOKEN_METADATA_PROGRAM_ID
-            ))[0];
+        const buyerKeypair = anchor.web3.Keypair.generate();
+        const referrerKeypair = anchor.web3.Keypair.generate();
+        await airdrop(provider.connection, buyerKeypair.publicKey, anchor.web3.LAMPORTS_PER_SOL * 2);
+        await airdrop(provider.connection, referrerKeypair.publicKey);
 
-            // Mint the NFT
-            await program.methods.mint()
-                .accounts({
-                    mint: mintKeypair.publicKey,
-                    tokenAccount: tokenAddress,
-                    mintAuthority: wallet.publicKey, 
-                })
-                .signers([mintKeypair])
-                .rpc();
+        const buyerTokenAccount = await anchor.utils.token.associatedAddress({
+            mint: mintKeypair.publicKey,
+            owner: buyerKeypair.publicKey
+        });
 
-            await program.methods.createMetadata(
-                testNftTitle, testNftUri
-            )
-                .accounts({
-                    mint: mintKeypair.publicKey,
-                    mintAuthority: wallet.publicKey,
-                    metadata: metadataAddress,
-                    tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
-                    masterEdition: masterEditionAddress,
-                })
-                .signers([mintKeypair])
-                .rpc();
+        const treasuryInitialBalance = await provider.connection.getBalance(treasuryKeypair.publicKey);
+        const referrerInitialBalance = await provider.connection.getBalance(referrerKeypair.publicKey);
 
-            // List the NFT
-            const priceInLamports = new anchor.BN(anchor.web3.LAMPORTS_PER_SOL * 1); // 1 SOL
-            const [nftAccountPda, nftAccountBump] = await anchor.web3.PublicKey.findProgramAddress(
-                [Buffer.from(LISTED_NFT_SEED), mintKeypair.publicKey.toBuffer()],
-                program.programId
-            );
+        try {
+            await program.methods.buyNft(null)
+            .accounts({
+                buyer: buyerKeypair.publicKey,
+                seller: wallet.publicKey,
+                mint: mintKeypair.publicKey,
+                marketplace: marketplacePda,
+                treasury: treasuryKeypair.publicKey,
+                referrer: referrerKeypair.publicKey,
+                nftListingAccount: nftAccountPda,
+                pdaTokenAccount: pdaTokenAccount,
+                buyerTokenAccount: buyerTokenAccount,
+                programPda: programPda,
+            })
+            .signers([buyerKeypair])
+            .rpc();
+        } finally {
+            await program.methods.updateMarketplace({
+                newAuthority: null,
+                treasury: null,
+                feeBps: 0,
+                referralBps: 0,
+                arbiter: null,
+                disputeWindow: null,
+                complianceAuthority: null,
+                priceFeed: null,
+                maxPriceAge: null,
+            })
+            .accounts({
+                authority: wallet.publicKey,
+                marketplace: marketplacePda,
+            })
+            .rpc();
+        }
 
-            const [programPda, programPdaBump] = await anchor.web3.PublicKey.findProgramAddress(
-                [Buffer.from(NFT_MARKET_PLACE_SEED)],
-                program.programId
-            );
+        const fee = priceInLamports.toNumber() * 500 / 10000;
+        const referralFee = fee * 2000 / 10000;
 
-            const sellerTokenAccount = tokenAddress;
+        const referrerFinalBalance = await provider.connection.getBalance(referrerKeypair.publicKey);
+        assert.strictEqual(referrerFinalBalance - referrerInitialBalance, referralFee);
 
-            const pdaTokenAccount = await anchor.utils.token.associatedAddress({
-                mint: mintKeypair.publicKey,
-                owner: programPda
-            });
+        const treasuryFinalBalance = await provider.connection.getBalance(treasuryKeypair.publicKey);
+        assert.strictEqual(treasuryFinalBalance - treasuryInitialBalance, fee - referralFee);
 
-            await program.methods.listNft(priceInLamports)
-                .accounts({
-                    owner: wallet.publicKey,
-                    mint: mintKeypair.publicKey,
-                    ownerTokenAccount: sellerTokenAccount,
-                    nftListingAccount: nftAccountPda,
-                    pdaTokenAccount: pdaTokenAccount,
-                    programPda: programPda,
-                })
-                .signers([wallet.payer])
-                .rpc();
+        // The emptied escrow token account is closed after the sale
+        assert.isNull(await provider.connection.getAccountInfo(pdaTokenAccount));
+    }).timeout(15000);
 
-            // Create a buyer with insufficient SOL
-            const buyerKeypair = anchor.web3.Keypair.generate();
+    it("Attempt to stake an NFT outside the pool's collection", async () => {
+        const STAKE_POOL_SEED = "STAKE_POOL_EMSKIQ_SEED";
+        const STAKE_ENTRY_SEED = "STAKE_ENTRY_EMSKIQ_SEED";
+        const REWARD_MINT_SEED = "REWARD_MINT_EMSKIQ_SEED";
 
-            // Transfer SOL to buyer from provider's wallet
-            // (if airdroping is timeouting, pbly because you are not on localnet, but on devnet)
-            // await transferSol(provider, wallet.publicKey, buyerKeypair.publicKey, 0.01);
+        // Any NFT whose update authority is the wallet can serve as a collection
+        const collection = await mintNftWithMetadata(program, wallet.payer, "Collection", testNftUri);
 
-            // Airdrop minimal SOL to buyer (e.g., 0.001 SOL) -> Not working for me... :(
-            await airdrop(provider.connection, buyerKeypair.publicKey, anchor.web3.LAMPORTS_PER_SOL * 0.001);
+        const [stakePool] = anchor.web3.PublicKey.findProgramAddressSync(
+            [Buffer.from(STAKE_POOL_SEED), collection.mintKeypair.publicKey.toBuffer()],
+            program.programId
+        );
+        const [rewardMint] = anchor.web3.PublicKey.findProgramAddressSync(
+            [Buffer.from(REWARD_MINT_SEED), stakePool.toBuffer()],
+            program.programId
+        );
 
-            const buyerTokenAccount = await anchor.utils.token.associatedAddress({
+        await program.methods.initializeStakePool(new anchor.BN(10))
+        .accounts({
+            authority: wallet.publicKey,
+            collectionMint: collection.mintKeypair.publicKey,
+            collectionMetadata: collection.metadataAddress,
+            stakePool: stakePool,
+            rewardMint: rewardMint,
+            tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+        })
+        .rpc();
+
+        const stakePoolAccount = await program.account.stakePool.fetch(stakePool);
+        assert.strictEqual(stakePoolAccount.rewardMint.toString(), rewardMint.toString());
+
+        const { mintKeypair, tokenAddress, metadataAddress } = await mintNftWithMetadata(
+            program, wallet.payer, testNftTitle, testNftUri
+        );
+        const [stakeEntry] = anchor.web3.PublicKey.findProgramAddressSync(
+            [Buffer.from(STAKE_ENTRY_SEED), mintKeypair.publicKey.toBuffer()],
+            program.programId
+        );
+        const [programPda] = anchor.web3.PublicKey.findProgramAddressSync(
+            [Buffer.from(NFT_MARKET_PLACE_SEED)],
+            program.programId
+        );
+        const pdaTokenAccount = await anchor.utils.token.associatedAddress({
+            mint: mintKeypair.publicKey,
+            owner: programPda
+        });
+
+        try {
+            await program.methods.stakeNft()
+            .accounts({
+                owner: wallet.publicKey,
+                stakePool: stakePool,
+                mint: mintKeypair.publicKey,
+                metadata: metadataAddress,
+                ownerTokenAccount: tokenAddress,
+                stakeEntry: stakeEntry,
+                pdaTokenAccount: pdaTokenAccount,
+                programPda: programPda,
+                tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+            })
+            .rpc();
+            assert.fail("Staking should have failed for an NFT outside the collection");
+        } catch (err: any) {
+            assert.include(err.message, "NotInCollection");
+        }
+    }).timeout(15000);
+
+    it("Stake a collection member, claim rewards and unstake it", async () => {
+        const STAKE_POOL_SEED = "STAKE_POOL_EMSKIQ_SEED";
+        const STAKE_ENTRY_SEED = "STAKE_ENTRY_EMSKIQ_SEED";
+        const REWARD_MINT_SEED = "REWARD_MINT_EMSKIQ_SEED";
+        const rewardRate = 10;
+
+        const collection = await mintNftWithMetadata(program, wallet.payer, "Collection", testNftUri);
+        const [stakePool] = anchor.web3.PublicKey.findProgramAddressSync(
+            [Buffer.from(STAKE_POOL_SEED), collection.mintKeypair.publicKey.toBuffer()],
+            program.programId
+        );
+        const [rewardMint] = anchor.web3.PublicKey.findProgramAddressSync(
+            [Buffer.from(REWARD_MINT_SEED), stakePool.toBuffer()],
+            program.programId
+        );
+        await program.methods.initializeStakePool(new anchor.BN(rewardRate))
+        .accounts({
+            authority: wallet.publicKey,
+            collectionMint: collection.mintKeypair.publicKey,
+            collectionMetadata: collection.metadataAddress,
+            stakePool: stakePool,
+            rewardMint: rewardMint,
+            tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+        })
+        .rpc();
+
+        // Only the pool PDA can mint rewards
+        const rewardMintInfo = await provider.connection.getParsedAccountInfo(rewardMint);
+        const { mintAuthority } = (rewardMintInfo.value.data as anchor.web3.ParsedAccountData).parsed.info;
+        assert.strictEqual(mintAuthority, stakePool.toBase58());
+
+        const member = await mintNftWithMetadata(program, wallet.payer, "Member", testNftUri);
+        await verifyCollection(provider, member.metadataAddress, collection);
+        const mint = member.mintKeypair.publicKey;
+
+        const [stakeEntry] = anchor.web3.PublicKey.findProgramAddressSync(
+            [Buffer.from(STAKE_ENTRY_SEED), mint.toBuffer()],
+            program.programId
+        );
+        const [programPda] = anchor.web3.PublicKey.findProgramAddressSync(
+            [Buffer.from(NFT_MARKET_PLACE_SEED)],
+            program.programId
+        );
+        const pdaTokenAccount = await anchor.utils.token.associatedAddress({ mint: mint, owner: programPda });
+        const rewardAccount = await anchor.utils.token.associatedAddress({ mint: rewardMint, owner: wallet.publicKey });
+
+        await program.methods.stakeNft()
+        .accounts({
+            owner: wallet.publicKey,
+            stakePool: stakePool,
+            mint: mint,
+            metadata: member.metadataAddress,
+            ownerTokenAccount: member.tokenAddress,
+            stakeEntry: stakeEntry,
+            pdaTokenAccount: pdaTokenAccount,
+            programPda: programPda,
+            tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+        })
+        .rpc();
+
+        const { stakedAt } = await program.account.stakeEntry.fetch(stakeEntry);
+        assert.strictEqual((await provider.connection.getTokenAccountBalance(pdaTokenAccount)).value.amount, "1");
+        assert.strictEqual((await program.account.stakePool.fetch(stakePool)).totalStaked.toNumber(), 1);
+
+        // Rewards accrue per second of the validator clock
+        await new Promise((resolve) => setTimeout(resolve, 3000));
+        await program.methods.claimRewards()
+        .accounts({
+            owner: wallet.publicKey,
+            stakePool: stakePool,
+            stakeEntry: stakeEntry,
+            rewardMint: rewardMint,
+            ownerRewardAccount: rewardAccount,
+        })
+        .rpc();
+
+        const { lastClaimedAt } = await program.account.stakeEntry.fetch(stakeEntry);
+        const claimed = lastClaimedAt.sub(stakedAt).toNumber() * rewardRate;
+        assert.isAbove(claimed, 0);
+        assert.strictEqual(
+            (await provider.connection.getTokenAccountBalance(rewardAccount)).value.amount, claimed.toString()
+        );
+
+        await new Promise((resolve) => setTimeout(resolve, 2000));
+        const txSignature = await program.methods.unstakeNft()
+        .accounts({
+            owner: wallet.publicKey,
+            stakePool: stakePool,
+            stakeEntry: stakeEntry,
+            mint: mint,
+            rewardMint: rewardMint,
+            ownerRewardAccount: rewardAccount,
+            ownerTokenAccount: member.tokenAddress,
+            pdaTokenAccount: pdaTokenAccount,
+            programPda: programPda,
+        })
+        .rpc({ commitment: "confirmed" });
+
+        // Unstaking pays out the rest, so the owner holds the rewards of the whole stake
+        const tx = await provider.connection.getParsedTransaction(txSignature, "confirmed");
+        const eventParser = new anchor.EventParser(program.programId, new anchor.BorshCoder(program.idl));
+        const unstaked = [...eventParser.parseLogs(tx.meta.logMessages)].find((event) => event.name === "nftUnstakedEvent");
+        const total = unstaked.data.stakedSeconds.toNumber() * rewardRate;
+        assert.isAbove(total, claimed);
+        assert.strictEqual((await provider.connection.getTokenAccountBalance(rewardAccount)).value.amount, total.toString());
+        const rewardSupply = await provider.connection.getTokenSupply(rewardMint);
+        assert.strictEqual(rewardSupply.value.amount, total.toString());
+
+        assert.strictEqual((await provider.connection.getTokenAccountBalance(member.tokenAddress)).value.amount, "1");
+        assert.isNull(await provider.connection.getAccountInfo(pdaTokenAccount));
+        assert.isNull(await provider.connection.getAccountInfo(stakeEntry));
+        assert.strictEqual((await program.account.stakePool.fetch(stakePool)).totalStaked.toNumber(), 0);
+    }).timeout(30000);
+
+    it("Rent out an NFT and refuse to end the lease early", async () => {
+        const RENTAL_SEED = "RENTAL_EMSKIQ_SEED";
+
+        const { mintKeypair, tokenAddress, metadataAddress, masterEditionAddress } = await mintNftWithMetadata(
+            program, wallet.payer, testNftTitle, testNftUri
+        );
+        const [rental] = anchor.web3.PublicKey.findProgramAddressSync(
+            [Buffer.from(RENTAL_SEED), mintKeypair.publicKey.toBuffer()],
+            program.programId
+        );
+        const [programPda] = anchor.web3.PublicKey.findProgramAddressSync(
+            [Buffer.from(NFT_MARKET_PLACE_SEED)],
+            program.programId
+        );
+        const pdaTokenAccount = await anchor.utils.token.associatedAddress({
+            mint: mintKeypair.publicKey,
+            owner: programPda
+        });
+
+        const dailyRate = new anchor.BN(anchor.web3.LAMPORTS_PER_SOL * 0.01);
+        await program.methods.listForRent(dailyRate, 30)
+        .accounts({
+            owner: wallet.publicKey,
+            mint: mintKeypair.publicKey,
+            ownerTokenAccount: tokenAddress,
+            rental: rental,
+            pdaTokenAccount: pdaTokenAccount,
+            programPda: programPda,
+        })
+        .rpc();
+
+        const renterKeypair = anchor.web3.Keypair.generate();
+        await airdrop(provider.connection, renterKeypair.publicKey);
+        const renterTokenAccount = await anchor.utils.token.associatedAddress({
+            mint: mintKeypair.publicKey,
+            owner: renterKeypair.publicKey
+        });
+
+        const ownerInitialBalance = await provider.connection.getBalance(wallet.publicKey);
+        const escrowRent = await provider.connection.getBalance(pdaTokenAccount);
+
+        await program.methods.rentNft(3)
+        .accounts({
+            renter: renterKeypair.publicKey,
+            owner: wallet.publicKey,
+            mint: mintKeypair.publicKey,
+            rental: rental,
+            pdaTokenAccount: pdaTokenAccount,
+            renterTokenAccount: renterTokenAccount,
+            metadata: metadataAddress,
+            masterEdition: masterEditionAddress,
+            programPda: programPda,
+            tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+        })
+        .signers([renterKeypair])
+        .rpc();
+
+        // The owner gets the rent up-front and the rent of the emptied escrow account
+        const ownerFinalBalance = await provider.connection.getBalance(wallet.publicKey);
+        assert.strictEqual(ownerFinalBalance - ownerInitialBalance, dailyRate.toNumber() * 3 + escrowRent);
+        assert.isNull(await provider.connection.getAccountInfo(pdaTokenAccount));
+
+        const renterTokenAccountInfo = await provider.connection.getParsedAccountInfo(renterTokenAccount);
+        const parsed = (renterTokenAccountInfo.value.data as any).parsed.info;
+        assert.strictEqual(parsed.tokenAmount.amount, "1");
+        assert.strictEqual(parsed.state, "frozen");
+        assert.strictEqual(parsed.delegate, programPda.toString());
+
+        try {
+            await program.methods.endRental()
+            .accounts({
+                caller: wallet.publicKey,
+                owner: wallet.publicKey,
+                mint: mintKeypair.publicKey,
+                rental: rental,
+                pdaTokenAccount: null,
+                renterTokenAccount: renterTokenAccount,
+                ownerTokenAccount: tokenAddress,
+                metadata: metadataAddress,
+                masterEdition: masterEditionAddress,
+                programPda: programPda,
+                tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+            })
+            .rpc();
+            assert.fail("Ending the rental should have failed before expiry");
+        } catch (err: any) {
+            assert.include(err.message, "RentalNotExpired");
+        }
+    }).timeout(15000);
+
+    it("Fractionalize an NFT, buy it out and redeem the fractions", async () => {
+        const FRACTION_VAULT_SEED = "FRACTION_VAULT_EMSKIQ_SEED";
+
+        const { mintKeypair, tokenAddress } = await mintNftWithMetadata(
+            program, wallet.payer, testNftTitle, testNftUri
+        );
+        const [fractionVault] = anchor.web3.PublicKey.findProgramAddressSync(
+            [Buffer.from(FRACTION_VAULT_SEED), mintKeypair.publicKey.toBuffer()],
+            program.programId
+        );
+        const fractionMintKeypair = anchor.web3.Keypair.generate();
+        const fractionMint = fractionMintKeypair.publicKey;
+        const [programPda] = anchor.web3.PublicKey.findProgramAddressSync(
+            [Buffer.from(NFT_MARKET_PLACE_SEED)],
+            program.programId
+        );
+        const pdaTokenAccount = await anchor.utils.token.associatedAddress({
+            mint: mintKeypair.publicKey,
+            owner: programPda
+        });
+        const ownerFractionAccount = await anchor.utils.token.associatedAddress({
+            mint: fractionMint,
+            owner: wallet.publicKey
+        });
+
+        const reservePrice = new anchor.BN(anchor.web3.LAMPORTS_PER_SOL * 1);
+        await program.methods.fractionalize(new anchor.BN(100), reservePrice)
+        .accounts({
+            owner: wallet.publicKey,
+            mint: mintKeypair.publicKey,
+            ownerTokenAccount: tokenAddress,
+            fractionVault: fractionVault,
+            fractionMint: fractionMint,
+            ownerFractionAccount: ownerFractionAccount,
+            pdaTokenAccount: pdaTokenAccount,
+            programPda: programPda,
+        })
+        .signers([fractionMintKeypair])
+        .rpc();
+
+        const fractionBalance = await provider.connection.getTokenAccountBalance(ownerFractionAccount);
+        assert.strictEqual(fractionBalance.value.amount, "100");
+
+        const buyerKeypair = anchor.web3.Keypair.generate();
+        await airdrop(provider.connection, buyerKeypair.publicKey, anchor.web3.LAMPORTS_PER_SOL * 2);
+        const buyerTokenAccount = await anchor.utils.token.associatedAddress({
+            mint: mintKeypair.publicKey,
+            owner: buyerKeypair.publicKey
+        });
+
+        await program.methods.startBuyout()
+        .accounts({
+            buyer: buyerKeypair.publicKey,
+            mint: mintKeypair.publicKey,
+            fractionVault: fractionVault,
+            escrowPayer: wallet.publicKey,
+            pdaTokenAccount: pdaTokenAccount,
+            buyerTokenAccount: buyerTokenAccount,
+            programPda: programPda,
+        })
+        .signers([buyerKeypair])
+        .rpc();
+
+        const buyerTokenBalance = await provider.connection.getTokenAccountBalance(buyerTokenAccount);
+        assert.strictEqual(buyerTokenBalance.value.amount, "1");
+        assert.isNull(await provider.connection.getAccountInfo(pdaTokenAccount));
+
+        const redeemAccounts = {
+            holder: wallet.publicKey,
+            fractionVault: fractionVault,
+            rentPayer: wallet.publicKey,
+            fractionMint: fractionMint,
+            holderFractionAccount: ownerFractionAccount,
+        };
+        const vaultBalanceBefore = await provider.connection.getBalance(fractionVault);
+        await program.methods.redeem(new anchor.BN(40)).accounts(redeemAccounts).rpc();
+        const vaultBalanceAfter = await provider.connection.getBalance(fractionVault);
+        assert.strictEqual(vaultBalanceBefore - vaultBalanceAfter, reservePrice.toNumber() * 40 / 100);
+
+        const vaultAccount = await program.account.fractionVault.fetch(fractionVault);
+        assert.strictEqual(vaultAccount.proceeds.toNumber(), reservePrice.toNumber() * 60 / 100);
+
+        // Redeeming the last fractions closes the vault so the NFT can be fractionalized again
+        await program.methods.redeem(new anchor.BN(60)).accounts(redeemAccounts).rpc();
+        assert.isNull(await provider.connection.getAccountInfo(fractionVault));
+    }).timeout(15000);
+
+    it("Borrow against an NFT and repay the loan", async () => {
+        const LOAN_SEED = "LOAN_EMSKIQ_SEED";
+
+        const lenderKeypair = anchor.web3.Keypair.generate();
+        await airdrop(provider.connection, lenderKeypair.publicKey, anchor.web3.LAMPORTS_PER_SOL * 3);
+
+        const offerId = new anchor.BN(1);
+        const [loan] = anchor.web3.PublicKey.findProgramAddressSync(
+            [Buffer.from(LOAN_SEED), lenderKeypair.publicKey.toBuffer(), offerId.toArrayLike(Buffer, "le", 8)],
+            program.programId
+        );
+
+        const { mintKeypair, tokenAddress } = await mintNftWithMetadata(
+            program, wallet.payer, testNftTitle, testNftUri
+        );
+
+        // An offer open to any mint would accept self-minted junk as collateral
+        const principal = new anchor.BN(anchor.web3.LAMPORTS_PER_SOL * 1);
+        let rejected = false;
+        try {
+            await program.methods.createLoanOffer(offerId, principal, 1000, new anchor.BN(3600), null, null)
+            .accounts({
+                lender: lenderKeypair.publicKey,
+                loan: loan,
+            })
+            .signers([lenderKeypair])
+            .rpc();
+        } catch (err) {
+            rejected = true;
+            assert.include(err.message, "LoanCollateralUnrestricted");
+        }
+        assert.isTrue(rejected, "An unrestricted loan offer should have been rejected");
+
+        await program.methods.createLoanOffer(
+            offerId, principal, 1000, new anchor.BN(3600), null, mintKeypair.publicKey
+        )
+        .accounts({
+            lender: lenderKeypair.publicKey,
+            loan: loan,
+        })
+        .signers([lenderKeypair])
+        .rpc();
+        const [programPda] = anchor.web3.PublicKey.findProgramAddressSync(
+            [Buffer.from(NFT_MARKET_PLACE_SEED)],
+            program.programId
+        );
+        const pdaTokenAccount = await anchor.utils.token.associatedAddress({
+            mint: mintKeypair.publicKey,
+            owner: programPda
+        });
+
+        await program.methods.takeLoan()
+        .accounts({
+            borrower: wallet.publicKey,
+            loan: loan,
+            mint: mintKeypair.publicKey,
+            metadata: null,
+            borrowerTokenAccount: tokenAddress,
+            pdaTokenAccount: pdaTokenAccount,
+            programPda: programPda,
+        })
+        .rpc();
+
+        const loanAccount = await program.account.loan.fetch(loan);
+        assert.strictEqual(loanAccount.borrower.toString(), wallet.publicKey.toString());
+        assert.strictEqual(
+            (await provider.connection.getTokenAccountBalance(pdaTokenAccount)).value.amount, "1"
+        );
+
+        const lenderInitialBalance = await provider.connection.getBalance(lenderKeypair.publicKey);
+        const loanRent = await provider.connection.getBalance(loan);
+
+        await program.methods.repayLoan()
+        .accounts({
+            borrower: wallet.publicKey,
+            lender: lenderKeypair.publicKey,
+            loan: loan,
+            mint: mintKeypair.publicKey,
+            borrowerTokenAccount: tokenAddress,
+            pdaTokenAccount: pdaTokenAccount,
+            programPda: programPda,
+        })
+        .rpc();
+
+        // 10% flat interest plus the loan account rent back to the lender
+        const lenderFinalBalance = await provider.connection.getBalance(lenderKeypair.publicKey);
+        assert.strictEqual(
+            lenderFinalBalance - lenderInitialBalance,
+            principal.toNumber() * 1.1 + loanRent
+        );
+        assert.strictEqual(
+            (await provider.connection.getTokenAccountBalance(tokenAddress)).value.amount, "1"
+        );
+        assert.isNull(await provider.connection.getAccountInfo(pdaTokenAccount));
+    }).timeout(15000);
+
+    it("Buy a listed NFT in installments", async () => {
+        const INSTALLMENT_SEED = "INSTALLMENT_EMSKIQ_SEED";
+
+        const { mintKeypair } = await mintNftWithMetadata(
+            program, wallet.payer, testNftTitle, testNftUri
+        );
+        const priceInLamports = new anchor.BN(anchor.web3.LAMPORTS_PER_SOL * 1);
+        const { nftAccountPda, programPda, pdaTokenAccount } = await listNft(
+            program, wallet.payer, mintKeypair.publicKey, priceInLamports
+        );
+        const [installmentPlan] = anchor.web3.PublicKey.findProgramAddressSync(
+            [Buffer.from(INSTALLMENT_SEED), mintKeypair.publicKey.toBuffer()],
+            program.programId
+        );
+
+        const buyerKeypair = anchor.web3.Keypair.generate();
+        await airdrop(provider.connection, buyerKeypair.publicKey, anchor.web3.LAMPORTS_PER_SOL * 2);
+        const buyerTokenAccount = await anchor.utils.token.associatedAddress({
+            mint: mintKeypair.publicKey,
+            owner: buyerKeypair.publicKey
+        });
+
+        // A deposit below 10% is rejected
+        try {
+            await program.methods.startInstallmentPurchase(new anchor.BN(1000), new anchor.BN(3600))
+            .accounts({
+                buyer: buyerKeypair.publicKey,
+                seller: wallet.publicKey,
+                mint: mintKeypair.publicKey,
+                marketplace: marketplacePda,
+                treasury: treasuryKeypair.publicKey,
+                nftListingAccount: nftAccountPda,
+                installmentPlan: installmentPlan,
+            })
+            .signers([buyerKeypair])
+            .rpc();
+            assert.fail("Deposit below the minimum should have failed");
+        } catch (err: any) {
+            assert.include(err.message, "InvalidInstallmentDeposit");
+        }
+
+        // Paying the full price as the deposit would leave no installment to release the NFT
+        let rejected = false;
+        try {
+            await program.methods.startInstallmentPurchase(priceInLamports, new anchor.BN(3600))
+            .accounts({
+                buyer: buyerKeypair.publicKey,
+                seller: wallet.publicKey,
+                mint: mintKeypair.publicKey,
+                marketplace: marketplacePda,
+                treasury: treasuryKeypair.publicKey,
+                nftListingAccount: nftAccountPda,
+                installmentPlan: installmentPlan,
+            })
+            .signers([buyerKeypair])
+            .rpc();
+        } catch (err) {
+            rejected = true;
+            assert.include(err.message, "InvalidInstallmentDeposit");
+        }
+        assert.isTrue(rejected, "A deposit of the full price should have failed");
+
+        const deposit = priceInLamports.divn(2);
+        await program.methods.startInstallmentPurchase(deposit, new anchor.BN(3600))
+        .accounts({
+            buyer: buyerKeypair.publicKey,
+            seller: wallet.publicKey,
+            mint: mintKeypair.publicKey,
+            marketplace: marketplacePda,
+            treasury: treasuryKeypair.publicKey,
+            nftListingAccount: nftAccountPda,
+            installmentPlan: installmentPlan,
+        })
+        .signers([buyerKeypair])
+        .rpc();
+
+        // The listing is gone, so nobody else can buy it in the meantime
+        assert.isNull(await provider.connection.getAccountInfo(nftAccountPda));
+
+        const payInstallment = async (amount: anchor.BN) => {
+            await program.methods.payInstallment(amount)
+            .accounts({
+                buyer: buyerKeypair.publicKey,
+                seller: wallet.publicKey,
+                mint: mintKeypair.publicKey,
+                marketplace: marketplacePda,
+                treasury: treasuryKeypair.publicKey,
+                installmentPlan: installmentPlan,
+                pdaTokenAccount: pdaTokenAccount,
+                buyerTokenAccount: buyerTokenAccount,
+                programPda: programPda,
+            })
+            .signers([buyerKeypair])
+            .rpc();
+        };
+
+        await payInstallment(priceInLamports.divn(4));
+        const plan = await program.account.installmentPlan.fetch(installmentPlan);
+        assert.strictEqual(plan.paid.toNumber(), priceInLamports.toNumber() * 3 / 4);
+
+        // Only the final payment counts as a sale
+        const [marketplaceStats] = anchor.web3.PublicKey.findProgramAddressSync(
+            [Buffer.from("MARKETPLACE_STATS_EMSKIQ_SEED")],
+            program.programId
+        );
+        const statsBefore = await program.account.marketplaceStats.fetch(marketplaceStats);
+
+        await payInstallment(priceInLamports.divn(4));
+        assert.strictEqual(
+            (await provider.connection.getTokenAccountBalance(buyerTokenAccount)).value.amount, "1"
+        );
+        assert.isNull(await provider.connection.getAccountInfo(installmentPlan));
+
+        const statsAfter = await program.account.marketplaceStats.fetch(marketplaceStats);
+        assert.strictEqual(statsAfter.totalSales.toNumber() - statsBefore.totalSales.toNumber(), 1);
+        assert.strictEqual(
+            statsAfter.totalVolume.sub(statsBefore.totalVolume).toString(), priceInLamports.toString()
+        );
+    }).timeout(15000);
+
+    it("Buy NFT into escrow and let the arbiter refund a dispute", async () => {
+        const SETTLEMENT_SEED = "SETTLEMENT_EMSKIQ_SEED";
+
+        const { mintKeypair, tokenAddress } = await mintNftWithMetadata(
+            program, wallet.payer, testNftTitle, testNftUri
+        );
+        const priceInLamports = new anchor.BN(anchor.web3.LAMPORTS_PER_SOL * 1);
+        const { nftAccountPda, programPda, pdaTokenAccount } = await listNft(
+            program, wallet.payer, mintKeypair.publicKey, priceInLamports
+        );
+        const [settlement] = anchor.web3.PublicKey.findProgramAddressSync(
+            [Buffer.from(SETTLEMENT_SEED), mintKeypair.publicKey.toBuffer()],
+            program.programId
+        );
+
+        const buyerKeypair = anchor.web3.Keypair.generate();
+        await airdrop(provider.connection, buyerKeypair.publicKey, anchor.web3.LAMPORTS_PER_SOL * 2);
+        const buyerTokenAccount = await anchor.utils.token.associatedAddress({
+            mint: mintKeypair.publicKey,
+            owner: buyerKeypair.publicKey
+        });
+
+        await program.methods.buyNftEscrowed()
+        .accounts({
+            buyer: buyerKeypair.publicKey,
+            seller: wallet.publicKey,
+            mint: mintKeypair.publicKey,
+            marketplace: marketplacePda,
+            nftListingAccount: nftAccountPda,
+            settlement: settlement,
+        })
+        .signers([buyerKeypair])
+        .rpc();
+
+        // The seller is not paid before the settlement is released
+        const escrowed = await program.account.settlement.fetch(settlement);
+        assert.strictEqual(escrowed.price.toNumber(), priceInLamports.toNumber());
+        assert.isFalse(escrowed.disputed);
+
+        await program.methods.openDispute()
+        .accounts({
+            buyer: buyerKeypair.publicKey,
+            settlement: settlement,
+        })
+        .signers([buyerKeypair])
+        .rpc();
+
+        const [marketplaceStats] = anchor.web3.PublicKey.findProgramAddressSync(
+            [Buffer.from("MARKETPLACE_STATS_EMSKIQ_SEED")],
+            program.programId
+        );
+        const salesBeforeRefund = (await program.account.marketplaceStats.fetch(marketplaceStats)).totalSales;
+        const buyerBalanceBeforeRefund = await provider.connection.getBalance(buyerKeypair.publicKey);
+
+        // The wallet is the default arbiter
+        await program.methods.resolveDispute(true)
+        .accounts({
+            caller: wallet.publicKey,
+            seller: wallet.publicKey,
+            buyer: buyerKeypair.publicKey,
+            mint: mintKeypair.publicKey,
+            marketplace: marketplacePda,
+            treasury: treasuryKeypair.publicKey,
+            settlement: settlement,
+            pdaTokenAccount: pdaTokenAccount,
+            buyerTokenAccount: buyerTokenAccount,
+            sellerTokenAccount: tokenAddress,
+            programPda: programPda,
+        })
+        .rpc();
+
+        const buyerBalanceAfterRefund = await provider.connection.getBalance(buyerKeypair.publicKey);
+        assert.isAtLeast(buyerBalanceAfterRefund - buyerBalanceBeforeRefund, priceInLamports.toNumber());
+        assert.strictEqual(
+            (await provider.connection.getTokenAccountBalance(tokenAddress)).value.amount, "1"
+        );
+        assert.isNull(await provider.connection.getAccountInfo(settlement));
+        assert.isNull(await provider.connection.getAccountInfo(pdaTokenAccount));
+
+        // A refunded purchase is no sale
+        const salesAfterRefund = (await program.account.marketplaceStats.fetch(marketplaceStats)).totalSales;
+        assert.strictEqual(salesAfterRefund.toString(), salesBeforeRefund.toString());
+    }).timeout(15000);
+
+    it("List two NFTs as a bundle and buy them together", async () => {
+        const BUNDLE_SEED = "BUNDLE_EMSKIQ_SEED";
+
+        const first = await mintNftWithMetadata(program, wallet.payer, "Unit 1", testNftUri);
+        const second = await mintNftWithMetadata(program, wallet.payer, "Unit 2", testNftUri);
+        const mints = [first.mintKeypair.publicKey, second.mintKeypair.publicKey];
+        const metadataAddresses = [first.metadataAddress, second.metadataAddress];
+
+        const [programPda] = anchor.web3.PublicKey.findProgramAddressSync(
+            [Buffer.from(NFT_MARKET_PLACE_SEED)],
+            program.programId
+        );
+        const bundleId = new anchor.BN(Date.now());
+        const [bundle] = anchor.web3.PublicKey.findProgramAddressSync(
+            [Buffer.from(BUNDLE_SEED), wallet.publicKey.toBuffer(), bundleId.toArrayLike(Buffer, "le", 8)],
+            program.programId
+        );
+        const priceInLamports = new anchor.BN(anchor.web3.LAMPORTS_PER_SOL * 1);
+
+        const pdaTokenAccounts = await Promise.all(mints.map((mint) =>
+            anchor.utils.token.associatedAddress({ mint, owner: programPda })
+        ));
+
+        await program.methods.listBundle(bundleId, priceInLamports)
+        .accounts({
+            owner: wallet.publicKey,
+            bundle: bundle,
+            programPda: programPda,
+        })
+        .remainingAccounts([first.tokenAddress, second.tokenAddress].flatMap((tokenAccount, i) => [
+            { pubkey: mints[i], isSigner: false, isWritable: false },
+            { pubkey: tokenAccount, isSigner: false, isWritable: true },
+            { pubkey: pdaTokenAccounts[i], isSigner: false, isWritable: true },
+        ]))
+        .rpc();
+
+        const listed = await program.account.bundle.fetch(bundle);
+        assert.strictEqual(listed.mints.length, 2);
+
+        const buyerKeypair = anchor.web3.Keypair.generate();
+        await airdrop(provider.connection, buyerKeypair.publicKey, anchor.web3.LAMPORTS_PER_SOL * 2);
+        const buyerTokenAccounts = await Promise.all(mints.map((mint) =>
+            anchor.utils.token.associatedAddress({ mint, owner: buyerKeypair.publicKey })
+        ));
+
+        await program.methods.buyBundle()
+        .accounts({
+            buyer: buyerKeypair.publicKey,
+            seller: wallet.publicKey,
+            marketplace: marketplacePda,
+            treasury: treasuryKeypair.publicKey,
+            bundle: bundle,
+            programPda: programPda,
+        })
+        // Neither NFT is in a collection, the program id stands in for their collection stats
+        .remainingAccounts(mints.flatMap((mint, i) => [
+            { pubkey: mint, isSigner: false, isWritable: false },
+            { pubkey: pdaTokenAccounts[i], isSigner: false, isWritable: true },
+            { pubkey: buyerTokenAccounts[i], isSigner: false, isWritable: true },
+            { pubkey: metadataAddresses[i], isSigner: false, isWritable: false },
+            { pubkey: program.programId, isSigner: false, isWritable: false },
+        ]))
+        .signers([buyerKeypair])
+        .rpc();
+
+        for (const buyerTokenAccount of buyerTokenAccounts) {
+            assert.strictEqual(
+                (await provider.connection.getTokenAccountBalance(buyerTokenAccount)).value.amount, "1"
+            );
+        }
+        assert.isNull(await provider.connection.getAccountInfo(bundle));
+    }).timeout(20000);
+
+    it("Cancel a bundle and get the NFTs back", async () => {
+        const BUNDLE_SEED = "BUNDLE_EMSKIQ_SEED";
+
+        const first = await mintNftWithMetadata(program, wallet.payer, "Unit 1", testNftUri);
+        const second = await mintNftWithMetadata(program, wallet.payer, "Unit 2", testNftUri);
+        const mints = [first.mintKeypair.publicKey, second.mintKeypair.publicKey];
+        const ownerTokenAccounts = [first.tokenAddress, second.tokenAddress];
+
+        const [programPda] = anchor.web3.PublicKey.findProgramAddressSync(
+            [Buffer.from(NFT_MARKET_PLACE_SEED)],
+            program.programId
+        );
+        const bundleId = new anchor.BN(Date.now());
+        const [bundle] = anchor.web3.PublicKey.findProgramAddressSync(
+            [Buffer.from(BUNDLE_SEED), wallet.publicKey.toBuffer(), bundleId.toArrayLike(Buffer, "le", 8)],
+            program.programId
+        );
+        const pdaTokenAccounts = await Promise.all(mints.map((mint) =>
+            anchor.utils.token.associatedAddress({ mint, owner: programPda })
+        ));
+
+        await program.methods.listBundle(bundleId, new anchor.BN(anchor.web3.LAMPORTS_PER_SOL))
+        .accounts({
+            owner: wallet.publicKey,
+            bundle: bundle,
+            programPda: programPda,
+        })
+        .remainingAccounts(ownerTokenAccounts.flatMap((tokenAccount, i) => [
+            { pubkey: mints[i], isSigner: false, isWritable: false },
+            { pubkey: tokenAccount, isSigner: false, isWritable: true },
+            { pubkey: pdaTokenAccounts[i], isSigner: false, isWritable: true },
+        ]))
+        .rpc();
+
+        const cancelAccounts = ownerTokenAccounts.flatMap((tokenAccount, i) => [
+            { pubkey: mints[i], isSigner: false, isWritable: false },
+            { pubkey: pdaTokenAccounts[i], isSigner: false, isWritable: true },
+            { pubkey: tokenAccount, isSigner: false, isWritable: true },
+        ]);
+
+        // Only the seller can delist the bundle
+        const strangerKeypair = anchor.web3.Keypair.generate();
+        await airdrop(provider.connection, strangerKeypair.publicKey, anchor.web3.LAMPORTS_PER_SOL);
+        let rejected = false;
+        try {
+            await program.methods.cancelBundle()
+            .accounts({
+                owner: strangerKeypair.publicKey,
+                bundle: bundle,
+                programPda: programPda,
+            })
+            .remainingAccounts(cancelAccounts)
+            .signers([strangerKeypair])
+            .rpc();
+        } catch (err) {
+            rejected = true;
+        }
+        assert.isTrue(rejected, "Cancelling someone else's bundle should have failed");
+
+        await program.methods.cancelBundle()
+        .accounts({
+            owner: wallet.publicKey,
+            bundle: bundle,
+            programPda: programPda,
+        })
+        .remainingAccounts(cancelAccounts)
+        .rpc();
+
+        for (const tokenAccount of ownerTokenAccounts) {
+            assert.strictEqual(
+                (await provider.connection.getTokenAccountBalance(tokenAccount)).value.amount, "1"
+            );
+        }
+        for (const pdaTokenAccount of pdaTokenAccounts) {
+            assert.isNull(await provider.connection.getAccountInfo(pdaTokenAccount));
+        }
+        assert.isNull(await provider.connection.getAccountInfo(bundle));
+    }).timeout(20000);
+
+    it("Attempt to sell an NFT outside the collection into a collection bid", async () => {
+        const COLLECTION_BID_SEED = "COLLECTION_BID_EMSKIQ_SEED";
+
+        const collection = await mintNftWithMetadata(program, wallet.payer, "Collection", testNftUri);
+        const traitRoot = new Array(32).fill(0);
+        const priceInLamports = new anchor.BN(anchor.web3.LAMPORTS_PER_SOL / 2);
+
+        const bidderKeypair = anchor.web3.Keypair.generate();
+        await airdrop(provider.connection, bidderKeypair.publicKey, anchor.web3.LAMPORTS_PER_SOL * 2);
+        const [bid] = anchor.web3.PublicKey.findProgramAddressSync(
+            [
+                Buffer.from(COLLECTION_BID_SEED),
+                bidderKeypair.publicKey.toBuffer(),
+                collection.mintKeypair.publicKey.toBuffer(),
+                Buffer.from(traitRoot),
+            ],
+            program.programId
+        );
+
+        await program.methods.placeCollectionBid(collection.mintKeypair.publicKey, traitRoot, priceInLamports)
+        .accounts({
+            bidder: bidderKeypair.publicKey,
+            bid: bid,
+        })
+        .signers([bidderKeypair])
+        .rpc();
+
+        // An NFT without the verified collection cannot fill the bid
+        const { mintKeypair, tokenAddress, metadataAddress } = await mintNftWithMetadata(
+            program, wallet.payer, testNftTitle, testNftUri
+        );
+        const bidderTokenAccount = await anchor.utils.token.associatedAddress({
+            mint: mintKeypair.publicKey,
+            owner: bidderKeypair.publicKey
+        });
+        try {
+            await program.methods.acceptCollectionBid([])
+            .accounts({
+                seller: wallet.publicKey,
+                bidder: bidderKeypair.publicKey,
+                mint: mintKeypair.publicKey,
+                metadata: metadataAddress,
+                sellerTokenAccount: tokenAddress,
+                bidderTokenAccount: bidderTokenAccount,
+                bid: bid,
+                marketplace: marketplacePda,
+                treasury: treasuryKeypair.publicKey,
+                tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+            })
+            .rpc();
+            assert.fail("Accepting with a non-member NFT should have failed");
+        } catch (err: any) {
+            assert.include(err.message, "NotInCollection");
+        }
+
+        const bidderBalanceBeforeCancel = await provider.connection.getBalance(bidderKeypair.publicKey);
+        await program.methods.cancelCollectionBid()
+        .accounts({
+            bidder: bidderKeypair.publicKey,
+            bid: bid,
+        })
+        .signers([bidderKeypair])
+        .rpc();
+
+        const bidderBalanceAfterCancel = await provider.connection.getBalance(bidderKeypair.publicKey);
+        assert.isAtLeast(bidderBalanceAfterCancel - bidderBalanceBeforeCancel, priceInLamports.toNumber());
+        assert.isNull(await provider.connection.getAccountInfo(bid));
+    }).timeout(20000);
+
+    it("Fill collection bids with verified members, with and without a trait root", async () => {
+        const COLLECTION_BID_SEED = "COLLECTION_BID_EMSKIQ_SEED";
+
+        const collection = await mintNftWithMetadata(program, wallet.payer, "Collection", testNftUri);
+        const collectionMint = collection.mintKeypair.publicKey;
+        // Untracked, but a verified member still has to pass the stats PDA
+        const [collectionStats] = anchor.web3.PublicKey.findProgramAddressSync(
+            [Buffer.from("COLLECTION_STATS_EMSKIQ_SEED"), collectionMint.toBuffer()],
+            program.programId
+        );
+        const members = [];
+        for (const title of ["Member 1", "Member 2", "Member 3"]) {
+            const member = await mintNftWithMetadata(program, wallet.payer, title, testNftUri);
+            await verifyCollection(provider, member.metadataAddress, collection);
+            members.push(member);
+        }
+
+        // Trait tree over the first two members, leaves and pairs hashed like the program does
+        const leaves = members.slice(0, 2).map((member) => Buffer.from(keccak_256(member.mintKeypair.publicKey.toBuffer())));
+        const traitRoot = Array.from(keccak_256(Buffer.concat([...leaves].sort(Buffer.compare))));
+        const emptyRoot = new Array(32).fill(0);
+        const bidPrice = new anchor.BN(anchor.web3.LAMPORTS_PER_SOL / 2);
+
+        const bidderKeypair = anchor.web3.Keypair.generate();
+        await airdrop(provider.connection, bidderKeypair.publicKey, anchor.web3.LAMPORTS_PER_SOL * 2);
+        const placeBid = async (root: number[]) => {
+            const [bid] = anchor.web3.PublicKey.findProgramAddressSync(
+                [
+                    Buffer.from(COLLECTION_BID_SEED),
+                    bidderKeypair.publicKey.toBuffer(),
+                    collectionMint.toBuffer(),
+                    Buffer.from(root),
+                ],
+                program.programId
+            );
+            await program.methods.placeCollectionBid(collectionMint, root, bidPrice)
+            .accounts({
+                bidder: bidderKeypair.publicKey,
+                bid: bid,
+            })
+            .signers([bidderKeypair])
+            .rpc();
+            return bid;
+        };
+        const acceptBid = async (bid: anchor.web3.PublicKey, member: any, proof: number[][]) => {
+            const bidderTokenAccount = await anchor.utils.token.associatedAddress({
+                mint: member.mintKeypair.publicKey,
+                owner: bidderKeypair.publicKey
+            });
+            const signature = await program.methods.acceptCollectionBid(proof)
+            .accounts({
+                seller: wallet.publicKey,
+                bidder: bidderKeypair.publicKey,
+                mint: member.mintKeypair.publicKey,
+                metadata: member.metadataAddress,
+                sellerTokenAccount: member.tokenAddress,
+                bidderTokenAccount: bidderTokenAccount,
+                bid: bid,
+                marketplace: marketplacePda,
+                treasury: treasuryKeypair.publicKey,
+                collectionStats: collectionStats,
+                tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+            })
+            .rpc({ commitment: "confirmed" });
+            return { signature, bidderTokenAccount };
+        };
+
+        const [marketplaceStats] = anchor.web3.PublicKey.findProgramAddressSync(
+            [Buffer.from("MARKETPLACE_STATS_EMSKIQ_SEED")],
+            program.programId
+        );
+        // The seller pays the transaction fee and the rent of the bidder's token
+        // account, and of the stats account if this is the first sale
+        const assertPaymentSplit = async (fill: () => ReturnType<typeof acceptBid>) => {
+            const statsExisted = (await provider.connection.getAccountInfo(marketplaceStats)) !== null;
+            const sellerBefore = await provider.connection.getBalance(wallet.publicKey, "confirmed");
+            const treasuryBefore = await provider.connection.getBalance(treasuryKeypair.publicKey, "confirmed");
+
+            const { signature, bidderTokenAccount } = await fill();
+
+            const tx = await provider.connection.getTransaction(signature, {
+                commitment: "confirmed",
+                maxSupportedTransactionVersion: 0,
+            });
+            const rent = await provider.connection.getBalance(bidderTokenAccount, "confirmed")
+                + (statsExisted ? 0 : await provider.connection.getBalance(marketplaceStats, "confirmed"));
+            const fee = bidPrice.toNumber() * 500 / 10000;
+            const sellerAfter = await provider.connection.getBalance(wallet.publicKey, "confirmed");
+            const treasuryAfter = await provider.connection.getBalance(treasuryKeypair.publicKey, "confirmed");
+            assert.strictEqual(sellerAfter - sellerBefore, bidPrice.toNumber() - fee - tx.meta.fee - rent);
+            assert.strictEqual(treasuryAfter - treasuryBefore, fee);
+            assert.strictEqual(
+                (await provider.connection.getTokenAccountBalance(bidderTokenAccount)).value.amount, "1"
+            );
+        };
+
+        const openBid = await placeBid(emptyRoot);
+        const traitBid = await placeBid(traitRoot);
+
+        // A member outside the trait tree cannot fill the trait bid
+        let rejected = false;
+        try {
+            await acceptBid(traitBid, members[2], [Array.from(leaves[1])]);
+        } catch (err) {
+            rejected = true;
+            assert.include(err.message, "TraitMismatch");
+        }
+        assert.isTrue(rejected, "Filling a trait bid without a valid proof should have failed");
+
+        // 5% platform fee on both fills
+        await program.methods.updateMarketplace({
+            newAuthority: null,
+            treasury: null,
+            feeBps: 500,
+            referralBps: null,
+            arbiter: null,
+            disputeWindow: null,
+            complianceAuthority: null,
+            priceFeed: null,
+            maxPriceAge: null,
+        })
+        .accounts({
+            authority: wallet.publicKey,
+            marketplace: marketplacePda,
+        })
+        .rpc();
+        try {
+            // Any verified member fills the bid without a trait root
+            await assertPaymentSplit(() => acceptBid(openBid, members[2], []));
+            // The proof of the first member is the leaf of the second one
+            await assertPaymentSplit(() => acceptBid(traitBid, members[0], [Array.from(leaves[1])]));
+        } finally {
+            await program.methods.updateMarketplace({
+                newAuthority: null,
+                treasury: null,
+                feeBps: 0,
+                referralBps: null,
+                arbiter: null,
+                disputeWindow: null,
+                complianceAuthority: null,
+                priceFeed: null,
+                maxPriceAge: null,
+            })
+            .accounts({
+                authority: wallet.publicKey,
+                marketplace: marketplacePda,
+            })
+            .rpc();
+        }
+
+        assert.isNull(await provider.connection.getAccountInfo(openBid));
+        assert.isNull(await provider.connection.getAccountInfo(traitBid));
+    }).timeout(30000);
+
+    it("Review the seller once after buying an NFT", async () => {
+        const SALE_RECEIPT_SEED = "SALE_RECEIPT_EMSKIQ_SEED";
+        const REPUTATION_SEED = "REPUTATION_EMSKIQ_SEED";
+
+        const { mintKeypair } = await mintNftWithMetadata(
+            program, wallet.payer, testNftTitle, testNftUri
+        );
+        const priceInLamports = new anchor.BN(anchor.web3.LAMPORTS_PER_SOL / 10);
+        const { nftAccountPda, programPda, pdaTokenAccount } = await listNft(
+            program, wallet.payer, mintKeypair.publicKey, priceInLamports
+        );
+
+        const buyerKeypair = anchor.web3.Keypair.generate();
+        await airdrop(provider.connection, buyerKeypair.publicKey);
+        const buyerTokenAccount = await anchor.utils.token.associatedAddress({
+            mint: mintKeypair.publicKey,
+            owner: buyerKeypair.publicKey
+        });
+        const [saleReceipt] = anchor.web3.PublicKey.findProgramAddressSync(
+            [Buffer.from(SALE_RECEIPT_SEED), mintKeypair.publicKey.toBuffer(), buyerKeypair.publicKey.toBuffer()],
+            program.programId
+        );
+        const [reputation] = anchor.web3.PublicKey.findProgramAddressSync(
+            [Buffer.from(REPUTATION_SEED), wallet.publicKey.toBuffer()],
+            program.programId
+        );
+
+        // The seller cannot buy the listing to review themselves
+        let rejected = false;
+        try {
+            await program.methods.buyNft(null)
+            .accounts({
+                buyer: wallet.publicKey,
+                seller: wallet.publicKey,
+                mint: mintKeypair.publicKey,
+                marketplace: marketplacePda,
+                treasury: treasuryKeypair.publicKey,
+                referrer: null,
+                nftListingAccount: nftAccountPda,
+                pdaTokenAccount: pdaTokenAccount,
+                buyerTokenAccount: await anchor.utils.token.associatedAddress({
+                    mint: mintKeypair.publicKey,
+                    owner: wallet.publicKey
+                }),
+                programPda: programPda,
+            })
+            .rpc();
+        } catch (err) {
+            rejected = true;
+            assert.include(err.message, "SelfPurchase");
+        }
+        assert.isTrue(rejected, "Buying one's own listing should have failed");
+
+        await program.methods.buyNft(null)
+        .accounts({
+            buyer: buyerKeypair.publicKey,
+            seller: wallet.publicKey,
+            mint: mintKeypair.publicKey,
+            marketplace: marketplacePda,
+            treasury: treasuryKeypair.publicKey,
+            referrer: null,
+            nftListingAccount: nftAccountPda,
+            pdaTokenAccount: pdaTokenAccount,
+            buyerTokenAccount: buyerTokenAccount,
+            saleReceipt: saleReceipt,
+            programPda: programPda,
+        })
+        .signers([buyerKeypair])
+        .rpc();
+
+        const receipt = await program.account.saleReceipt.fetch(saleReceipt);
+        assert.strictEqual(receipt.seller.toBase58(), wallet.publicKey.toBase58());
+
+        const reviewCountBefore = (await program.account.reputation.fetchNullable(reputation))?.reviewCount.toNumber() ?? 0;
+
+        const leaveReview = () => program.methods.leaveReview(5)
+        .accounts({
+            buyer: buyerKeypair.publicKey,
+            seller: wallet.publicKey,
+            saleReceipt: saleReceipt,
+            reputation: reputation,
+        })
+        .signers([buyerKeypair])
+        .rpc();
+
+        await leaveReview();
+        const sellerReputation = await program.account.reputation.fetch(reputation);
+        assert.strictEqual(sellerReputation.reviewCount.toNumber(), reviewCountBefore + 1);
+        assert.isNull(await provider.connection.getAccountInfo(saleReceipt));
+
+        // The receipt is gone, so the same sale cannot be reviewed twice
+        try {
+            await leaveReview();
+            assert.fail("Second review of the same sale should have failed");
+        } catch (err: any) {
+            assert.include(err.message, "AccountNotInitialized");
+        }
+    }).timeout(15000);
+
+    it("Buy a gated listing only with a valid buyer attestation", async () => {
+        const BUYER_ATTESTATION_SEED = "BUYER_ATTESTATION_EMSKIQ_SEED";
+
+        const { mintKeypair } = await mintNftWithMetadata(
+            program, wallet.payer, testNftTitle, testNftUri
+        );
+        const priceInLamports = new anchor.BN(anchor.web3.LAMPORTS_PER_SOL / 10);
+        const { nftAccountPda, programPda, pdaTokenAccount } = await listNft(
+            program, wallet.payer, mintKeypair.publicKey, priceInLamports, true
+        );
+
+        const buyerKeypair = anchor.web3.Keypair.generate();
+        await airdrop(provider.connection, buyerKeypair.publicKey);
+        const buyerTokenAccount = await anchor.utils.token.associatedAddress({
+            mint: mintKeypair.publicKey,
+            owner: buyerKeypair.publicKey
+        });
+        const [buyerAttestation] = anchor.web3.PublicKey.findProgramAddressSync(
+            [Buffer.from(BUYER_ATTESTATION_SEED), buyerKeypair.publicKey.toBuffer()],
+            program.programId
+        );
+
+        const buy = (attestation: anchor.web3.PublicKey | null) => program.methods.buyNft(null)
+        .accounts({
+            buyer: buyerKeypair.publicKey,
+            seller: wallet.publicKey,
+            mint: mintKeypair.publicKey,
+            marketplace: marketplacePda,
+            treasury: treasuryKeypair.publicKey,
+            referrer: null,
+            nftListingAccount: nftAccountPda,
+            buyerAttestation: attestation,
+            pdaTokenAccount: pdaTokenAccount,
+            buyerTokenAccount: buyerTokenAccount,
+            programPda: programPda,
+        })
+        .signers([buyerKeypair])
+        .rpc();
+
+        try {
+            await buy(null);
+            assert.fail("Buying a gated listing without attestation should have failed");
+        } catch (err: any) {
+            assert.include(err.message, "AttestationRequired");
+        }
+
+        // The wallet is the default compliance authority
+        const expiresAt = new anchor.BN(Math.floor(Date.now() / 1000) + 3600);
+        await program.methods.issueAttestation(expiresAt)
+        .accounts({
+            complianceAuthority: wallet.publicKey,
+            buyer: buyerKeypair.publicKey,
+            marketplace: marketplacePda,
+            buyerAttestation: buyerAttestation,
+        })
+        .rpc();
+
+        // Rotating the compliance authority revokes the attestations of the old key
+        const setComplianceAuthority = (complianceAuthority: anchor.web3.PublicKey) =>
+            program.methods.updateMarketplace({
+                newAuthority: null,
+                treasury: null,
+                feeBps: null,
+                referralBps: null,
+                arbiter: null,
+                disputeWindow: null,
+                complianceAuthority: complianceAuthority,
+                priceFeed: null,
+                maxPriceAge: null,
+            })
+            .accounts({
+                authority: wallet.publicKey,
+                marketplace: marketplacePda,
+            })
+            .rpc();
+
+        await setComplianceAuthority(anchor.web3.Keypair.generate().publicKey);
+        let rejected = false;
+        try {
+            await buy(buyerAttestation);
+        } catch (err) {
+            rejected = true;
+            assert.include(err.message, "AttestationIssuerRevoked");
+        } finally {
+            await setComplianceAuthority(wallet.publicKey);
+        }
+        assert.isTrue(rejected, "An attestation from a rotated authority should have been rejected");
+
+        await buy(buyerAttestation);
+        assert.strictEqual(
+            (await provider.connection.getTokenAccountBalance(buyerTokenAccount)).value.amount, "1"
+        );
+    }).timeout(15000);
+
+    it("Attempt to list a non-transferable NFT", async () => {
+        const { mintKeypair, tokenAddress } = await mintNftWithMetadata(
+            program, wallet.payer, "Residency Pass", testNftUri, true
+        );
+
+        const tokenAccount = await provider.connection.getParsedAccountInfo(tokenAddress);
+        assert.strictEqual((tokenAccount.value.data as any).parsed.info.state, "frozen");
+
+        try {
+            await listNft(program, wallet.payer, mintKeypair.publicKey, new anchor.BN(anchor.web3.LAMPORTS_PER_SOL));
+            assert.fail("Listing a soulbound NFT should have failed");
+        } catch (err: any) {
+            assert.include(err.message, "NftNonTransferable");
+        }
+    }).timeout(15000);
+
+    it("Verify co-signing and later signing creators", async () => {
+        const mintKeypair = anchor.web3.Keypair.generate();
+        const tokenAddress = await anchor.utils.token.associatedAddress({
+            mint: mintKeypair.publicKey,
+            owner: wallet.publicKey
+        });
+        const [metadataAddress] = anchor.web3.PublicKey.findProgramAddressSync(
+            [Buffer.from("metadata"), TOKEN_METADATA_PROGRAM_ID.toBuffer(), mintKeypair.publicKey.toBuffer()],
+            TOKEN_METADATA_PROGRAM_ID
+        );
+        const [masterEditionAddress] = anchor.web3.PublicKey.findProgramAddressSync(
+            [Buffer.from("metadata"), TOKEN_METADATA_PROGRAM_ID.toBuffer(), mintKeypair.publicKey.toBuffer(), Buffer.from("edition")],
+            TOKEN_METADATA_PROGRAM_ID
+        );
+
+        await program.methods.mint()
+        .accounts({
+            mint: mintKeypair.publicKey,
+            tokenAccount: tokenAddress,
+            mintAuthority: wallet.publicKey,
+        })
+        .signers([mintKeypair])
+        .rpc();
+
+        // The architect co-signs the mint, the developer verifies later
+        const architect = anchor.web3.Keypair.generate();
+        const developer = anchor.web3.Keypair.generate();
+        await program.methods.createMetadata(testNftTitle, testNftUri, false, [
+            { address: architect.publicKey, share: 30 },
+            { address: developer.publicKey, share: 20 },
+        ])
+        .accounts({
+            mint: mintKeypair.publicKey,
+            mintAuthority: wallet.publicKey,
+            metadata: metadataAddress,
+            tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+            masterEdition: masterEditionAddress,
+            tokenAccount: tokenAddress,
+        })
+        .remainingAccounts([
+            { pubkey: architect.publicKey, isSigner: true, isWritable: false },
+        ])
+        .signers([mintKeypair, architect])
+        .rpc();
+
+        const signAsCreator = (creator: anchor.web3.Keypair) => program.methods.signAsCreator()
+        .accounts({
+            creator: creator.publicKey,
+            mint: mintKeypair.publicKey,
+            metadata: metadataAddress,
+            tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+        })
+        .signers([creator])
+        .rpc({ commitment: "confirmed" });
+
+        const txSignature = await signAsCreator(developer);
+        const tx = await provider.connection.getParsedTransaction(txSignature, "confirmed");
+        const eventParser = new anchor.EventParser(program.programId, new anchor.BorshCoder(program.idl));
+        const events = [...eventParser.parseLogs(tx.meta.logMessages)];
+        const verified = events.find((event) => event.name === "creatorVerifiedEvent");
+        assert.strictEqual(verified.data.creator.toBase58(), developer.publicKey.toBase58());
+
+        // Somebody who is not listed as a creator cannot sign
+        let rejected = false;
+        try {
+            await signAsCreator(anchor.web3.Keypair.generate());
+        } catch (err: any) {
+            rejected = true;
+        }
+        assert.isTrue(rejected, "Signing as a non-creator should have failed");
+    }).timeout(15000);
+
+    it("Raffle an NFT, draw a winner and deliver the prize", async () => {
+        const RAFFLE_SEED = "RAFFLE_EMSKIQ_SEED";
+        const RAFFLE_TICKETS_SEED = "RAFFLE_TICKETS_EMSKIQ_SEED";
+
+        const { mintKeypair, tokenAddress } = await mintNftWithMetadata(
+            program, wallet.payer, testNftTitle, testNftUri
+        );
+        const [raffle] = anchor.web3.PublicKey.findProgramAddressSync(
+            [Buffer.from(RAFFLE_SEED), mintKeypair.publicKey.toBuffer()],
+            program.programId
+        );
+        const [programPda] = anchor.web3.PublicKey.findProgramAddressSync(
+            [Buffer.from(NFT_MARKET_PLACE_SEED)],
+            program.programId
+        );
+        const pdaTokenAccount = await anchor.utils.token.associatedAddress({
+            mint: mintKeypair.publicKey,
+            owner: programPda
+        });
+
+        // Use the validator clock, which can drift from the local one
+        const now = await provider.connection.getBlockTime(await provider.connection.getSlot());
+        const endTime = new anchor.BN(now + 3);
+        const ticketPrice = new anchor.BN(anchor.web3.LAMPORTS_PER_SOL / 100);
+
+        await program.methods.createRaffle(ticketPrice, 10, endTime)
+        .accounts({
+            seller: wallet.publicKey,
+            mint: mintKeypair.publicKey,
+            sellerTokenAccount: tokenAddress,
+            raffle: raffle,
+            pdaTokenAccount: pdaTokenAccount,
+            programPda: programPda,
+        })
+        .rpc();
+
+        const buyers = [anchor.web3.Keypair.generate(), anchor.web3.Keypair.generate()];
+        const ticketAccounts: anchor.web3.PublicKey[] = [];
+        for (const [i, buyer] of buyers.entries()) {
+            await airdrop(provider.connection, buyer.publicKey);
+            const [tickets] = anchor.web3.PublicKey.findProgramAddressSync(
+                [
+                    Buffer.from(RAFFLE_TICKETS_SEED),
+                    raffle.toBuffer(),
+                    endTime.toArrayLike(Buffer, "le", 8),
+                    new anchor.BN(i * 2).toArrayLike(Buffer, "le", 4),
+                ],
+                program.programId
+            );
+            await program.methods.buyRaffleTickets(2)
+            .accounts({
+                buyer: buyer.publicKey,
+                raffle: raffle,
+                tickets: tickets,
+            })
+            .signers([buyer])
+            .rpc();
+            ticketAccounts.push(tickets);
+        }
+
+        const commitDraw = () => program.methods.commitRaffleDraw()
+            .accounts({
+                raffle: raffle,
+                slotHashes: anchor.web3.SYSVAR_SLOT_HASHES_PUBKEY,
+            })
+            .rpc();
+
+        // Committing the draw before the end time is rejected
+        let rejected = false;
+        try {
+            await commitDraw();
+        } catch (err: any) {
+            rejected = true;
+            assert.include(err.message, "RaffleNotEnded");
+        }
+        assert.isTrue(rejected, "Committing before the end should have failed");
+
+        await new Promise((resolve) => setTimeout(resolve, 5000));
+        await commitDraw();
+
+        // The draw slot lies a few slots ahead of the commit
+        rejected = false;
+        try {
+            await commitDraw();
+        } catch (err: any) {
+            rejected = true;
+            assert.include(err.message, "RaffleDrawCommitted");
+        }
+        assert.isTrue(rejected, "Committing twice should have failed");
+        await new Promise((resolve) => setTimeout(resolve, 3000));
+
+        const sellerBalanceBeforeDraw = await provider.connection.getBalance(wallet.publicKey);
+        await program.methods.drawRaffle()
+        .accounts({
+            caller: wallet.publicKey,
+            seller: wallet.publicKey,
+            raffle: raffle,
+            mint: mintKeypair.publicKey,
+            marketplace: marketplacePda,
+            treasury: treasuryKeypair.publicKey,
+            slotHashes: anchor.web3.SYSVAR_SLOT_HASHES_PUBKEY,
+        })
+        .rpc();
+
+        // The seller pays the transaction fee, but receives all 4 tickets
+        const sellerBalanceAfterDraw = await provider.connection.getBalance(wallet.publicKey);
+        assert.isAbove(sellerBalanceAfterDraw - sellerBalanceBeforeDraw, ticketPrice.toNumber() * 3);
+
+        const { winningTicket } = await program.account.raffle.fetch(raffle);
+        const winnerIndex = Math.floor(winningTicket / 2);
+        const winner = buyers[winnerIndex];
+        const winnerTokenAccount = await anchor.utils.token.associatedAddress({
+            mint: mintKeypair.publicKey,
+            owner: winner.publicKey
+        });
+
+        await program.methods.claimRafflePrize()
+        .accounts({
+            caller: wallet.publicKey,
+            winner: winner.publicKey,
+            seller: wallet.publicKey,
+            mint: mintKeypair.publicKey,
+            raffle: raffle,
+            tickets: ticketAccounts[winnerIndex],
+            pdaTokenAccount: pdaTokenAccount,
+            winnerTokenAccount: winnerTokenAccount,
+            programPda: programPda,
+        })
+        .rpc();
+
+        assert.strictEqual(
+            (await provider.connection.getTokenAccountBalance(winnerTokenAccount)).value.amount, "1"
+        );
+        assert.isNull(await provider.connection.getAccountInfo(raffle));
+
+        // The other buyer gets the rent of their tickets back
+        const loserIndex = 1 - winnerIndex;
+        await program.methods.closeRaffleTickets()
+        .accounts({
+            buyer: buyers[loserIndex].publicKey,
+            raffle: raffle,
+            tickets: ticketAccounts[loserIndex],
+        })
+        .signers([buyers[loserIndex]])
+        .rpc();
+        assert.isNull(await provider.connection.getAccountInfo(ticketAccounts[loserIndex]));
+    }).timeout(30000);
+
+    it("Pool contributions to buy a listed NFT into a fraction vault", async () => {
+        const PURCHASE_POOL_SEED = "PURCHASE_POOL_EMSKIQ_SEED";
+        const POOL_CONTRIBUTION_SEED = "POOL_CONTRIBUTION_EMSKIQ_SEED";
+        const FRACTION_VAULT_SEED = "FRACTION_VAULT_EMSKIQ_SEED";
+
+        const { mintKeypair } = await mintNftWithMetadata(
+            program, wallet.payer, testNftTitle, testNftUri
+        );
+        const priceInLamports = new anchor.BN(anchor.web3.LAMPORTS_PER_SOL * 1);
+        const { nftAccountPda } = await listNft(
+            program, wallet.payer, mintKeypair.publicKey, priceInLamports
+        );
+
+        const [purchasePool] = anchor.web3.PublicKey.findProgramAddressSync(
+            [Buffer.from(PURCHASE_POOL_SEED), mintKeypair.publicKey.toBuffer(), wallet.publicKey.toBuffer()],
+            program.programId
+        );
+        const [fractionVault] = anchor.web3.PublicKey.findProgramAddressSync(
+            [Buffer.from(FRACTION_VAULT_SEED), mintKeypair.publicKey.toBuffer()],
+            program.programId
+        );
+        const fractionMintKeypair = anchor.web3.Keypair.generate();
+        const fractionMint = fractionMintKeypair.publicKey;
+        const poolFractionAccount = await anchor.utils.token.associatedAddress({
+            mint: fractionMint,
+            owner: purchasePool
+        });
+
+        const deadline = new anchor.BN(Math.floor(Date.now() / 1000) + 3600);
+
+        // A reserve below the price would let the organizer buy the NFT out for less
+        let rejected = false;
+        try {
+            await program.methods.createPurchasePool(deadline, priceInLamports.subn(1))
+            .accounts({
+                organizer: wallet.publicKey,
+                mint: mintKeypair.publicKey,
+                nftListingAccount: nftAccountPda,
+                purchasePool: purchasePool,
+            })
+            .rpc();
+        } catch (err) {
+            rejected = true;
+            assert.include(err.message, "PoolReserveBelowTarget");
+        }
+        assert.isTrue(rejected, "A reserve below the target should have been rejected");
+
+        await program.methods.createPurchasePool(deadline, priceInLamports.muln(2))
+        .accounts({
+            organizer: wallet.publicKey,
+            mint: mintKeypair.publicKey,
+            nftListingAccount: nftAccountPda,
+            purchasePool: purchasePool,
+        })
+        .rpc();
+
+        // Two club members put in 70% and 30% of the price
+        const members = [anchor.web3.Keypair.generate(), anchor.web3.Keypair.generate()];
+        const shares = [priceInLamports.muln(7).divn(10), priceInLamports.muln(3).divn(10)];
+        const contributions: anchor.web3.PublicKey[] = [];
+        for (const [i, member] of members.entries()) {
+            await airdrop(provider.connection, member.publicKey, anchor.web3.LAMPORTS_PER_SOL * 2);
+            const [contribution] = anchor.web3.PublicKey.findProgramAddressSync(
+                [Buffer.from(POOL_CONTRIBUTION_SEED), purchasePool.toBuffer(), member.publicKey.toBuffer()],
+                program.programId
+            );
+            await program.methods.contributeToPool(shares[i])
+            .accounts({
+                contributor: member.publicKey,
+                purchasePool: purchasePool,
+                contribution: contribution,
+            })
+            .signers([member])
+            .rpc();
+            contributions.push(contribution);
+        }
+
+        await program.methods.executePoolPurchase()
+        .accounts({
+            payer: wallet.publicKey,
+            seller: wallet.publicKey,
+            mint: mintKeypair.publicKey,
+            marketplace: marketplacePda,
+            treasury: treasuryKeypair.publicKey,
+            nftListingAccount: nftAccountPda,
+            purchasePool: purchasePool,
+            fractionVault: fractionVault,
+            fractionMint: fractionMint,
+            poolFractionAccount: poolFractionAccount,
+        })
+        .signers([fractionMintKeypair])
+        .rpc();
+
+        const vault = await program.account.fractionVault.fetch(fractionVault);
+        assert.strictEqual(vault.curator.toBase58(), purchasePool.toBase58());
+        assert.isNull(await provider.connection.getAccountInfo(nftAccountPda));
+
+        for (const [i, member] of members.entries()) {
+            const memberFractionAccount = await anchor.utils.token.associatedAddress({
+                mint: fractionMint,
+                owner: member.publicKey
+            });
+            await program.methods.claimPoolFractions()
+            .accounts({
+                contributor: member.publicKey,
+                organizer: wallet.publicKey,
+                purchasePool: purchasePool,
+                contribution: contributions[i],
+                fractionVault: fractionVault,
+                fractionMint: fractionMint,
+                poolFractionAccount: poolFractionAccount,
+                contributorFractionAccount: memberFractionAccount,
+            })
+            .signers([member])
+            .rpc();
+
+            assert.strictEqual(
+                (await provider.connection.getTokenAccountBalance(memberFractionAccount)).value.amount,
+                shares[i].toString()
+            );
+        }
+
+        // The last claim closes the pool and its fraction account
+        assert.isNull(await provider.connection.getAccountInfo(purchasePool));
+        assert.isNull(await provider.connection.getAccountInfo(poolFractionAccount));
+    }).timeout(20000);
+
+    it("Migrate unversioned listings of both legacy layouts", async () => {
+        // Loaded from tests/fixtures, the 72 byte layout predates `requires_attestation`
+        const legacyOwner = new anchor.web3.PublicKey("3ydsAAfhKNjXKt6hZN58gLwENWHS7CWBo76ehsZw9BGj");
+        const fixtures = [
+            {
+                mint: new anchor.web3.PublicKey("2DzmGxo4wPdfja5xPfb6M5vLGZBe5JCvxSU1JGxR4xDF"),
+                listing: new anchor.web3.PublicKey("6rFopZo6rQzAEsU8K2PLNFXCTTzTPPruLEN5zffC8eds"),
+                size: 72,
+                price: 1_500_000_000,
+                requiresAttestation: false,
+            },
+            {
+                mint: new anchor.web3.PublicKey("38dgYGeePVFLYT41DEi4MwtJKGJdsCj1p4Ewmvz8B332"),
+                listing: new anchor.web3.PublicKey("6tLPStnaCPhxkz1Mx54ry3Kghnajete6sKFvg8ghM3Yo"),
+                size: 73,
+                price: 2_500_000_000,
+                requiresAttestation: true,
+            },
+        ];
+
+        for (const fixture of fixtures) {
+            const [listingPda, bump] = anchor.web3.PublicKey.findProgramAddressSync(
+                [Buffer.from(LISTED_NFT_SEED), fixture.mint.toBuffer()],
+                program.programId
+            );
+            assert.strictEqual(listingPda.toBase58(), fixture.listing.toBase58());
+            const legacyInfo = await provider.connection.getAccountInfo(fixture.listing);
+            assert.strictEqual(legacyInfo.data.length, 8 + fixture.size);
+
+            await program.methods.migrateListing()
+            .accounts({
+                payer: wallet.publicKey,
+                mint: fixture.mint,
+                nftListingAccount: fixture.listing,
+            })
+            .rpc();
+
+            const listing = await program.account.listedNft.fetch(fixture.listing);
+            assert.strictEqual(listing.mint.toBase58(), fixture.mint.toBase58());
+            assert.strictEqual(listing.owner.toBase58(), legacyOwner.toBase58());
+            assert.strictEqual(listing.price.toNumber(), fixture.price);
+            assert.strictEqual(listing.requiresAttestation, fixture.requiresAttestation);
+            assert.strictEqual(listing.version, 1);
+            assert.strictEqual(listing.bump, bump);
+            assert.strictEqual(listing.usdPrice.toNumber(), 0);
+
+            const migratedInfo = await provider.connection.getAccountInfo(fixture.listing);
+            assert.strictEqual(migratedInfo.data.length, 8 + 147);
+            assert.isAtLeast(
+                migratedInfo.lamports,
+                await provider.connection.getMinimumBalanceForRentExemption(8 + 147)
+            );
+        }
+    }).timeout(15000);
+
+    it("New listings use the versioned layout and cannot be migrated again", async () => {
+        const { mintKeypair } = await mintNftWithMetadata(program, wallet.payer, "Versioned", testNftUri);
+        const { nftAccountPda } = await listNft(
+            program, wallet.payer, mintKeypair.publicKey, new anchor.BN(anchor.web3.LAMPORTS_PER_SOL)
+        );
+
+        const listing = await program.account.listedNft.fetch(nftAccountPda);
+        const [, bump] = anchor.web3.PublicKey.findProgramAddressSync(
+            [Buffer.from("LISTED_NFT_EMSKIQ_SEED"), mintKeypair.publicKey.toBuffer()],
+            program.programId
+        );
+        assert.strictEqual(listing.version, 1);
+        assert.strictEqual(listing.bump, bump);
+        assert.isAbove(listing.createdAt.toNumber(), 0);
+
+        try {
+            await program.methods.migrateListing()
+            .accounts({
+                payer: wallet.publicKey,
+                mint: mintKeypair.publicKey,
+                nftListingAccount: nftAccountPda,
+            })
+            .rpc();
+            assert.fail("Migrating a current listing should have failed");
+        } catch (err) {
+            assert.include(err.message, "ListingAlreadyMigrated");
+        }
+    }).timeout(15000);
+
+    it("Track listing and sale counters in the marketplace stats", async () => {
+        const [marketplaceStats] = anchor.web3.PublicKey.findProgramAddressSync(
+            [Buffer.from("MARKETPLACE_STATS_EMSKIQ_SEED")],
+            program.programId
+        );
+        const { mintKeypair } = await mintNftWithMetadata(program, wallet.payer, "Counted", testNftUri);
+        const priceInLamports = new anchor.BN(anchor.web3.LAMPORTS_PER_SOL / 2);
+        const { nftAccountPda, programPda, pdaTokenAccount } = await listNft(
+            program, wallet.payer, mintKeypair.publicKey, priceInLamports
+        );
+        const statsAfterListing = await program.account.marketplaceStats.fetch(marketplaceStats);
+        assert.isAtLeast(statsAfterListing.activeListings.toNumber(), 1);
+
+        // Unverified NFTs cannot move the stats of a collection
+        const collection = anchor.web3.Keypair.generate().publicKey;
+        const [collectionStats] = anchor.web3.PublicKey.findProgramAddressSync(
+            [Buffer.from("COLLECTION_STATS_EMSKIQ_SEED"), collection.toBuffer()],
+            program.programId
+        );
+        await program.methods.initializeCollectionStats(collection)
+        .accounts({
+            payer: wallet.publicKey,
+            collectionStats: collectionStats,
+        })
+        .rpc();
+
+        const [metadata] = anchor.web3.PublicKey.findProgramAddressSync(
+            [Buffer.from("metadata"), TOKEN_METADATA_PROGRAM_ID.toBuffer(), mintKeypair.publicKey.toBuffer()],
+            TOKEN_METADATA_PROGRAM_ID
+        );
+        const buyerKeypair = anchor.web3.Keypair.generate();
+        await airdrop(provider.connection, buyerKeypair.publicKey, anchor.web3.LAMPORTS_PER_SOL);
+        const buyerTokenAccount = await anchor.utils.token.associatedAddress({
+            mint: mintKeypair.publicKey,
+            owner: buyerKeypair.publicKey
+        });
+        const buyAccounts = {
+            buyer: buyerKeypair.publicKey,
+            seller: wallet.publicKey,
+            mint: mintKeypair.publicKey,
+            marketplace: marketplacePda,
+            treasury: treasuryKeypair.publicKey,
+            nftListingAccount: nftAccountPda,
+            marketplaceStats: marketplaceStats,
+            pdaTokenAccount: pdaTokenAccount,
+            buyerTokenAccount: buyerTokenAccount,
+            programPda: programPda,
+        };
+
+        let rejected = false;
+        try {
+            await program.methods.buyNft(null)
+            .accounts({ ...buyAccounts, collectionStats: collectionStats, metadata: metadata })
+            .signers([buyerKeypair])
+            .rpc();
+        } catch (err) {
+            rejected = true;
+            assert.include(err.message, "NotInCollection");
+        }
+        assert.isTrue(rejected, "Buying with foreign collection stats should have failed");
+
+        await program.methods.buyNft(null)
+        .accounts(buyAccounts)
+        .signers([buyerKeypair])
+        .rpc();
+
+        const stats = await program.account.marketplaceStats.fetch(marketplaceStats);
+        assert.strictEqual(stats.totalSales.toNumber() - statsAfterListing.totalSales.toNumber(), 1);
+        assert.strictEqual(
+            stats.totalVolume.sub(statsAfterListing.totalVolume).toString(), priceInLamports.toString()
+        );
+        assert.strictEqual(stats.activeListings.toNumber(), statsAfterListing.activeListings.toNumber() - 1);
+        assert.strictEqual(stats.totalListings.toNumber(), statsAfterListing.totalListings.toNumber());
+
+        const untouched = await program.account.collectionStats.fetch(collectionStats);
+        assert.strictEqual(untouched.totalSales.toNumber(), 0);
+    }).timeout(15000);
+
+    it("Record sales of verified collection members in the collection stats", async () => {
+        const COLLECTION_BID_SEED = "COLLECTION_BID_EMSKIQ_SEED";
+        const BUNDLE_SEED = "BUNDLE_EMSKIQ_SEED";
+
+        const collection = await mintNftWithMetadata(program, wallet.payer, "Collection", testNftUri);
+        const collectionMint = collection.mintKeypair.publicKey;
+        const [collectionStats] = anchor.web3.PublicKey.findProgramAddressSync(
+            [Buffer.from("COLLECTION_STATS_EMSKIQ_SEED"), collectionMint.toBuffer()],
+            program.programId
+        );
+        await program.methods.initializeCollectionStats(collectionMint)
+        .accounts({
+            payer: wallet.publicKey,
+            collectionStats: collectionStats,
+        })
+        .rpc();
+
+        // Sold into a collection bid
+        const member = await mintNftWithMetadata(program, wallet.payer, "Member", testNftUri);
+        await verifyCollection(provider, member.metadataAddress, collection);
+
+        const traitRoot = new Array(32).fill(0);
+        const bidPrice = new anchor.BN(anchor.web3.LAMPORTS_PER_SOL / 4);
+        const bidderKeypair = anchor.web3.Keypair.generate();
+        await airdrop(provider.connection, bidderKeypair.publicKey, anchor.web3.LAMPORTS_PER_SOL * 2);
+        const [bid] = anchor.web3.PublicKey.findProgramAddressSync(
+            [
+                Buffer.from(COLLECTION_BID_SEED),
+                bidderKeypair.publicKey.toBuffer(),
+                collectionMint.toBuffer(),
+                Buffer.from(traitRoot),
+            ],
+            program.programId
+        );
+        await program.methods.placeCollectionBid(collectionMint, traitRoot, bidPrice)
+        .accounts({
+            bidder: bidderKeypair.publicKey,
+            bid: bid,
+        })
+        .signers([bidderKeypair])
+        .rpc();
+
+        const acceptAccounts = {
+            seller: wallet.publicKey,
+            bidder: bidderKeypair.publicKey,
+            mint: member.mintKeypair.publicKey,
+            metadata: member.metadataAddress,
+            sellerTokenAccount: member.tokenAddress,
+            bidderTokenAccount: await anchor.utils.token.associatedAddress({
+                mint: member.mintKeypair.publicKey,
+                owner: bidderKeypair.publicKey
+            }),
+            bid: bid,
+            marketplace: marketplacePda,
+            treasury: treasuryKeypair.publicKey,
+            tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+        };
+
+        // The collection is tracked, so the sale cannot leave its stats out
+        let rejected = false;
+        try {
+            await program.methods.acceptCollectionBid([]).accounts(acceptAccounts).rpc();
+        } catch (err) {
+            rejected = true;
+            assert.include(err.message, "CollectionStatsRequired");
+        }
+        assert.isTrue(rejected, "Selling a tracked collection member without its stats should have failed");
+
+        await program.methods.acceptCollectionBid([])
+        .accounts({ ...acceptAccounts, collectionStats: collectionStats })
+        .rpc();
+
+        let stats = await program.account.collectionStats.fetch(collectionStats);
+        assert.strictEqual(stats.totalSales.toNumber(), 1);
+        assert.strictEqual(stats.lastSalePrice.toString(), bidPrice.toString());
+        assert.isTrue(stats.lastSaleAt.toNumber() > 0);
+
+        // Sold in a bundle, each member counts with half the bundle price
+        const units = [
+            await mintNftWithMetadata(program, wallet.payer, "Unit 1", testNftUri),
+            await mintNftWithMetadata(program, wallet.payer, "Unit 2", testNftUri),
+        ];
+        for (const unit of units) {
+            await verifyCollection(provider, unit.metadataAddress, collection);
+        }
+        const mints = units.map((unit) => unit.mintKeypair.publicKey);
+
+        const [programPda] = anchor.web3.PublicKey.findProgramAddressSync(
+            [Buffer.from(NFT_MARKET_PLACE_SEED)],
+            program.programId
+        );
+        const bundleId = new anchor.BN(Date.now());
+        const [bundle] = anchor.web3.PublicKey.findProgramAddressSync(
+            [Buffer.from(BUNDLE_SEED), wallet.publicKey.toBuffer(), bundleId.toArrayLike(Buffer, "le", 8)],
+            program.programId
+        );
+        const bundlePrice = new anchor.BN(anchor.web3.LAMPORTS_PER_SOL);
+        const pdaTokenAccounts = await Promise.all(mints.map((mint) =>
+            anchor.utils.token.associatedAddress({ mint, owner: programPda })
+        ));
+
+        await program.methods.listBundle(bundleId, bundlePrice)
+        .accounts({
+            owner: wallet.publicKey,
+            bundle: bundle,
+            programPda: programPda,
+        })
+        .remainingAccounts(units.flatMap((unit, i) => [
+            { pubkey: mints[i], isSigner: false, isWritable: false },
+            { pubkey: unit.tokenAddress, isSigner: false, isWritable: true },
+            { pubkey: pdaTokenAccounts[i], isSigner: false, isWritable: true },
+        ]))
+        .rpc();
+
+        const buyerKeypair = anchor.web3.Keypair.generate();
+        await airdrop(provider.connection, buyerKeypair.publicKey, anchor.web3.LAMPORTS_PER_SOL * 2);
+        const buyerTokenAccounts = await Promise.all(mints.map((mint) =>
+            anchor.utils.token.associatedAddress({ mint, owner: buyerKeypair.publicKey })
+        ));
+
+        await program.methods.buyBundle()
+        .accounts({
+            buyer: buyerKeypair.publicKey,
+            seller: wallet.publicKey,
+            marketplace: marketplacePda,
+            treasury: treasuryKeypair.publicKey,
+            bundle: bundle,
+            programPda: programPda,
+        })
+        .remainingAccounts(units.flatMap((unit, i) => [
+            { pubkey: mints[i], isSigner: false, isWritable: false },
+            { pubkey: pdaTokenAccounts[i], isSigner: false, isWritable: true },
+            { pubkey: buyerTokenAccounts[i], isSigner: false, isWritable: true },
+            { pubkey: unit.metadataAddress, isSigner: false, isWritable: false },
+            { pubkey: collectionStats, isSigner: false, isWritable: true },
+        ]))
+        .signers([buyerKeypair])
+        .rpc();
+
+        stats = await program.account.collectionStats.fetch(collectionStats);
+        assert.strictEqual(stats.totalSales.toNumber(), 3);
+        assert.strictEqual(stats.lastSalePrice.toString(), bundlePrice.divn(2).toString());
+        assert.strictEqual(stats.totalVolume.toString(), bidPrice.add(bundlePrice).toString());
+    }).timeout(30000);
+
+    it("Buy and list NFTs from a DAO treasury PDA through CPI", async () => {
+        const daoProgram = anchor.workspace.DaoTreasury as Program<DaoTreasury>;
+        const [dao] = anchor.web3.PublicKey.findProgramAddressSync(
+            [Buffer.from("DAO_EMSKIQ_SEED"), wallet.publicKey.toBuffer()],
+            daoProgram.programId
+        );
+        const [daoTreasury] = anchor.web3.PublicKey.findProgramAddressSync(
+            [Buffer.from("DAO_TREASURY_EMSKIQ_SEED"), dao.toBuffer()],
+            daoProgram.programId
+        );
+        await daoProgram.methods.createDao()
+        .accounts({
+            authority: wallet.publicKey,
+            dao: dao,
+            daoTreasury: daoTreasury,
+        })
+        .rpc();
+        await airdrop(provider.connection, daoTreasury, anchor.web3.LAMPORTS_PER_SOL * 2);
+
+        const { mintKeypair, metadataAddress } = await mintNftWithMetadata(program, wallet.payer, "Town Hall", testNftUri);
+        const mint = mintKeypair.publicKey;
+        const priceInLamports = new anchor.BN(anchor.web3.LAMPORTS_PER_SOL);
+        const { nftAccountPda, programPda, pdaTokenAccount } = await listNft(program, wallet.payer, mint, priceInLamports);
+
+        const [marketplaceStats] = anchor.web3.PublicKey.findProgramAddressSync(
+            [Buffer.from("MARKETPLACE_STATS_EMSKIQ_SEED")],
+            program.programId
+        );
+        const [saleReceipt] = anchor.web3.PublicKey.findProgramAddressSync(
+            [Buffer.from("SALE_RECEIPT_EMSKIQ_SEED"), mint.toBuffer(), daoTreasury.toBuffer()],
+            program.programId
+        );
+        const treasuryTokenAccount = await anchor.utils.token.associatedAddress({
+            mint: mint,
+            owner: daoTreasury
+        });
+        const buyAccounts = {
+            authority: wallet.publicKey,
+            dao: dao,
+            daoTreasury: daoTreasury,
+            seller: wallet.publicKey,
+            mint: mint,
+            marketplace: marketplacePda,
+            marketplaceTreasury: treasuryKeypair.publicKey,
+            nftListingAccount: nftAccountPda,
+            marketplaceStats: marketplaceStats,
+            collectionStats: null,
+            metadata: metadataAddress,
+            pdaTokenAccount: pdaTokenAccount,
+            treasuryTokenAccount: treasuryTokenAccount,
+            saleReceipt: saleReceipt,
+            programPda: programPda,
+            marketplaceProgram: program.programId,
+        };
+
+        // The DAO only approved half the asking price
+        let rejected = false;
+        try {
+            await daoProgram.methods.buyForTreasury(priceInLamports.divn(2))
+            .accounts(buyAccounts)
+            .rpc();
+        } catch (err) {
+            rejected = true;
+            assert.include(err.message, "PriceAboveMax");
+        }
+        assert.isTrue(rejected, "Buying above the approved price should have failed");
+
+        await daoProgram.methods.buyForTreasury(priceInLamports)
+        .accounts(buyAccounts)
+        .rpc();
+
+        assert.strictEqual(
+            (await provider.connection.getTokenAccountBalance(treasuryTokenAccount)).value.amount, "1"
+        );
+        const receipt = await program.account.saleReceipt.fetch(saleReceipt);
+        assert.strictEqual(receipt.buyer.toBase58(), daoTreasury.toBase58());
+
+        // Relist it with the treasury PDA as the owner
+        const relistPrice = new anchor.BN(anchor.web3.LAMPORTS_PER_SOL * 1.5);
+        await daoProgram.methods.listFromTreasury(relistPrice, false)
+        .accounts({
+            authority: wallet.publicKey,
+            dao: dao,
+            daoTreasury: daoTreasury,
+            mint: mint,
+            treasuryTokenAccount: treasuryTokenAccount,
+            nftListingAccount: nftAccountPda,
+            marketplaceStats: marketplaceStats,
+            pdaTokenAccount: pdaTokenAccount,
+            programPda: programPda,
+            marketplaceProgram: program.programId,
+        })
+        .rpc();
+
+        const listing = await program.account.listedNft.fetch(nftAccountPda);
+        assert.strictEqual(listing.owner.toBase58(), daoTreasury.toBase58());
+        assert.strictEqual(listing.price.toString(), relistPrice.toString());
+    }).timeout(20000);
+
+     it("Attempt to buy an NFT with insufficient SOL", async () => {
+        try {
+            // Mint and list an NFT
+            const mintKeypair: anchor.web3.Keypair = anchor.web3.Keypair.generate();
+            const tokenAddress = await anchor.utils.token.associatedAddress({
+                mint: mintKeypair.publicKey,
+                owner: wallet.publicKey 
+            });
+
+
+            // Derive the metadata address
+            const metadataAddress = (await anchor.web3.PublicKey.findProgramAddress(
+                [
+                    Buffer.from("metadata"),
+                    TOKEN_METADATA_PROGRAM_ID.toBuffer(),
+                    mintKeypair.publicKey.toBuffer(),
+                ],
+                TOKEN_METADATA_PROGRAM_ID
+            ))[0];
+
+            // Derive the master edition address
+            const masterEditionAddress = (await anchor.web3.PublicKey.findProgramAddress(
+                [
+                    Buffer.from("metadata"),
+                    TOKEN_METADATA_PROGRAM_ID.toBuffer(),
+                    mintKeypair.publicKey.toBuffer(),
+                    Buffer.from("edition"),
+                ],
+                TOKEN_METADATA_PROGRAM_ID
+            ))[0];
+
+            // Mint the NFT
+            await program.methods.mint()
+                .accounts({
+                    mint: mintKeypair.publicKey,
+                    tokenAccount: tokenAddress,
+                    mintAuthority: wallet.publicKey, 
+                })
+                .signers([mintKeypair])
+                .rpc();
+
+            await program.methods.createMetadata(
+                testNftTitle, testNftUri, false, []
+            )
+                .accounts({
+                    mint: mintKeypair.publicKey,
+                    mintAuthority: wallet.publicKey,
+                    metadata: metadataAddress,
+                    tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+                    masterEdition: masterEditionAddress,
+                })
+                .signers([mintKeypair])
+                .rpc();
+
+            // List the NFT
+            const priceInLamports = new anchor.BN(anchor.web3.LAMPORTS_PER_SOL * 1); // 1 SOL
+            const [nftAccountPda, nftAccountBump] = await anchor.web3.PublicKey.findProgramAddress(
+                [Buffer.from(LISTED_NFT_SEED), mintKeypair.publicKey.toBuffer()],
+                program.programId
+            );
+
+            const [programPda, programPdaBump] = await anchor.web3.PublicKey.findProgramAddress(
+                [Buffer.from(NFT_MARKET_PLACE_SEED)],
+                program.programId
+            );
+
+            const sellerTokenAccount = tokenAddress;
+
+            const pdaTokenAccount = await anchor.utils.token.associatedAddress({
+                mint: mintKeypair.publicKey,
+                owner: programPda
+            });
+
+            await program.methods.listNft(priceInLamports, false)
+                .accounts({
+                    owner: wallet.publicKey,
+                    mint: mintKeypair.publicKey,
+                    ownerTokenAccount: sellerTokenAccount,
+                    nftListingAccount: nftAccountPda,
+                    pdaTokenAccount: pdaTokenAccount,
+                    programPda: programPda,
+                })
+                .signers([wallet.payer])
+                .rpc();
+
+            // Create a buyer with insufficient SOL
+            const buyerKeypair = anchor.web3.Keypair.generate();
+
+            // Transfer SOL to buyer from provider's wallet
+            // (if airdroping is timeouting, pbly because you are not on localnet, but on devnet)
+            // await transferSol(provider, wallet.publicKey, buyerKeypair.publicKey, 0.01);
+
+            // Airdrop minimal SOL to buyer (e.g., 0.001 SOL) -> Not working for me... :(
+            await airdrop(provider.connection, buyerKeypair.publicKey, anchor.web3.LAMPORTS_PER_SOL * 0.001);
+
+            const buyerTokenAccount = await anchor.utils.token.associatedAddress({
                 mint: mintKeypair.publicKey,
                 owner: buyerKeypair.publicKey
             });
 
             // Attempt to buy the NFT
-            await program.methods.buyNft()
+            await program.methods.buyNft(null)
                 .accounts({
                     buyer: buyerKeypair.publicKey,
                     seller: wallet.publicKey,
                     mint: mintKeypair.publicKey,
+                    marketplace: marketplacePda,
+                    treasury: treasuryKeypair.publicKey,
+                    referrer: null,
                     nftListingAccount: nftAccountPda,
                     pdaTokenAccount: pdaTokenAccount,
                     buyerTokenAccount: buyerTokenAccount,
@@ -419,7 +2933,7 @@ describe("NFT Marketplace", () => {
                 owner: programPda,
             });
 
-            await program.methods.listNft(priceInLamports)
+            await program.methods.listNft(priceInLamports, false)
             .accounts({
                 owner: unauthorizedSeller.publicKey,
                 mint: mintKeypair.publicKey,
@@ -439,6 +2953,112 @@ describe("NFT Marketplace", () => {
 
 });
 
+async function mintNftWithMetadata(program: any, owner: anchor.web3.Keypair, title: string, uri: string, nonTransferable = false) {
+    const mintKeypair: anchor.web3.Keypair = anchor.web3.Keypair.generate();
+    const tokenAddress = await anchor.utils.token.associatedAddress({
+        mint: mintKeypair.publicKey,
+        owner: owner.publicKey
+    });
+
+    const metadataAddress = (await anchor.web3.PublicKey.findProgramAddress(
+        [
+            Buffer.from("metadata"),
+            TOKEN_METADATA_PROGRAM_ID.toBuffer(),
+            mintKeypair.publicKey.toBuffer(),
+        ],
+        TOKEN_METADATA_PROGRAM_ID
+    ))[0];
+
+    const masterEditionAddress = (await anchor.web3.PublicKey.findProgramAddress(
+        [
+            Buffer.from("metadata"),
+            TOKEN_METADATA_PROGRAM_ID.toBuffer(),
+            mintKeypair.publicKey.toBuffer(),
+            Buffer.from("edition"),
+        ],
+        TOKEN_METADATA_PROGRAM_ID
+    ))[0];
+
+    await program.methods.mint()
+    .accounts({
+        mint: mintKeypair.publicKey,
+        tokenAccount: tokenAddress,
+        mintAuthority: owner.publicKey,
+    })
+    .signers([mintKeypair, owner])
+    .rpc();
+
+    await program.methods.createMetadata(title, uri, nonTransferable, [])
+    .accounts({
+        mint: mintKeypair.publicKey,
+        mintAuthority: owner.publicKey,
+        metadata: metadataAddress,
+        tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+        masterEdition: masterEditionAddress,
+        tokenAccount: tokenAddress,
+    })
+    .signers([mintKeypair, owner])
+    .rpc();
+
+    return { mintKeypair, tokenAddress, metadataAddress, masterEditionAddress };
+}
+
+// Sets and verifies the collection of an NFT through the legacy SetAndVerifyCollection
+// instruction of Token Metadata. The provider wallet is the update authority of both.
+async function verifyCollection(provider: anchor.AnchorProvider, metadata: anchor.web3.PublicKey, collection: any) {
+    const authority = provider.wallet.publicKey;
+    const instruction = new anchor.web3.TransactionInstruction({
+        programId: TOKEN_METADATA_PROGRAM_ID,
+        keys: [
+            { pubkey: metadata, isSigner: false, isWritable: true },
+            { pubkey: authority, isSigner: true, isWritable: true },
+            { pubkey: authority, isSigner: true, isWritable: true },
+            { pubkey: authority, isSigner: false, isWritable: false },
+            { pubkey: collection.mintKeypair.publicKey, isSigner: false, isWritable: false },
+            { pubkey: collection.metadataAddress, isSigner: false, isWritable: false },
+            { pubkey: collection.masterEditionAddress, isSigner: false, isWritable: false },
+        ],
+        data: Buffer.from([25]),
+    });
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(instruction));
+}
+
+async function listNft(program: any, owner: anchor.web3.Keypair, mint: anchor.web3.PublicKey, price: anchor.BN, requiresAttestation = false) {
+    const [nftAccountPda] = anchor.web3.PublicKey.findProgramAddressSync(
+        [Buffer.from("LISTED_NFT_EMSKIQ_SEED"), mint.toBuffer()],
+        program.programId
+    );
+
+    const [programPda] = anchor.web3.PublicKey.findProgramAddressSync(
+        [Buffer.from(NFT_MARKET_PLACE_SEED)],
+        program.programId
+    );
+
+    const ownerTokenAccount = await anchor.utils.token.associatedAddress({
+        mint: mint,
+        owner: owner.publicKey
+    });
+
+    const pdaTokenAccount = await anchor.utils.token.associatedAddress({
+        mint: mint,
+        owner: programPda
+    });
+
+    await program.methods.listNft(price, requiresAttestation)
+    .accounts({
+        owner: owner.publicKey,
+        mint: mint,
+        ownerTokenAccount: ownerTokenAccount,
+        nftListingAccount: nftAccountPda,
+        pdaTokenAccount: pdaTokenAccount,
+        programPda: programPda,
+    })
+    .signers([owner])
+    .rpc();
+
+    return { nftAccountPda, programPda, ownerTokenAccount, pdaTokenAccount };
+}
+
 async function airdrop(connection: any, address: any, amount = 1000000000) {
     await connection.confirmTransaction(await connection.requestAirdrop(address, amount), "confirmed");
 }